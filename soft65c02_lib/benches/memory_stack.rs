@@ -0,0 +1,73 @@
+//! Memory access benchmarks.
+//!
+//! Run them with `cargo bench -p soft65c02_lib`. Each benchmark prints the
+//! average time per operation, the `read` ones compare the allocating path
+//! with the byte fast path and pages shared by several subsystems (which are
+//! resolved through the address map) with pages owned by a single subsystem
+//! (which are resolved through the page table).
+use soft65c02_lib::memory::ROM;
+use soft65c02_lib::{execute_step, AddressableIO, Memory, Registers};
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: usize = 2_000_000;
+
+fn bench<F: FnMut(usize)>(name: &str, mut operation: F) {
+    let start = Instant::now();
+    for iteration in 0..ITERATIONS {
+        operation(iteration);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<40} {:>8.2} ns/op",
+        name,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
+
+/*
+ * RAM everywhere, a ROM in the upper 16K and a 16 bytes device in the middle
+ * of page 0xBF so this page is shared by RAM and the device.
+ */
+fn init_memory() -> Memory {
+    let mut memory = Memory::new_with_ram();
+    memory.add_subsystem("ROM", 0xC000, ROM::new(vec![0xAE; 0x4000]));
+    memory.add_subsystem("DEVICE", 0xBF80, ROM::new(vec![0x55; 0x10]));
+
+    memory
+}
+
+fn main() {
+    let mut memory = init_memory();
+
+    bench("read 1 byte (single subsystem page)", |i| {
+        black_box(memory.read(0x1000 + (i & 0xff), 1).unwrap());
+    });
+    bench("read_byte (single subsystem page)", |i| {
+        black_box(memory.read_byte(0x1000 + (i & 0xff)).unwrap());
+    });
+    bench("read 1 byte (shared page)", |i| {
+        black_box(memory.read(0xBF00 + (i & 0x7f), 1).unwrap());
+    });
+    bench("read_byte (shared page)", |i| {
+        black_box(memory.read_byte(0xBF00 + (i & 0x7f)).unwrap());
+    });
+    bench("write 1 byte (single subsystem page)", |i| {
+        memory.write(0x1000 + (i & 0xff), &[i as u8]).unwrap();
+    });
+    bench("write_byte (single subsystem page)", |i| {
+        memory.write_byte(0x1000 + (i & 0xff), i as u8).unwrap();
+    });
+
+    // LDX #$00 / loop: INX / STX $2000,X / BNE loop / BRA start
+    memory
+        .write(
+            0x0800,
+            &[0xa2, 0x00, 0xe8, 0x9d, 0x00, 0x20, 0xd0, 0xfa, 0x80, 0xf6],
+        )
+        .unwrap();
+    let mut registers = Registers::new_initialized(0x0800);
+    bench("execute_step", |_| {
+        black_box(execute_step(&mut registers, &mut memory).unwrap());
+    });
+}
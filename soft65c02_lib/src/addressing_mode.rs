@@ -7,7 +7,7 @@ use std::fmt;
 
 pub type Result<T> = std::result::Result<T, ResolutionError>;
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub enum ResolutionError {
     Solving(AddressingMode, usize, Option<usize>),
     Memory(MemoryError),
@@ -15,7 +15,7 @@ pub enum ResolutionError {
 
 impl fmt::Display for ResolutionError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             ResolutionError::Solving(addressing_mode, opcode_address, target_address) => {
                 let dst_addr_message = match target_address {
                     Some(v) => format!("#0x{v:04X}"),
@@ -44,6 +44,7 @@ impl std::convert::From<memory::MemoryError> for ResolutionError {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AddressingModeResolution {
     pub operands: Vec<u8>,
     pub addressing_mode: AddressingMode,
@@ -79,6 +80,8 @@ impl fmt::Display for AddressingModeResolution {
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "mode", content = "operands", rename_all = "snake_case"))]
 pub enum AddressingMode {
     Implied,
     Accumulator,
@@ -137,8 +140,8 @@ impl AddressingMode {
                 let pointer_addr = (v[0] as usize + registers.register_x as usize) % 0x100;
 
                 // Handle zero page wraparound for pointer reading
-                let lsb = memory.read(pointer_addr, 1)?[0];
-                let msb = memory.read((pointer_addr + 1) % 0x100, 1)?[0];
+                let lsb = memory.read_byte(pointer_addr)?;
+                let msb = memory.read_byte((pointer_addr + 1) % 0x100)?;
                 let dst_addr = little_endian(vec![lsb, msb]);
 
                 // Address should wrap at 16-bit boundary (like the real 6502/65C02)
@@ -153,8 +156,8 @@ impl AddressingMode {
                 let pointer_addr = v[0] as usize;
 
                 // Handle zero page wraparound for pointer reading
-                let lsb = memory.read(pointer_addr, 1)?[0];
-                let msb = memory.read((pointer_addr + 1) % 0x100, 1)?[0];
+                let lsb = memory.read_byte(pointer_addr)?;
+                let msb = memory.read_byte((pointer_addr + 1) % 0x100)?;
                 let base_addr = little_endian(vec![lsb, msb]);
                 let dst_addr = base_addr + registers.register_y as usize;
 
@@ -170,8 +173,8 @@ impl AddressingMode {
                 let pointer_addr = v[0] as usize;
 
                 // Handle zero page wraparound for pointer reading
-                let lsb = memory.read(pointer_addr, 1)?[0];
-                let msb = memory.read((pointer_addr + 1) % 0x100, 1)?[0];
+                let lsb = memory.read_byte(pointer_addr)?;
+                let msb = memory.read_byte((pointer_addr + 1) % 0x100)?;
                 let dst_addr = little_endian(vec![lsb, msb]);
                 Ok(AddressingModeResolution::new(
                     vec![v[0]],
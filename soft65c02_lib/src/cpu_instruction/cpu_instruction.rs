@@ -114,6 +114,7 @@ impl fmt::Display for CPUInstruction {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RegisterState {
     pub accumulator: u8,
     pub register_x: u8,
@@ -149,6 +150,7 @@ impl RegisterState {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LogLine {
     pub address: usize,
     pub opcode: u8,
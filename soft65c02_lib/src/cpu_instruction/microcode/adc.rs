@@ -11,6 +11,7 @@ use super::*;
 /// On the 65C02 (unlike the 6502):
 /// - In decimal mode, N, V, and Z flags are valid
 /// - Decimal mode takes one extra cycle compared to binary mode
+///
 /// See http://www.6502.org/tutorials/65c02opcodes.html
 pub fn adc(
     memory: &mut Memory,
@@ -33,7 +34,7 @@ pub fn adc(
         cpu_instruction.cycles.set(cpu_instruction.cycles.get() + 1);
     }
 
-    let byte = memory.read(target_address, 1)?[0];
+    let byte = memory.read_byte(target_address)?;
     let a = registers.accumulator;
 
     if registers.d_flag_is_set() {
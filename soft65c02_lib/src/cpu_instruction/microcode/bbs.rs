@@ -14,7 +14,7 @@ pub fn bbs(
         .expect("BBS must have operands, crashing the application");
     
     // Test the specified bit
-    let byte = memory.read(target_address, 1)?[0];
+    let byte = memory.read_byte(target_address)?;
     let mut bit = 0b00000001;
     (0..(cpu_instruction.opcode >> 4) - 8).for_each(|_| bit <<= 1);
 
@@ -8,7 +8,7 @@ use super::*;
 /// interrupt occures (with subtle differences).
 ///
 /// * Command Pointer register is pushed to the stack pointing 2 bytes after the
-/// BRK instruction.
+///   BRK instruction.
 /// * Status register is pushed to the stack with the B flag set .
 /// * Status register I flag is set, D flag is cleared.
 ///
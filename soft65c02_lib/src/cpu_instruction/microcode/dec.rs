@@ -10,7 +10,7 @@ pub fn dec(
             .addressing_mode
             .solve(registers.command_pointer, memory, registers)?;
     let byte = match resolution.target_address {
-        Some(addr) => memory.read(addr, 1)?[0],
+        Some(addr) => memory.read_byte(addr)?,
         None => registers.accumulator,
     };
 
@@ -22,7 +22,7 @@ pub fn dec(
 
     let outcome = match resolution.target_address {
         Some(addr) => {
-            memory.write(addr, &[res])?;
+            memory.write_byte(addr, res)?;
             format!("0x{:02x}", res)
         }
         None => {
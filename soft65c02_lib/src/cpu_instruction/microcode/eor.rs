@@ -16,7 +16,7 @@ pub fn eor(
     // Add extra cycle for page boundary crossing in indexed addressing modes
     cpu_instruction.adjust_base_cycles(registers, memory);
 
-    let byte = memory.read(target_address, 1)?[0];
+    let byte = memory.read_byte(target_address)?;
     registers.accumulator ^= byte;
     registers.set_z_flag(registers.accumulator == 0);
     registers.set_n_flag(registers.accumulator & 0x80 != 0);
@@ -13,9 +13,37 @@ pub enum MicrocodeError {
 
 pub type Result<T> = std::result::Result<T, MicrocodeError>;
 
+impl MicrocodeError {
+    /*
+     * Attach the instruction being executed to the underlying memory error if
+     * any.
+     */
+    pub fn at_instruction(self, command_pointer: usize, instruction: &str) -> MicrocodeError {
+        match self {
+            MicrocodeError::MemoryOverflow(e) => {
+                MicrocodeError::MemoryOverflow(e.at_instruction(command_pointer, instruction))
+            }
+            MicrocodeError::Resolution(addressing_mode::ResolutionError::Memory(e)) => {
+                MicrocodeError::Resolution(addressing_mode::ResolutionError::Memory(
+                    e.at_instruction(command_pointer, instruction),
+                ))
+            }
+            e => e,
+        }
+    }
+
+    pub fn memory_error(&self) -> Option<&memory::MemoryError> {
+        match self {
+            MicrocodeError::MemoryOverflow(e)
+            | MicrocodeError::Resolution(addressing_mode::ResolutionError::Memory(e)) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for MicrocodeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
             MicrocodeError::MemoryOverflow(e) => {
                 write!(f, "memory overflow during microcode operation: {}", e)
             }
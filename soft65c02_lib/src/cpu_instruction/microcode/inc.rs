@@ -10,7 +10,7 @@ pub fn inc(
             .addressing_mode
             .solve(registers.command_pointer, memory, registers)?;
     let mut byte = match resolution.target_address {
-        Some(addr) => memory.read(addr, 1)?[0],
+        Some(addr) => memory.read_byte(addr)?,
         None => registers.accumulator,
     };
 
@@ -25,7 +25,7 @@ pub fn inc(
 
     let outcome = match resolution.target_address {
         Some(addr) => {
-            memory.write(addr, &[byte])?;
+            memory.write_byte(addr, byte)?;
             format!("(0x{:02x})[S={}]", byte, registers.format_status())
         }
         None => {
@@ -17,7 +17,7 @@ pub fn lda(
         .target_address
         .expect("LDA instruction must have operands, crashing the application");
 
-    registers.accumulator = memory.read(target_address, 1)?[0];
+    registers.accumulator = memory.read_byte(target_address)?;
     registers.set_n_flag(registers.accumulator & 0b10000000 != 0);
     registers.set_z_flag(registers.accumulator == 0);
     registers.command_pointer += 1 + resolution.operands.len();
@@ -16,7 +16,7 @@ pub fn ldx(
     // Add extra cycle for page boundary crossing in indexed addressing modes
     cpu_instruction.adjust_base_cycles(registers, memory);
 
-    registers.register_x = memory.read(target_address, 1)?[0];
+    registers.register_x = memory.read_byte(target_address)?;
     registers.set_n_flag(registers.register_x & 0b10000000 != 0);
     registers.set_z_flag(registers.register_x == 0);
     registers.command_pointer += 1 + resolution.operands.len();
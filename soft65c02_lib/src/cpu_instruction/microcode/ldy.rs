@@ -16,7 +16,7 @@ pub fn ldy(
     // Add extra cycle for page boundary crossing in indexed addressing modes
     cpu_instruction.adjust_base_cycles(registers, memory);
 
-    registers.register_y = memory.read(target_address, 1)?[0];
+    registers.register_y = memory.read_byte(target_address)?;
     registers.set_n_flag(registers.register_y & 0b10000000 != 0);
     registers.set_z_flag(registers.register_y == 0);
     registers.command_pointer += 1 + resolution.operands.len();
@@ -12,12 +12,12 @@ pub fn rmb(
     let addr = resolution
         .target_address
         .expect("RMB must have operands, crashing the application");
-    let byte = memory.read(addr, 1)?[0];
+    let byte = memory.read_byte(addr)?;
     let mut bit = 0b00000001;
     (0..cpu_instruction.opcode >> 4).for_each(|_| bit <<= 1);
     let bit = 0b11111111 ^ bit;
     let byte = byte & bit;
-    memory.write(addr, &[byte])?;
+    memory.write_byte(addr, byte)?;
 
     registers.command_pointer += 1 + resolution.operands.len();
 
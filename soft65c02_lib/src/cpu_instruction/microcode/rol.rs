@@ -23,7 +23,7 @@ pub fn rol(
     cpu_instruction.adjust_base_cycles(registers, memory);
 
     let byte = match resolution.target_address {
-        Some(addr) => memory.read(addr, 1)?[0],
+        Some(addr) => memory.read_byte(addr)?,
         None => registers.accumulator,
     };
 
@@ -38,7 +38,7 @@ pub fn rol(
 
     let outcome = match resolution.target_address {
         Some(addr) => {
-            memory.write(addr, &[res])?;
+            memory.write_byte(addr, res)?;
             format!("(0x{:02x})[S={}]", res, registers.format_status())
         }
         None => {
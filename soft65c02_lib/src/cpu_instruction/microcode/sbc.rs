@@ -28,7 +28,7 @@ pub fn sbc(
         cpu_instruction.cycles.set(cpu_instruction.cycles.get() + 1);
     }
 
-    let byte = memory.read(target_address, 1)?[0];
+    let byte = memory.read_byte(target_address)?;
     let a = registers.accumulator;
     if registers.d_flag_is_set() {
         let carry = if registers.c_flag_is_set() { 0 } else { 1 };
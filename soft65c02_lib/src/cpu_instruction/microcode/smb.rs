@@ -12,12 +12,12 @@ pub fn smb(
     let addr = resolution
         .target_address
         .expect("SMB expects an operand, crashing the application");
-    let byte = memory.read(addr, 1)?[0];
+    let byte = memory.read_byte(addr)?;
 
     let mut bit = 0b00000001;
     (0..(cpu_instruction.opcode >> 4) - 8).for_each(|_| bit <<= 1);
     let byte = byte | bit;
-    memory.write(addr, &[byte])?;
+    memory.write_byte(addr, byte)?;
 
     registers.command_pointer += 1 + resolution.operands.len();
 
@@ -15,7 +15,7 @@ pub fn sta(
 
     // No cycle adjustments needed - table values are complete for write operations
 
-    memory.write(target_address, &[registers.accumulator])?;
+    memory.write_byte(target_address, registers.accumulator)?;
     registers.command_pointer += 1 + resolution.operands.len();
 
     Ok(LogLine::new(
@@ -13,7 +13,7 @@ pub fn stz(
         .target_address
         .expect("STZ instruction must have operands, crashing the application");
 
-    memory.write(target_address, &[0x00])?;
+    memory.write_byte(target_address, 0x00)?;
     registers.command_pointer += 1 + resolution.operands.len();
 
     Ok(LogLine::new(
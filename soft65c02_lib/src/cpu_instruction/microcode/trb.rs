@@ -13,10 +13,10 @@ pub fn trb(
         .target_address
         .expect("TRB must have operands, crashing the application");
 
-    let mut byte = memory.read(target_address, 1)?[0];
+    let mut byte = memory.read_byte(target_address)?;
     if byte & registers.accumulator != 0 {
         byte &= registers.accumulator ^ 0xff;
-        memory.write(target_address, &[byte])?;
+        memory.write_byte(target_address, byte)?;
         registers.set_z_flag(false);
     } else {
         registers.set_z_flag(true);
@@ -13,10 +13,10 @@ pub fn tsb(
         .target_address
         .expect("TSB must have operands, crashing the application");
 
-    let byte = memory.read(target_address, 1)?[0];
+    let byte = memory.read_byte(target_address)?;
     registers.set_z_flag(byte & registers.accumulator == 0);
     let res = byte | registers.accumulator;
-    memory.write(target_address, &[res])?;
+    memory.write_byte(target_address, res)?;
     registers.command_pointer += 1 + resolution.operands.len();
 
     Ok(LogLine::new(
@@ -0,0 +1,397 @@
+use std::any::Any;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::memory::{AddressableIO, MemoryError};
+
+pub const REGISTER_MIXER: usize = 7;
+pub const REGISTER_AMPLITUDE_A: usize = 8;
+pub const REGISTER_ENVELOPE_SHAPE: usize = 13;
+
+const ENVELOPE_CONTINUE: u8 = 0x08;
+const ENVELOPE_ATTACK: u8 = 0x04;
+const ENVELOPE_ALTERNATE: u8 = 0x02;
+const ENVELOPE_HOLD: u8 = 0x01;
+
+/*
+ * Bits of the registers that exist on the chip, the others read back as 0.
+ */
+const REGISTER_MASKS: [u8; 16] = [
+    0xFF, 0x0F, 0xFF, 0x0F, 0xFF, 0x0F, 0x1F, 0xFF, 0x1F, 0x1F, 0x1F, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF,
+];
+
+/*
+ * Output level of the 16 amplitudes, logarithmic as on the chip, scaled so
+ * the three channels at full volume fit in a signed 16 bits sample.
+ */
+const VOLUMES: [u16; 16] = [
+    0, 109, 158, 230, 335, 497, 704, 1173, 1383, 2239, 3192, 4072, 5379, 6939, 8799, 10922,
+];
+
+/*
+ * RegisterWrite
+ * Entry of the register log, the cycle is the one of the instruction end
+ * preceding the write.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterWrite {
+    pub cycle: u64,
+    pub register: u8,
+    pub value: u8,
+}
+
+impl fmt::Display for RegisterWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: R{:02} = 0x{:02X}", self.cycle, self.register, self.value)
+    }
+}
+
+/*
+ * AY38910
+ * AY-3-8910 programmable sound generator clocked at the CPU frequency. Its 16
+ * registers are mapped at consecutive addresses instead of going through the
+ * latch address / write data bus protocol of the chip:
+ *   0-5:   tone period of channels A, B and C (12 bits, little endian)
+ *   6:     noise period (5 bits)
+ *   7:     mixer, bits 0-2 disable the tone, bits 3-5 the noise of A, B, C
+ *   8-10:  amplitude of A, B and C, bit 4 uses the envelope instead
+ *   11-12: envelope period (little endian)
+ *   13:    envelope shape, writing it restarts the envelope
+ *   14-15: I/O ports, only stored
+ * The chip is sampled in step with the CPU cycles at the given sample rate
+ * and the samples kept in memory to be saved as a WAV file. Every register
+ * write is logged with its cycle so tests can compare sequences of writes
+ * rather than audio.
+ */
+#[derive(Debug)]
+pub struct AY38910 {
+    registers: [u8; 16],
+    frequency: u64,
+    sample_rate: u32,
+    now: Option<u64>,
+    clocks: u64,
+    tone_counters: [u16; 3],
+    tone_outputs: [bool; 3],
+    noise_counter: u16,
+    noise_shift: u32,
+    envelope_counter: u32,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_hold: Option<u8>,
+    samples: Vec<i16>,
+    log: Vec<RegisterWrite>,
+}
+
+impl Default for AY38910 {
+    fn default() -> Self {
+        Self::new(1_000_000, 44100)
+    }
+}
+
+impl AY38910 {
+    pub fn new(frequency: u64, sample_rate: u32) -> Self {
+        assert!(frequency > 0 && sample_rate > 0, "frequencies cannot be 0");
+
+        Self {
+            registers: [0x00; 16],
+            frequency,
+            sample_rate,
+            now: None,
+            clocks: 0,
+            tone_counters: [0; 3],
+            tone_outputs: [false; 3],
+            noise_counter: 0,
+            noise_shift: 1,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_hold: None,
+            samples: Vec::new(),
+            log: Vec::new(),
+        }
+    }
+
+    pub fn get_register(&self, register: usize) -> u8 {
+        self.registers[register]
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn get_samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    pub fn get_register_log(&self) -> &[RegisterWrite] {
+        &self.log
+    }
+
+    /// Write the register log, one write per line.
+    pub fn write_register_log<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for entry in &self.log {
+            writeln!(writer, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the samples as a mono 16 bits PCM WAV file.
+    pub fn write_wav<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let data_size = (self.samples.len() * 2) as u32;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&1u16.to_le_bytes())?; // mono
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        writer.write_all(&(self.sample_rate * 2).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // block align
+        writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save_wav(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_wav(&mut writer)?;
+
+        writer.flush()
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let period = u16::from_le_bytes([self.registers[channel * 2], self.registers[channel * 2 + 1]]);
+
+        period.max(1)
+    }
+
+    fn envelope_level(&self) -> u8 {
+        match self.envelope_hold {
+            Some(level) => level,
+            None if self.envelope_attack => self.envelope_step,
+            None => 15 - self.envelope_step,
+        }
+    }
+
+    fn restart_envelope(&mut self) {
+        self.envelope_counter = 0;
+        self.envelope_step = 0;
+        self.envelope_attack = self.registers[REGISTER_ENVELOPE_SHAPE] & ENVELOPE_ATTACK != 0;
+        self.envelope_hold = None;
+    }
+
+    fn step_envelope(&mut self) {
+        if self.envelope_hold.is_some() {
+            return;
+        }
+        self.envelope_step += 1;
+        if self.envelope_step < 16 {
+            return;
+        }
+        let shape = self.registers[REGISTER_ENVELOPE_SHAPE];
+        if shape & ENVELOPE_CONTINUE == 0 {
+            self.envelope_hold = Some(0);
+        } else if shape & ENVELOPE_HOLD != 0 {
+            let last = if self.envelope_attack { 15 } else { 0 };
+            let alternate = shape & ENVELOPE_ALTERNATE != 0;
+            self.envelope_hold = Some(if alternate { 15 - last } else { last });
+        } else {
+            if shape & ENVELOPE_ALTERNATE != 0 {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = 0;
+        }
+    }
+
+    /*
+     * Advance the generators by 8 clocks: tones toggle every period, noise
+     * and envelope move on every 2 periods.
+     */
+    fn step(&mut self) {
+        for channel in 0..3 {
+            self.tone_counters[channel] += 1;
+            if self.tone_counters[channel] >= self.tone_period(channel) {
+                self.tone_counters[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+        let noise_period = (self.registers[6] as u16).max(1) * 2;
+        self.noise_counter += 1;
+        if self.noise_counter >= noise_period {
+            self.noise_counter = 0;
+            // 17 bits LFSR, taps on bits 0 and 3
+            let bit = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+            self.noise_shift = (self.noise_shift >> 1) | (bit << 16);
+        }
+        let envelope_period =
+            (u16::from_le_bytes([self.registers[11], self.registers[12]]) as u32).max(1) * 2;
+        self.envelope_counter += 1;
+        if self.envelope_counter >= envelope_period {
+            self.envelope_counter = 0;
+            self.step_envelope();
+        }
+    }
+
+    fn sample(&self) -> i16 {
+        let mixer = self.registers[REGISTER_MIXER];
+        let noise = self.noise_shift & 1 != 0;
+
+        (0..3)
+            .filter(|channel| {
+                (self.tone_outputs[*channel] || mixer & (1 << channel) != 0)
+                    && (noise || mixer & (8 << channel) != 0)
+            })
+            .map(|channel| {
+                let amplitude = self.registers[REGISTER_AMPLITUDE_A + channel];
+                let level = if amplitude & 0x10 != 0 { self.envelope_level() } else { amplitude & 0x0F };
+                VOLUMES[level as usize] as i16
+            })
+            .sum()
+    }
+
+    fn render(&mut self, clocks: u64) {
+        for _ in 0..clocks {
+            self.clocks += 1;
+            if self.clocks.is_multiple_of(8) {
+                self.step();
+            }
+            // a sample is due every frequency / sample_rate clocks
+            let due = self.clocks * self.sample_rate as u64 / self.frequency;
+            if due > self.samples.len() as u64 {
+                self.samples.push(self.sample());
+            }
+        }
+    }
+}
+
+impl AddressableIO for AY38910 {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok(self.registers[addr..addr + len].to_vec())
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        for (register, value) in (location..).zip(data.iter()) {
+            self.registers[register] = value & REGISTER_MASKS[register];
+            self.log.push(RegisterWrite {
+                cycle: self.now.unwrap_or(0),
+                register: register as u8,
+                value: *value,
+            });
+            if register == REGISTER_ENVELOPE_SHAPE {
+                self.restart_envelope();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        16
+    }
+
+    fn kind(&self) -> &str {
+        "sound"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn tick(&mut self, cycle_count: u64) {
+        // the cycle counter going backwards is a reset, the sound goes on
+        if let Some(now) = self.now.filter(|now| cycle_count > *now) {
+            self.render(cycle_count - now);
+        }
+        self.now = Some(cycle_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tone() {
+        let mut chip = AY38910::new(1_000_000, 50_000);
+        // channel A at 1MHz / (16 * 5) = 12.5kHz, tone only, full volume
+        chip.write(0, &[0x05, 0x00]).unwrap();
+        chip.write_byte(REGISTER_MIXER, 0x3E).unwrap();
+        chip.write_byte(REGISTER_AMPLITUDE_A, 0x0F).unwrap();
+        chip.tick(0);
+        chip.tick(160);
+
+        // 20 cycles per sample, the output toggles every 40 cycles
+        assert_eq!(8, chip.get_samples().len());
+        assert_eq!(
+            vec![0, 10922, 10922, 0, 0, 10922, 10922, 0],
+            chip.get_samples()
+        );
+    }
+
+    #[test]
+    fn test_envelope() {
+        let mut chip = AY38910::new(1_000_000, 1_000_000 / 16);
+        // tone and noise disabled, channel A follows the envelope
+        chip.write_byte(REGISTER_MIXER, 0x3F).unwrap();
+        chip.write_byte(REGISTER_AMPLITUDE_A, 0x10).unwrap();
+        chip.write(11, &[0x01, 0x00]).unwrap();
+        // attack then hold
+        chip.write_byte(REGISTER_ENVELOPE_SHAPE, 0x0D).unwrap();
+        chip.tick(0);
+        chip.tick(16 * 20);
+
+        let samples = chip.get_samples();
+        assert_eq!(VOLUMES[1] as i16, samples[0]);
+        assert_eq!(VOLUMES[15] as i16, samples[14]);
+        assert_eq!(VOLUMES[15] as i16, samples[19]);
+
+        // single decay goes down to 0 and stays there
+        chip.write_byte(REGISTER_ENVELOPE_SHAPE, 0x00).unwrap();
+        chip.tick(16 * 40);
+        assert_eq!(VOLUMES[14] as i16, chip.get_samples()[20]);
+        assert_eq!(0, chip.get_samples()[39]);
+    }
+
+    #[test]
+    fn test_register_log() {
+        let mut chip = AY38910::default();
+        chip.write_byte(1, 0xFF).unwrap();
+        assert_eq!(0x0F, chip.read_byte(1).unwrap());
+        chip.tick(100);
+        chip.write_byte(REGISTER_MIXER, 0x38).unwrap();
+
+        let mut log = Vec::new();
+        chip.write_register_log(&mut log).unwrap();
+        assert_eq!("0: R01 = 0xFF\n100: R07 = 0x38\n", String::from_utf8(log).unwrap());
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let mut chip = AY38910::new(1_000_000, 8000);
+        chip.tick(0);
+        chip.tick(1000);
+        let mut wav = Vec::new();
+        chip.write_wav(&mut wav).unwrap();
+
+        assert_eq!(44 + 16, wav.len());
+        assert_eq!(b"RIFF", &wav[0..4]);
+        assert_eq!(52u32.to_le_bytes(), wav[4..8]);
+        assert_eq!(8000u32.to_le_bytes(), wav[24..28]);
+        assert_eq!(16u32.to_le_bytes(), wav[40..44]);
+    }
+}
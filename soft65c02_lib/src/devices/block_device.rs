@@ -0,0 +1,263 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::fs::OpenOptions;
+use std::io::{self, SeekFrom};
+use std::path::Path;
+
+use super::{DiskImage, BLOCK_SIZE};
+use crate::memory::{AddressableIO, DmaRequest, MemoryError};
+
+pub const REGISTER_SECTOR: usize = 0;
+pub const REGISTER_BUFFER: usize = 2;
+pub const REGISTER_COMMAND: usize = 4;
+pub const REGISTER_CONTROL: usize = 5;
+
+pub const COMMAND_READ: u8 = 0x01;
+pub const COMMAND_WRITE: u8 = 0x02;
+
+pub const STATUS_ERROR: u8 = 0x01;
+pub const STATUS_DONE: u8 = 0x80;
+
+pub const CONTROL_IRQ_ENABLE: u8 = 0x01;
+
+/*
+ * BlockDevice
+ * Disk controller moving whole sectors between a disk image and memory by
+ * DMA. Its registers are:
+ *   0-1: sector number (little endian)
+ *   2-3: address of the buffer in memory (little endian)
+ *   4:   command when written (1 = read, 2 = write), status when read
+ *   5:   control, bit 0 enables the IRQ
+ * Writing a command performs the transfer at once. The status has bit 7 set
+ * when the command is done and bit 0 set if it failed (unknown command,
+ * sector out of the image, buffer out of memory). The IRQ line is held until
+ * the status register is read.
+ */
+pub struct BlockDevice {
+    image: Box<dyn DiskImage>,
+    blocks: usize,
+    registers: [u8; 6],
+    status: Cell<u8>,
+    request: Option<DmaRequest>,
+    write_sector: Option<usize>,
+}
+
+impl BlockDevice {
+    pub fn new(mut image: impl DiskImage + 'static) -> io::Result<Self> {
+        let size = image.seek(SeekFrom::End(0))?;
+
+        Ok(Self {
+            image: Box::new(image),
+            blocks: size as usize / BLOCK_SIZE,
+            registers: [0x00; 6],
+            status: Cell::new(0x00),
+            request: None,
+            write_sector: None,
+        })
+    }
+
+    /// Open a disk image file in read-write mode.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        Self::new(file)
+    }
+
+    pub fn get_blocks(&self) -> usize {
+        self.blocks
+    }
+
+    pub fn get_status(&self) -> u8 {
+        self.status.get()
+    }
+
+    fn register_word(&self, register: usize) -> usize {
+        u16::from_le_bytes([self.registers[register], self.registers[register + 1]]) as usize
+    }
+
+    fn read_block(&mut self, sector: usize) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; BLOCK_SIZE];
+        self.image.seek(SeekFrom::Start((sector * BLOCK_SIZE) as u64))?;
+        self.image.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+
+    fn write_block(&mut self, sector: usize, data: &[u8]) -> io::Result<()> {
+        self.image.seek(SeekFrom::Start((sector * BLOCK_SIZE) as u64))?;
+        self.image.write_all(data)?;
+        self.image.flush()
+    }
+
+    fn start(&mut self, command: u8) {
+        let sector = self.register_word(REGISTER_SECTOR);
+        let buffer = self.register_word(REGISTER_BUFFER);
+        self.status.set(0x00);
+        self.write_sector = None;
+
+        if sector >= self.blocks {
+            return self.finish(false);
+        }
+        match command {
+            COMMAND_READ => match self.read_block(sector) {
+                Ok(data) => self.request = Some(DmaRequest::Write(buffer, data)),
+                Err(_) => self.finish(false),
+            },
+            COMMAND_WRITE => {
+                self.write_sector = Some(sector);
+                self.request = Some(DmaRequest::Read(buffer, BLOCK_SIZE));
+            }
+            _ => self.finish(false),
+        }
+    }
+
+    fn finish(&mut self, success: bool) {
+        let error = if success { 0x00 } else { STATUS_ERROR };
+        self.status.set(STATUS_DONE | error);
+    }
+
+    fn register(&self, offset: usize) -> u8 {
+        match offset {
+            REGISTER_COMMAND => self.status.get(),
+            _ => self.registers[offset],
+        }
+    }
+}
+
+impl AddressableIO for BlockDevice {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        let data = self.peek(addr, len)?;
+        // reading the status acknowledges the interrupt
+        if (addr..addr + len).contains(&REGISTER_COMMAND) {
+            self.status.set(self.status.get() & !STATUS_DONE);
+        }
+
+        Ok(data)
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        for (offset, value) in (location..).zip(data.iter()) {
+            self.registers[offset] = *value;
+            if offset == REGISTER_COMMAND {
+                self.start(*value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        self.registers.len()
+    }
+
+    fn peek(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok((addr..addr + len).map(|offset| self.register(offset)).collect())
+    }
+
+    fn kind(&self) -> &str {
+        "block device"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn irq(&self) -> bool {
+        self.registers[REGISTER_CONTROL] & CONTROL_IRQ_ENABLE != 0
+            && self.status.get() & STATUS_DONE != 0
+    }
+
+    fn take_dma_request(&mut self) -> Option<DmaRequest> {
+        self.request.take()
+    }
+
+    fn dma_complete(&mut self, result: Result<Vec<u8>, MemoryError>) {
+        let success = match (self.write_sector.take(), result) {
+            (_, Err(_)) => false,
+            (Some(sector), Ok(data)) => self.write_block(sector, &data).is_ok(),
+            (None, Ok(_)) => true,
+        };
+        self.finish(success);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStack;
+    use std::io::Cursor;
+
+    fn get_memory() -> MemoryStack {
+        let mut data = vec![0; BLOCK_SIZE * 4];
+        for (block, chunk) in data.chunks_mut(BLOCK_SIZE).enumerate() {
+            chunk.fill(block as u8 + 0xA0);
+        }
+        let mut memory = MemoryStack::new_with_ram();
+        let device = BlockDevice::new(Cursor::new(data)).unwrap();
+        assert_eq!(4, device.get_blocks());
+        memory.add_subsystem("DISK", 0xB000, device);
+
+        memory
+    }
+
+    #[test]
+    fn test_read_sector() {
+        let mut memory = get_memory();
+        memory.write(0xB000, &[0x02, 0x00, 0x00, 0x20]).unwrap();
+        memory.write_byte(0xB004, COMMAND_READ).unwrap();
+
+        assert!(memory.read(0x2000, BLOCK_SIZE).unwrap().iter().all(|b| *b == 0xA2));
+        assert_eq!(0x00, memory.read_byte(0x2000 + BLOCK_SIZE).unwrap());
+        assert_eq!(STATUS_DONE, memory.read_byte(0xB004).unwrap());
+        assert_eq!(0x00, memory.read_byte(0xB004).unwrap());
+    }
+
+    #[test]
+    fn test_write_sector() {
+        let mut memory = get_memory();
+        let data: Vec<u8> = (0..BLOCK_SIZE).map(|i| i as u8).collect();
+        memory.write(0x3000, &data).unwrap();
+        memory.write(0xB000, &[0x01, 0x00, 0x00, 0x30, COMMAND_WRITE]).unwrap();
+        assert_eq!(STATUS_DONE, memory.read_byte(0xB004).unwrap());
+
+        // read it back somewhere else
+        memory.write(0xB002, &[0x00, 0x40, COMMAND_READ]).unwrap();
+        assert_eq!(data, memory.read(0x4000, BLOCK_SIZE).unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut memory = get_memory();
+        // sector out of the image
+        memory.write(0xB000, &[0x04, 0x00, 0x00, 0x20, COMMAND_READ]).unwrap();
+        assert_eq!(STATUS_DONE | STATUS_ERROR, memory.read_byte(0xB004).unwrap());
+        // unknown command
+        memory.write(0xB000, &[0x00, 0x00, 0x00, 0x20, 0x07]).unwrap();
+        assert_eq!(STATUS_DONE | STATUS_ERROR, memory.read_byte(0xB004).unwrap());
+        // the buffer does not fit in memory
+        memory.write(0xB000, &[0x00, 0x00, 0x00, 0xFF, COMMAND_READ]).unwrap();
+        assert_eq!(STATUS_DONE | STATUS_ERROR, memory.read_byte(0xB004).unwrap());
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut memory = get_memory();
+        memory.write(0xB000, &[0x00, 0x00, 0x00, 0x20, COMMAND_READ]).unwrap();
+        // interrupt disabled
+        assert!(!memory.irq_pending());
+        memory.write_byte(0xB005, CONTROL_IRQ_ENABLE).unwrap();
+        assert!(memory.irq_pending());
+        // peeking does not acknowledge the interrupt
+        assert_eq!(STATUS_DONE, memory.peek(0xB004, 1).unwrap()[0]);
+        assert!(memory.irq_pending());
+        memory.read_byte(0xB004).unwrap();
+        assert!(!memory.irq_pending());
+    }
+}
@@ -0,0 +1,76 @@
+use std::any::Any;
+
+use crate::memory::{AddressableIO, MemoryError};
+
+/*
+ * Console
+ * Output port capturing the characters a program prints, ROM stubs of the
+ * machine profiles write their character output routine there. Each byte
+ * written is appended to the output, reading returns the last byte written.
+ */
+#[derive(Debug, Default)]
+pub struct Console {
+    output: Vec<u8>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append bytes to the output, for stubs serviced from Rust.
+    pub fn print(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+    }
+
+    pub fn get_output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// The output as text, carriage returns become new lines.
+    pub fn text(&self) -> String {
+        self.output
+            .iter()
+            .map(|byte| if *byte == b'\r' { '\n' } else { *byte as char })
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.output.clear();
+    }
+}
+
+impl AddressableIO for Console {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok(vec![self.output.last().copied().unwrap_or(0x00); len])
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        self.print(data);
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        1
+    }
+
+    fn kind(&self) -> &str {
+        "console"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+}
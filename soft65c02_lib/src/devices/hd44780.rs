@@ -0,0 +1,513 @@
+use std::any::Any;
+use std::cell::Cell;
+
+use crate::memory::{AddressableIO, MemoryError};
+
+const DDRAM_SIZE: usize = 0x80;
+const CGRAM_SIZE: usize = 0x40;
+const LINE_LENGTH: usize = 0x28;
+
+/*
+ * The register selected by the RS pin, wired to A0.
+ */
+const INSTRUCTION_REGISTER: usize = 0;
+const DATA_REGISTER: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RamTarget {
+    Ddram,
+    Cgram,
+}
+
+/*
+ * HD44780
+ * Character LCD controller. The instruction register is at offset 0 and the
+ * data register at offset 1 (RS wired to A0). The controller starts with an
+ * 8-bit interface, a function set instruction with DL=0 switches it to the
+ * 4-bit interface where each byte is transferred as two nibbles on D7-D4,
+ * high nibble first.
+ * There is no clock, the busy flag is reported set once after each
+ * operation so wait loops are exercised.
+ */
+pub struct HD44780 {
+    columns: usize,
+    rows: usize,
+    ddram: [u8; DDRAM_SIZE],
+    cgram: [u8; CGRAM_SIZE],
+    address_counter: Cell<usize>,
+    target: RamTarget,
+    increment: bool,
+    shift_on_write: bool,
+    display_on: bool,
+    cursor_on: bool,
+    blink_on: bool,
+    eight_bit: bool,
+    two_lines: bool,
+    large_font: bool,
+    display_offset: usize,
+    busy: Cell<bool>,
+    write_nibble: Option<u8>,
+    read_nibble: Cell<Option<u8>>,
+}
+
+impl Default for HD44780 {
+    fn default() -> Self {
+        Self::new(16, 2)
+    }
+}
+
+impl HD44780 {
+    pub fn new(columns: usize, rows: usize) -> Self {
+        assert!(
+            (1..=4).contains(&rows) && columns > 0 && columns * rows <= 80,
+            "invalid LCD size {}x{}",
+            columns,
+            rows
+        );
+
+        Self {
+            columns,
+            rows,
+            ddram: [0x20; DDRAM_SIZE],
+            cgram: [0x00; CGRAM_SIZE],
+            address_counter: Cell::new(0),
+            target: RamTarget::Ddram,
+            increment: true,
+            shift_on_write: false,
+            display_on: false,
+            cursor_on: false,
+            blink_on: false,
+            eight_bit: true,
+            two_lines: false,
+            large_font: false,
+            display_offset: 0,
+            busy: Cell::new(false),
+            write_nibble: None,
+            read_nibble: Cell::new(None),
+        }
+    }
+
+    pub fn get_dimensions(&self) -> (usize, usize) {
+        (self.columns, self.rows)
+    }
+
+    pub fn get_address_counter(&self) -> usize {
+        self.address_counter.get()
+    }
+
+    pub fn is_display_on(&self) -> bool {
+        self.display_on
+    }
+
+    pub fn is_cursor_on(&self) -> bool {
+        self.cursor_on
+    }
+
+    pub fn is_blink_on(&self) -> bool {
+        self.blink_on
+    }
+
+    pub fn is_eight_bit(&self) -> bool {
+        self.eight_bit
+    }
+
+    pub fn ddram(&self) -> &[u8] {
+        &self.ddram
+    }
+
+    pub fn cgram(&self) -> &[u8] {
+        &self.cgram
+    }
+
+    /*
+     * Character codes visible on the given row (starting at 0), a display
+     * switched off shows blanks.
+     */
+    pub fn line_bytes(&self, row: usize) -> Vec<u8> {
+        if row >= self.rows || !self.display_on || (!self.two_lines && row > 0) {
+            return vec![0x20; self.columns];
+        }
+        (0..self.columns)
+            .map(|column| self.ddram[self.visible_address(row, column)])
+            .collect()
+    }
+
+    /*
+     * Text visible on the given row, characters outside the printable ASCII
+     * range are displayed as '.'.
+     */
+    pub fn line(&self, row: usize) -> String {
+        self.line_bytes(row)
+            .iter()
+            .map(|&byte| {
+                if (0x20..=0x7E).contains(&byte) {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect()
+    }
+
+    pub fn text(&self) -> Vec<String> {
+        (0..self.rows).map(|row| self.line(row)).collect()
+    }
+
+    fn visible_address(&self, row: usize, column: usize) -> usize {
+        if self.two_lines {
+            let line_start = (row % 2) * 0x40;
+            let offset = (row / 2) * self.columns + column;
+
+            line_start + (offset + self.display_offset) % LINE_LENGTH
+        } else {
+            (column + self.display_offset) % (2 * LINE_LENGTH)
+        }
+    }
+
+    fn next_address(&self, address: usize, increment: bool) -> usize {
+        match (self.target, self.two_lines, increment) {
+            (RamTarget::Cgram, _, true) => (address + 1) % CGRAM_SIZE,
+            (RamTarget::Cgram, _, false) => (address + CGRAM_SIZE - 1) % CGRAM_SIZE,
+            (RamTarget::Ddram, true, true) => match address {
+                0x27 => 0x40,
+                0x67 => 0x00,
+                _ => (address + 1) % DDRAM_SIZE,
+            },
+            (RamTarget::Ddram, true, false) => match address {
+                0x00 => 0x67,
+                0x40 => 0x27,
+                _ => address - 1,
+            },
+            (RamTarget::Ddram, false, true) => (address + 1) % (2 * LINE_LENGTH),
+            (RamTarget::Ddram, false, false) => {
+                (address + 2 * LINE_LENGTH - 1) % (2 * LINE_LENGTH)
+            }
+        }
+    }
+
+    fn move_address_counter(&self, increment: bool) {
+        let address = self.next_address(self.address_counter.get(), increment);
+        self.address_counter.set(address);
+    }
+
+    /*
+     * Shifting the display to the left moves the text to the left, the first
+     * visible column then shows the next DDRAM address.
+     */
+    fn shift_display(&mut self, left: bool) {
+        let length = if self.two_lines {
+            LINE_LENGTH
+        } else {
+            2 * LINE_LENGTH
+        };
+        self.display_offset = if left {
+            (self.display_offset + 1) % length
+        } else {
+            (self.display_offset + length - 1) % length
+        };
+    }
+
+    fn execute_instruction(&mut self, instruction: u8) {
+        if instruction & 0x80 != 0 {
+            self.target = RamTarget::Ddram;
+            self.address_counter.set((instruction & 0x7F) as usize);
+        } else if instruction & 0x40 != 0 {
+            self.target = RamTarget::Cgram;
+            self.address_counter.set((instruction & 0x3F) as usize);
+        } else if instruction & 0x20 != 0 {
+            self.eight_bit = instruction & 0x10 != 0;
+            self.two_lines = instruction & 0x08 != 0;
+            self.large_font = instruction & 0x04 != 0;
+            self.write_nibble = None;
+            self.read_nibble.set(None);
+        } else if instruction & 0x10 != 0 {
+            let right = instruction & 0x04 != 0;
+            if instruction & 0x08 != 0 {
+                self.shift_display(!right);
+            } else {
+                self.move_address_counter(right);
+            }
+        } else if instruction & 0x08 != 0 {
+            self.display_on = instruction & 0x04 != 0;
+            self.cursor_on = instruction & 0x02 != 0;
+            self.blink_on = instruction & 0x01 != 0;
+        } else if instruction & 0x04 != 0 {
+            self.increment = instruction & 0x02 != 0;
+            self.shift_on_write = instruction & 0x01 != 0;
+        } else if instruction & 0x02 != 0 {
+            self.target = RamTarget::Ddram;
+            self.address_counter.set(0);
+            self.display_offset = 0;
+        } else if instruction & 0x01 != 0 {
+            self.ddram = [0x20; DDRAM_SIZE];
+            self.target = RamTarget::Ddram;
+            self.address_counter.set(0);
+            self.increment = true;
+            self.display_offset = 0;
+        }
+    }
+
+    fn write_data(&mut self, value: u8) {
+        let address = self.address_counter.get();
+        match self.target {
+            RamTarget::Ddram => self.ddram[address] = value,
+            RamTarget::Cgram => self.cgram[address] = value,
+        };
+        self.move_address_counter(self.increment);
+        if self.shift_on_write && self.target == RamTarget::Ddram {
+            self.shift_display(self.increment);
+        }
+    }
+
+    fn write_register(&mut self, register: usize, value: u8) {
+        let value = if self.eight_bit {
+            value
+        } else {
+            match self.write_nibble.take() {
+                Some(high) => high | (value >> 4),
+                None => {
+                    self.write_nibble = Some(value & 0xF0);
+                    return;
+                }
+            }
+        };
+        match register {
+            INSTRUCTION_REGISTER => self.execute_instruction(value),
+            _ => self.write_data(value),
+        };
+        self.busy.set(true);
+    }
+
+    fn register_value(&self, register: usize) -> u8 {
+        match register {
+            INSTRUCTION_REGISTER => {
+                ((self.busy.get() as u8) << 7) | (self.address_counter.get() as u8 & 0x7F)
+            }
+            _ => match self.target {
+                RamTarget::Ddram => self.ddram[self.address_counter.get()],
+                RamTarget::Cgram => self.cgram[self.address_counter.get()],
+            },
+        }
+    }
+
+    fn read_register(&self, register: usize) -> u8 {
+        if let Some(low) = self.read_nibble.take() {
+            return low << 4;
+        }
+        let value = self.register_value(register);
+        if register == DATA_REGISTER {
+            self.move_address_counter(self.increment);
+            self.busy.set(true);
+        } else {
+            self.busy.set(false);
+        }
+        if self.eight_bit {
+            value
+        } else {
+            self.read_nibble.set(Some(value & 0x0F));
+            value & 0xF0
+        }
+    }
+}
+
+impl AddressableIO for HD44780 {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok((addr..addr + len).map(|register| self.read_register(register)).collect())
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        for (offset, value) in data.iter().enumerate() {
+            self.write_register(location + offset, *value);
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        2
+    }
+
+    fn peek(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok((addr..addr + len).map(|register| self.register_value(register)).collect())
+    }
+
+    fn kind(&self) -> &str {
+        "LCD"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_lcd() -> HD44780 {
+        let mut lcd = HD44780::default();
+        // function set 8 bits 2 lines, display on, entry mode increment, clear
+        for instruction in [0x38, 0x0C, 0x06, 0x01] {
+            lcd.write_byte(INSTRUCTION_REGISTER, instruction).unwrap();
+        }
+
+        lcd
+    }
+
+    fn print(lcd: &mut HD44780, text: &str) {
+        for byte in text.bytes() {
+            lcd.write_byte(DATA_REGISTER, byte).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_write_text() {
+        let mut lcd = init_lcd();
+        print(&mut lcd, "HELLO");
+        lcd.write_byte(INSTRUCTION_REGISTER, 0xC0).unwrap();
+        print(&mut lcd, "WORLD");
+
+        assert_eq!("HELLO           ", lcd.line(0));
+        assert_eq!("WORLD           ", lcd.line(1));
+        assert_eq!(0x45, lcd.get_address_counter());
+    }
+
+    #[test]
+    fn test_display_off() {
+        let mut lcd = init_lcd();
+        print(&mut lcd, "HELLO");
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x08).unwrap();
+
+        assert_eq!(vec![" ".repeat(16), " ".repeat(16)], lcd.text());
+        assert!(!lcd.is_display_on());
+    }
+
+    #[test]
+    fn test_four_bit_interface() {
+        let mut lcd = HD44780::default();
+        for nibble in [0x30, 0x30, 0x30, 0x20] {
+            lcd.write_byte(INSTRUCTION_REGISTER, nibble).unwrap();
+        }
+        assert!(!lcd.is_eight_bit());
+        // function set 4 bits 2 lines, display on
+        for nibble in [0x20, 0x80, 0x00, 0xC0] {
+            lcd.write_byte(INSTRUCTION_REGISTER, nibble).unwrap();
+        }
+        for nibble in [0x40, 0x10, 0x60, 0x90] {
+            lcd.write_byte(DATA_REGISTER, nibble).unwrap();
+        }
+
+        assert_eq!("Ai              ", lcd.line(0));
+        // the address counter is read in two nibbles
+        assert_eq!(vec![0x80], lcd.read(INSTRUCTION_REGISTER, 1).unwrap());
+        assert_eq!(vec![0x20], lcd.read(INSTRUCTION_REGISTER, 1).unwrap());
+        assert_eq!(vec![0x00], lcd.read(INSTRUCTION_REGISTER, 1).unwrap());
+    }
+
+    #[test]
+    fn test_busy_flag() {
+        let mut lcd = init_lcd();
+        print(&mut lcd, "A");
+
+        assert_eq!(vec![0x81], lcd.peek(INSTRUCTION_REGISTER, 1).unwrap());
+        assert_eq!(vec![0x81], lcd.read(INSTRUCTION_REGISTER, 1).unwrap());
+        assert_eq!(vec![0x01], lcd.read(INSTRUCTION_REGISTER, 1).unwrap());
+    }
+
+    #[test]
+    fn test_read_data() {
+        let mut lcd = init_lcd();
+        print(&mut lcd, "HELLO");
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x81).unwrap();
+
+        assert_eq!(vec![b'E'], lcd.read(DATA_REGISTER, 1).unwrap());
+        assert_eq!(vec![b'L'], lcd.read(DATA_REGISTER, 1).unwrap());
+        assert_eq!(0x03, lcd.get_address_counter());
+    }
+
+    #[test]
+    fn test_line_wrap() {
+        let mut lcd = init_lcd();
+        lcd.write_byte(INSTRUCTION_REGISTER, 0xA7).unwrap();
+        print(&mut lcd, "AB");
+        assert_eq!(0x41, lcd.get_address_counter());
+        assert_eq!("B               ", lcd.line(1));
+    }
+
+    #[test]
+    fn test_shift_display() {
+        let mut lcd = init_lcd();
+        print(&mut lcd, "HELLO");
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x18).unwrap();
+        assert_eq!("ELLO            ", lcd.line(0));
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x1C).unwrap();
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x1C).unwrap();
+        assert_eq!(" HELLO          ", lcd.line(0));
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x02).unwrap();
+        assert_eq!("HELLO           ", lcd.line(0));
+        assert_eq!(0x00, lcd.get_address_counter());
+    }
+
+    #[test]
+    fn test_entry_mode() {
+        let mut lcd = init_lcd();
+        // decrement, no shift
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x04).unwrap();
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x84).unwrap();
+        print(&mut lcd, "CBA");
+        assert_eq!("  ABC           ", lcd.line(0));
+        // increment and shift the display
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x07).unwrap();
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x85).unwrap();
+        print(&mut lcd, "D");
+        assert_eq!(" ABCD           ", lcd.line(0));
+    }
+
+    #[test]
+    fn test_cgram() {
+        let mut lcd = init_lcd();
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x48).unwrap();
+        lcd.write(DATA_REGISTER, &[0x1F]).unwrap();
+        lcd.write(DATA_REGISTER, &[0x11]).unwrap();
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x80).unwrap();
+        lcd.write(DATA_REGISTER, &[0x01]).unwrap();
+
+        assert_eq!(&[0x1F, 0x11], &lcd.cgram()[8..10]);
+        assert_eq!(0x01, lcd.line_bytes(0)[0]);
+        assert_eq!('.', lcd.line(0).chars().next().unwrap());
+    }
+
+    #[test]
+    fn test_clear_display() {
+        let mut lcd = init_lcd();
+        print(&mut lcd, "HELLO");
+        lcd.write_byte(INSTRUCTION_REGISTER, 0x01).unwrap();
+        assert_eq!(" ".repeat(16), lcd.line(0));
+        assert_eq!(0x00, lcd.get_address_counter());
+    }
+
+    #[test]
+    fn test_four_lines() {
+        let mut lcd = HD44780::new(20, 4);
+        for instruction in [0x38, 0x0C, 0x06, 0x01, 0x94] {
+            lcd.write_byte(INSTRUCTION_REGISTER, instruction).unwrap();
+        }
+        print(&mut lcd, "THIRD");
+        lcd.write_byte(INSTRUCTION_REGISTER, 0xD4).unwrap();
+        print(&mut lcd, "FOURTH");
+
+        assert_eq!(format!("{:<20}", "THIRD"), lcd.line(2));
+        assert_eq!(format!("{:<20}", "FOURTH"), lcd.line(3));
+    }
+}
@@ -0,0 +1,233 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::VecDeque;
+
+use super::{MatrixKeyboard, Ps2Keyboard};
+use crate::memory::{AddressableIO, MemoryError, MemoryStack};
+
+/*
+ * Keyboard
+ * Keyboards are fed by scripts rather than by the host so tests can type on
+ * them deterministically.
+ */
+pub trait Keyboard {
+    /// Queue ASCII keystrokes, one every `delay` CPU cycles.
+    fn type_keys(&mut self, keys: &[u8], delay: u64);
+}
+
+/*
+ * Return the keyboard of the memory, whatever its kind.
+ */
+pub fn find_keyboard(memory: &mut MemoryStack) -> Option<&mut dyn Keyboard> {
+    if memory.find_device::<AsciiKeyboard>().is_some() {
+        return memory
+            .find_device_mut::<AsciiKeyboard>()
+            .map(|keyboard| keyboard as &mut dyn Keyboard);
+    }
+    if memory.find_device::<MatrixKeyboard>().is_some() {
+        return memory
+            .find_device_mut::<MatrixKeyboard>()
+            .map(|keyboard| keyboard as &mut dyn Keyboard);
+    }
+
+    memory
+        .find_device_mut::<Ps2Keyboard>()
+        .map(|keyboard| keyboard as &mut dyn Keyboard)
+}
+
+/*
+ * KeyScript
+ * Keystrokes waiting to be typed. A key is due `delay` cycles after the
+ * device starts polling for it, devices only poll when they are ready to
+ * take a new key.
+ */
+#[derive(Debug, Default)]
+pub struct KeyScript {
+    keys: VecDeque<u8>,
+    delay: u64,
+    next: Option<u64>,
+}
+
+impl KeyScript {
+    pub fn push(&mut self, keys: &[u8], delay: u64) {
+        self.keys.extend(keys);
+        self.delay = delay;
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn get_delay(&self) -> u64 {
+        self.delay
+    }
+
+    pub fn poll(&mut self, cycle_count: u64) -> Option<u8> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        // the cycle counter was reset
+        if self.next.is_some_and(|next| next > cycle_count + self.delay) {
+            self.next = None;
+        }
+        let next = *self.next.get_or_insert(cycle_count + self.delay);
+        if cycle_count < next {
+            return None;
+        }
+        self.next = None;
+
+        self.keys.pop_front()
+    }
+}
+
+/*
+ * AsciiKeyboard
+ * Keyboard latching the ASCII code of the last key with a strobe bit, as the
+ * Apple II does. Reading 0x00-0x0F returns the code with bit 7 set while a
+ * key is waiting, any access to 0x10-0x1F clears the strobe. Line feeds are
+ * typed as carriage returns. A new key is typed once the strobe is cleared.
+ */
+#[derive(Debug, Default)]
+pub struct AsciiKeyboard {
+    script: KeyScript,
+    latch: u8,
+    strobe: Cell<bool>,
+}
+
+impl AsciiKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_script(&self) -> &KeyScript {
+        &self.script
+    }
+
+    fn register(&self, offset: usize) -> u8 {
+        match offset {
+            0x00..=0x0F if self.strobe.get() => self.latch | 0x80,
+            0x00..=0x0F => self.latch,
+            _ => 0x00,
+        }
+    }
+}
+
+impl Keyboard for AsciiKeyboard {
+    fn type_keys(&mut self, keys: &[u8], delay: u64) {
+        let keys: Vec<u8> = keys
+            .iter()
+            .map(|key| if *key == b'\n' { b'\r' } else { *key })
+            .collect();
+        self.script.push(&keys, delay);
+    }
+}
+
+impl AddressableIO for AsciiKeyboard {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        let data = self.peek(addr, len)?;
+        if addr + len > 0x10 {
+            self.strobe.set(false);
+        }
+
+        Ok(data)
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        if location + data.len() > 0x10 {
+            self.strobe.set(false);
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        0x20
+    }
+
+    fn peek(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok((addr..addr + len).map(|offset| self.register(offset)).collect())
+    }
+
+    fn kind(&self) -> &str {
+        "keyboard"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+
+    fn tick(&mut self, cycle_count: u64) {
+        if self.strobe.get() {
+            return;
+        }
+        if let Some(key) = self.script.poll(cycle_count) {
+            self.latch = key & 0x7F;
+            self.strobe.set(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_script() {
+        let mut script = KeyScript::default();
+        assert_eq!(None, script.poll(0));
+        script.push(b"AB", 100);
+        assert_eq!(None, script.poll(50));
+        assert_eq!(None, script.poll(149));
+        assert_eq!(Some(b'A'), script.poll(150));
+        assert_eq!(None, script.poll(200));
+        assert_eq!(Some(b'B'), script.poll(300));
+        assert!(script.is_empty());
+    }
+
+    #[test]
+    fn test_ascii_keyboard() {
+        let mut keyboard = AsciiKeyboard::new();
+        keyboard.type_keys(b"R\n", 10);
+        keyboard.tick(0);
+        assert_eq!(0x00, keyboard.read_byte(0x00).unwrap());
+        keyboard.tick(10);
+        assert_eq!(b'R' | 0x80, keyboard.read_byte(0x00).unwrap());
+        // the next key waits for the strobe to be cleared
+        keyboard.tick(100);
+        assert_eq!(b'R' | 0x80, keyboard.read_byte(0x05).unwrap());
+        // peeking does not clear the strobe
+        keyboard.peek(0x10, 1).unwrap();
+        assert_eq!(b'R' | 0x80, keyboard.read_byte(0x00).unwrap());
+        keyboard.read_byte(0x10).unwrap();
+        assert_eq!(b'R', keyboard.read_byte(0x00).unwrap());
+        keyboard.tick(105);
+        keyboard.tick(115);
+        assert_eq!(b'\r' | 0x80, keyboard.read_byte(0x00).unwrap());
+        keyboard.write_byte(0x10, 0x00).unwrap();
+        assert_eq!(b'\r', keyboard.read_byte(0x00).unwrap());
+    }
+
+    #[test]
+    fn test_find_keyboard() {
+        let mut memory = MemoryStack::new_with_ram();
+        assert!(find_keyboard(&mut memory).is_none());
+        memory.add_subsystem("KBD", 0xC000, AsciiKeyboard::new());
+        find_keyboard(&mut memory).unwrap().type_keys(b"A", 1);
+        assert_eq!(1, memory.find_device::<AsciiKeyboard>().unwrap().get_script().len());
+    }
+}
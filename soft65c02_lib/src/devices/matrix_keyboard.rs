@@ -0,0 +1,222 @@
+use std::any::Any;
+
+use super::keyboard::{KeyScript, Keyboard};
+use crate::memory::{AddressableIO, MemoryError};
+
+/*
+ * Position of the keys in the C64 keyboard matrix, MATRIX[row][column]. The
+ * row is selected by the port A bit and the column read on the port B bit.
+ * Keys with no ASCII code are set to 0.
+ */
+const MATRIX: [[u8; 8]; 8] = [
+    [0x08, b'\r', 0, 0, 0, 0, 0, 0],
+    [b'3', b'W', b'A', b'4', b'Z', b'S', b'E', 0],
+    [b'5', b'R', b'D', b'6', b'C', b'F', b'T', b'X'],
+    [b'7', b'Y', b'G', b'8', b'B', b'H', b'U', b'V'],
+    [b'9', b'I', b'J', b'0', b'M', b'K', b'O', b'N'],
+    [b'+', b'P', b'L', b'-', b'.', b':', b'@', b','],
+    [0, b'*', b';', 0, 0, b'=', b'^', b'/'],
+    [b'1', 0, 0, b'2', b' ', 0, b'Q', 0],
+];
+
+/// Left shift key position (row, column).
+const LEFT_SHIFT: (usize, usize) = (1, 7);
+
+/*
+ * Characters typed with the shift key and the key they are on.
+ */
+const SHIFTED: [(u8, u8); 14] = [
+    (b'!', b'1'),
+    (b'"', b'2'),
+    (b'#', b'3'),
+    (b'$', b'4'),
+    (b'%', b'5'),
+    (b'&', b'6'),
+    (b'\'', b'7'),
+    (b'(', b'8'),
+    (b')', b'9'),
+    (b'[', b':'),
+    (b']', b';'),
+    (b'<', b','),
+    (b'>', b'.'),
+    (b'?', b'/'),
+];
+
+/*
+ * Return the matrix positions of the keys to hold down to type the given
+ * character.
+ */
+fn key_positions(key: u8) -> Vec<(usize, usize)> {
+    let key = match key {
+        b'\n' => b'\r',
+        b'a'..=b'z' => key.to_ascii_uppercase(),
+        _ => key,
+    };
+    let (base, shift) = match SHIFTED.iter().find(|(shifted, _)| *shifted == key) {
+        Some((_, base)) => (*base, true),
+        None => (key, false),
+    };
+    let mut positions: Vec<(usize, usize)> = MATRIX
+        .iter()
+        .enumerate()
+        .flat_map(|(row, keys)| {
+            keys.iter()
+                .enumerate()
+                .filter(|(_, code)| base != 0 && **code == base)
+                .map(move |(column, _)| (row, column))
+        })
+        .take(1)
+        .collect();
+    if shift && !positions.is_empty() {
+        positions.push(LEFT_SHIFT);
+    }
+
+    positions
+}
+
+/*
+ * MatrixKeyboard
+ * 8x8 key matrix scanned through two ports as on the C64. Register 0 selects
+ * the rows to scan (a bit cleared selects the row), register 1 reads the
+ * columns of the keys held down on the selected rows as cleared bits.
+ * Scripted keys are held down for the typing delay then released, characters
+ * the C64 types with the shift key hold both keys. Characters not on the
+ * keyboard are skipped.
+ */
+#[derive(Debug, Default)]
+pub struct MatrixKeyboard {
+    script: KeyScript,
+    row_select: u8,
+    pressed: Vec<(usize, usize)>,
+    release_at: Option<u64>,
+}
+
+impl MatrixKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_script(&self) -> &KeyScript {
+        &self.script
+    }
+
+    /// Matrix positions (row, column) of the keys held down.
+    pub fn get_pressed(&self) -> &[(usize, usize)] {
+        &self.pressed
+    }
+
+    fn columns(&self) -> u8 {
+        let columns = self
+            .pressed
+            .iter()
+            .filter(|(row, _)| self.row_select & (1 << row) == 0)
+            .fold(0u8, |columns, (_, column)| columns | 1 << column);
+
+        !columns
+    }
+}
+
+impl Keyboard for MatrixKeyboard {
+    fn type_keys(&mut self, keys: &[u8], delay: u64) {
+        let keys: Vec<u8> = keys
+            .iter()
+            .copied()
+            .filter(|key| !key_positions(*key).is_empty())
+            .collect();
+        self.script.push(&keys, delay);
+    }
+}
+
+impl AddressableIO for MatrixKeyboard {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+        let registers = [self.row_select, self.columns()];
+
+        Ok(registers[addr..addr + len].to_vec())
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        if location == 0 {
+            self.row_select = data[0];
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        2
+    }
+
+    fn kind(&self) -> &str {
+        "keyboard"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+
+    fn tick(&mut self, cycle_count: u64) {
+        if let Some(release_at) = self.release_at {
+            // wait for the release unless the cycle counter was reset
+            if cycle_count < release_at && release_at <= cycle_count + self.script.get_delay() {
+                return;
+            }
+            self.pressed.clear();
+            self.release_at = None;
+        }
+        if let Some(key) = self.script.poll(cycle_count) {
+            self.pressed = key_positions(key);
+            self.release_at = Some(cycle_count + self.script.get_delay());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(keyboard: &mut MatrixKeyboard, row: usize) -> u8 {
+        keyboard.write_byte(0, !(1 << row)).unwrap();
+        keyboard.read_byte(1).unwrap()
+    }
+
+    #[test]
+    fn test_key_positions() {
+        assert_eq!(vec![(2, 1)], key_positions(b'R'));
+        assert_eq!(vec![(2, 1)], key_positions(b'r'));
+        assert_eq!(vec![(0, 1)], key_positions(b'\n'));
+        assert_eq!(vec![(7, 0), (1, 7)], key_positions(b'!'));
+        assert_eq!(vec![(7, 4)], key_positions(b' '));
+        assert!(key_positions(b'{').is_empty());
+    }
+
+    #[test]
+    fn test_matrix_keyboard() {
+        let mut keyboard = MatrixKeyboard::new();
+        keyboard.type_keys(b"r{\"", 100);
+        keyboard.tick(0);
+        assert_eq!(0xFF, scan(&mut keyboard, 2));
+        keyboard.tick(100);
+        assert_eq!(!0x02, scan(&mut keyboard, 2));
+        assert_eq!(0xFF, scan(&mut keyboard, 1));
+        // all rows selected
+        keyboard.write_byte(0, 0x00).unwrap();
+        assert_eq!(!0x02, keyboard.read_byte(1).unwrap());
+        // released after the delay, '{' is skipped
+        keyboard.tick(200);
+        assert_eq!(0xFF, scan(&mut keyboard, 2));
+        keyboard.tick(300);
+        assert_eq!(!0x08, scan(&mut keyboard, 7));
+        assert_eq!(!0x80, scan(&mut keyboard, 1));
+        assert!(keyboard.get_script().is_empty());
+    }
+}
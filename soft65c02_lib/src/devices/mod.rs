@@ -0,0 +1,44 @@
+use std::io::{Read, Seek, Write};
+
+/*
+ * Devices
+ * Memory mapped peripherals, they can be added to the memory stack as any
+ * other subsystem and found back with `MemoryStack::find_device`.
+ */
+mod ay38910;
+mod block_device;
+mod console;
+mod hd44780;
+mod keyboard;
+mod matrix_keyboard;
+mod ps2_keyboard;
+mod recorder;
+mod rtc;
+mod sd_card;
+mod spi;
+mod timer;
+
+pub use ay38910::{RegisterWrite, AY38910};
+pub use block_device::BlockDevice;
+pub use console::Console;
+pub use hd44780::HD44780;
+pub use keyboard::{find_keyboard, AsciiKeyboard, KeyScript, Keyboard};
+pub use matrix_keyboard::MatrixKeyboard;
+pub use ps2_keyboard::Ps2Keyboard;
+pub use recorder::RegisterRecorder;
+pub use rtc::{DateTime, Rtc};
+pub use sd_card::SdCard;
+pub use spi::{SpiBitBang, SpiPins, SpiSlave};
+pub use timer::Timer;
+
+/// Size of the blocks of the disk images.
+pub const BLOCK_SIZE: usize = 512;
+
+/*
+ * DiskImage
+ * Storage behind block devices, a file on the host or an in-memory buffer
+ * in tests.
+ */
+pub trait DiskImage: Read + Write + Seek {}
+
+impl<T: Read + Write + Seek> DiskImage for T {}
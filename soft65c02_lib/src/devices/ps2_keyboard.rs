@@ -0,0 +1,322 @@
+use std::any::Any;
+use std::collections::VecDeque;
+
+use super::keyboard::{KeyScript, Keyboard};
+use crate::memory::{AddressableIO, MemoryError};
+
+pub const REGISTER_LINES: usize = 0;
+pub const REGISTER_STATUS: usize = 1;
+
+pub const LINE_CLOCK: u8 = 0x01;
+pub const LINE_DATA: u8 = 0x02;
+
+pub const STATUS_IRQ_ENABLE: u8 = 0x01;
+pub const STATUS_FALLING_EDGE: u8 = 0x80;
+
+const SHIFT_SCANCODE: u8 = 0x12;
+const BREAK_PREFIX: u8 = 0xF0;
+
+/*
+ * Scan codes (set 2) of the unshifted characters of a US keyboard.
+ */
+const SCANCODES: [(u8, u8); 52] = [
+    (b'a', 0x1C), (b'b', 0x32), (b'c', 0x21), (b'd', 0x23), (b'e', 0x24),
+    (b'f', 0x2B), (b'g', 0x34), (b'h', 0x33), (b'i', 0x43), (b'j', 0x3B),
+    (b'k', 0x42), (b'l', 0x4B), (b'm', 0x3A), (b'n', 0x31), (b'o', 0x44),
+    (b'p', 0x4D), (b'q', 0x15), (b'r', 0x2D), (b's', 0x1B), (b't', 0x2C),
+    (b'u', 0x3C), (b'v', 0x2A), (b'w', 0x1D), (b'x', 0x22), (b'y', 0x35),
+    (b'z', 0x1A), (b'0', 0x45), (b'1', 0x16), (b'2', 0x1E), (b'3', 0x26),
+    (b'4', 0x25), (b'5', 0x2E), (b'6', 0x36), (b'7', 0x3D), (b'8', 0x3E),
+    (b'9', 0x46), (b' ', 0x29), (b'\n', 0x5A), (0x08, 0x66), (b'\t', 0x0D),
+    (0x1B, 0x76), (b'-', 0x4E), (b'=', 0x55), (b'[', 0x54), (b']', 0x5B),
+    (b'\\', 0x5D), (b';', 0x4C), (b'\'', 0x52), (b'`', 0x0E), (b',', 0x41),
+    (b'.', 0x49), (b'/', 0x4A),
+];
+
+/*
+ * Characters typed with the shift key and the key they are on.
+ */
+const SHIFTED: &[u8; 21] = b"!@#$%^&*()_+{}|:\"~<>?";
+const UNSHIFTED: &[u8; 21] = b"1234567890-=[]\\;'`,./";
+
+/*
+ * Return the bytes the keyboard sends when the key of the given character is
+ * pressed then released, shift included.
+ */
+fn scancodes(key: u8) -> Vec<u8> {
+    let key = if key == b'\r' { b'\n' } else { key };
+    let (base, shift) = match SHIFTED.iter().position(|shifted| *shifted == key) {
+        Some(index) => (UNSHIFTED[index], true),
+        None if key.is_ascii_uppercase() => (key.to_ascii_lowercase(), true),
+        None => (key, false),
+    };
+    let code = match SCANCODES.iter().find(|(character, _)| *character == base) {
+        Some((_, code)) => *code,
+        None => return Vec::new(),
+    };
+
+    if shift {
+        vec![SHIFT_SCANCODE, code, BREAK_PREFIX, code, BREAK_PREFIX, SHIFT_SCANCODE]
+    } else {
+        vec![code, BREAK_PREFIX, code]
+    }
+}
+
+/*
+ * Frame being sent: start bit, 8 data bits LSB first, odd parity, stop bit.
+ */
+#[derive(Debug)]
+struct Frame {
+    bits: [bool; 11],
+    start: u64,
+}
+
+impl Frame {
+    fn new(byte: u8, start: u64) -> Self {
+        let mut bits = [false; 11];
+        for (index, bit) in bits[1..9].iter_mut().enumerate() {
+            *bit = byte & (1 << index) != 0;
+        }
+        bits[9] = byte.count_ones().is_multiple_of(2);
+        bits[10] = true;
+
+        Self { bits, start }
+    }
+}
+
+/*
+ * Ps2Keyboard
+ * PS/2 keyboard seen as its clock and data lines on an input port, the
+ * firmware shifts the frames in bit by bit. Register 0 holds the lines
+ * (bit 0 clock, bit 1 data). Register 1 is a status: bit 7 is set on each
+ * falling edge of the clock and cleared by writing the register, bit 0
+ * enables the IRQ on falling edges. Each bit lasts the given number of cycles,
+ * the data line is set while the clock is high and must be sampled once it
+ * falls. Characters are sent as set 2 scan codes of a US keyboard, with their
+ * break codes; characters with no key are skipped.
+ */
+#[derive(Debug)]
+pub struct Ps2Keyboard {
+    script: KeyScript,
+    bit_cycles: u64,
+    bytes: VecDeque<u8>,
+    frame: Option<Frame>,
+    idle_until: u64,
+    now: u64,
+    edges: usize,
+    lines: u8,
+    status: u8,
+}
+
+impl Default for Ps2Keyboard {
+    fn default() -> Self {
+        Self::new(80)
+    }
+}
+
+impl Ps2Keyboard {
+    pub fn new(bit_cycles: u64) -> Self {
+        assert!(bit_cycles >= 2, "a PS/2 bit lasts at least 2 cycles");
+
+        Self {
+            script: KeyScript::default(),
+            bit_cycles,
+            bytes: VecDeque::new(),
+            frame: None,
+            idle_until: 0,
+            now: 0,
+            edges: 0,
+            lines: LINE_CLOCK | LINE_DATA,
+            status: 0x00,
+        }
+    }
+
+    pub fn get_script(&self) -> &KeyScript {
+        &self.script
+    }
+
+    /// Tell if bytes are waiting to be sent or being sent.
+    pub fn is_sending(&self) -> bool {
+        self.frame.is_some() || !self.bytes.is_empty()
+    }
+
+    fn update_frame(&mut self, cycle_count: u64) {
+        let frame = match &self.frame {
+            Some(frame) => frame,
+            None => return,
+        };
+        let elapsed = cycle_count - frame.start;
+        let half = self.bit_cycles / 2;
+        let index = (elapsed / self.bit_cycles) as usize;
+        if index >= frame.bits.len() {
+            self.frame = None;
+            self.lines = LINE_CLOCK | LINE_DATA;
+            self.idle_until = cycle_count + self.bit_cycles;
+            return;
+        }
+        let edges = ((elapsed + self.bit_cycles - half) / self.bit_cycles) as usize;
+        if edges > self.edges {
+            self.status |= STATUS_FALLING_EDGE;
+            self.edges = edges;
+        }
+        let data = if frame.bits[index] { LINE_DATA } else { 0x00 };
+        let clock = if elapsed % self.bit_cycles < half { LINE_CLOCK } else { 0x00 };
+        self.lines = data | clock;
+    }
+}
+
+impl Keyboard for Ps2Keyboard {
+    fn type_keys(&mut self, keys: &[u8], delay: u64) {
+        let keys: Vec<u8> = keys
+            .iter()
+            .copied()
+            .filter(|key| !scancodes(*key).is_empty())
+            .collect();
+        self.script.push(&keys, delay);
+    }
+}
+
+impl AddressableIO for Ps2Keyboard {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok((addr..addr + len)
+            .map(|offset| match offset {
+                REGISTER_LINES => self.lines,
+                _ => self.status,
+            })
+            .collect())
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        if location + data.len() > REGISTER_STATUS {
+            self.status = data[REGISTER_STATUS - location] & STATUS_IRQ_ENABLE;
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        2
+    }
+
+    fn kind(&self) -> &str {
+        "keyboard"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+
+    fn irq(&self) -> bool {
+        self.status & STATUS_IRQ_ENABLE != 0 && self.status & STATUS_FALLING_EDGE != 0
+    }
+
+    fn tick(&mut self, cycle_count: u64) {
+        // the cycle counter was reset, the frame starts over
+        if cycle_count < self.now {
+            if let Some(frame) = self.frame.as_mut() {
+                frame.start = cycle_count;
+                self.edges = 0;
+            }
+            self.idle_until = cycle_count;
+        }
+        self.now = cycle_count;
+        self.update_frame(cycle_count);
+        if self.frame.is_some() {
+            return;
+        }
+        if self.bytes.is_empty() {
+            if let Some(key) = self.script.poll(cycle_count) {
+                self.bytes.extend(scancodes(key));
+            }
+        }
+        if cycle_count >= self.idle_until {
+            if let Some(byte) = self.bytes.pop_front() {
+                self.frame = Some(Frame::new(byte, cycle_count));
+                self.edges = 0;
+                self.update_frame(cycle_count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scancodes() {
+        assert_eq!(vec![0x2D, 0xF0, 0x2D], scancodes(b'r'));
+        assert_eq!(vec![0x12, 0x2D, 0xF0, 0x2D, 0xF0, 0x12], scancodes(b'R'));
+        assert_eq!(vec![0x12, 0x16, 0xF0, 0x16, 0xF0, 0x12], scancodes(b'!'));
+        assert_eq!(vec![0x5A, 0xF0, 0x5A], scancodes(b'\r'));
+        assert_eq!(vec![0x4A, 0xF0, 0x4A], scancodes(b'/'));
+        assert!(scancodes(0x80).is_empty());
+    }
+
+    /*
+     * Sample the data line on each falling edge as a firmware would.
+     */
+    fn receive(keyboard: &mut Ps2Keyboard, mut cycle: u64, count: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut bits = Vec::new();
+        while bytes.len() < count {
+            keyboard.tick(cycle);
+            if keyboard.read_byte(REGISTER_STATUS).unwrap() & STATUS_FALLING_EDGE != 0 {
+                keyboard.write_byte(REGISTER_STATUS, 0x00).unwrap();
+                bits.push(keyboard.read_byte(REGISTER_LINES).unwrap() & LINE_DATA != 0);
+                if bits.len() == 11 {
+                    assert!(!bits[0], "start bit");
+                    assert!(bits[10], "stop bit");
+                    let byte = (0..8).fold(0u8, |byte, i| byte | (bits[i + 1] as u8) << i);
+                    assert_eq!(byte.count_ones().is_multiple_of(2), bits[9], "parity");
+                    bytes.push(byte);
+                    bits.clear();
+                }
+            }
+            cycle += 3;
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn test_ps2_keyboard() {
+        let mut keyboard = Ps2Keyboard::default();
+        keyboard.tick(0);
+        assert_eq!(LINE_CLOCK | LINE_DATA, keyboard.read_byte(REGISTER_LINES).unwrap());
+        keyboard.type_keys(b"A", 1000);
+
+        assert_eq!(
+            vec![0x12, 0x1C, 0xF0, 0x1C, 0xF0, 0x12],
+            receive(&mut keyboard, 1, 6)
+        );
+        assert!(keyboard.get_script().is_empty());
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut keyboard = Ps2Keyboard::new(10);
+        keyboard.write_byte(REGISTER_STATUS, STATUS_IRQ_ENABLE).unwrap();
+        keyboard.type_keys(b"a", 0);
+        keyboard.tick(0);
+        // clock high during the first half of the start bit
+        assert_eq!(LINE_CLOCK, keyboard.read_byte(REGISTER_LINES).unwrap());
+        assert!(!keyboard.irq());
+        keyboard.tick(5);
+        assert_eq!(0x00, keyboard.read_byte(REGISTER_LINES).unwrap());
+        assert!(keyboard.irq());
+        keyboard.write_byte(REGISTER_STATUS, STATUS_IRQ_ENABLE).unwrap();
+        assert!(!keyboard.irq());
+    }
+}
@@ -0,0 +1,108 @@
+use std::any::Any;
+
+use super::RegisterWrite;
+use crate::memory::{AddressableIO, MemoryError};
+
+/*
+ * RegisterRecorder
+ * Stand-in for a chip that is not emulated: each write is logged with its
+ * cycle and latched, reading a register returns the last value written to it
+ * or the initial value.
+ */
+#[derive(Debug)]
+pub struct RegisterRecorder {
+    registers: Vec<u8>,
+    now: u64,
+    log: Vec<RegisterWrite>,
+}
+
+impl RegisterRecorder {
+    pub fn new(size: usize, initial: u8) -> Self {
+        Self {
+            registers: vec![initial; size],
+            now: 0,
+            log: Vec::new(),
+        }
+    }
+
+    pub fn get_register_log(&self) -> &[RegisterWrite] {
+        &self.log
+    }
+
+    /// Last value written to the register, if any.
+    pub fn last_write(&self, register: usize) -> Option<u8> {
+        self.log
+            .iter()
+            .rev()
+            .find(|entry| entry.register as usize == register)
+            .map(|entry| entry.value)
+    }
+}
+
+impl AddressableIO for RegisterRecorder {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok(self.registers[addr..addr + len].to_vec())
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        for (register, value) in (location..).zip(data.iter()) {
+            self.registers[register] = *value;
+            self.log.push(RegisterWrite {
+                cycle: self.now,
+                register: register as u8,
+                value: *value,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        self.registers.len()
+    }
+
+    fn kind(&self) -> &str {
+        "recorder"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn tick(&mut self, cycle_count: u64) {
+        self.now = cycle_count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorder() {
+        let mut recorder = RegisterRecorder::new(0x100, 0xFF);
+        assert_eq!(0xFF, recorder.read_byte(0x1F).unwrap());
+        recorder.write_byte(0x1A, 0x94).unwrap();
+        recorder.tick(120);
+        recorder.write(0x1A, &[0x00, 0x42]).unwrap();
+
+        assert_eq!(vec![0x00, 0x42], recorder.read(0x1A, 2).unwrap());
+        assert_eq!(Some(0x00), recorder.last_write(0x1A));
+        assert_eq!(None, recorder.last_write(0x1F));
+        assert_eq!(
+            &[
+                RegisterWrite { cycle: 0, register: 0x1A, value: 0x94 },
+                RegisterWrite { cycle: 120, register: 0x1A, value: 0x00 },
+                RegisterWrite { cycle: 120, register: 0x1B, value: 0x42 },
+            ],
+            recorder.get_register_log()
+        );
+    }
+}
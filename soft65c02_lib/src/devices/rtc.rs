@@ -0,0 +1,292 @@
+use std::any::Any;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::memory::{AddressableIO, MemoryError};
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+/*
+ * DateTime
+ * Calendar time of the RTC, from 1970 onwards, without time zone.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl DateTime {
+    fn is_leap_year(year: u16) -> bool {
+        (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+    }
+
+    fn days_in_month(year: u16, month: u8) -> u8 {
+        match month {
+            2 if Self::is_leap_year(year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    /*
+     * Build a date from the number of seconds since 1970-01-01T00:00:00,
+     * walking years then months is fast enough for a few centuries.
+     */
+    pub fn from_timestamp(timestamp: u64) -> Self {
+        let mut days = timestamp / SECONDS_PER_DAY;
+        let time = timestamp % SECONDS_PER_DAY;
+        let mut year = 1970;
+        loop {
+            let year_days = if Self::is_leap_year(year) { 366 } else { 365 };
+            if days < year_days {
+                break;
+            }
+            days -= year_days;
+            year += 1;
+        }
+        let mut month = 1;
+        while days >= Self::days_in_month(year, month) as u64 {
+            days -= Self::days_in_month(year, month) as u64;
+            month += 1;
+        }
+
+        Self {
+            year,
+            month,
+            day: days as u8 + 1,
+            hours: (time / 3600) as u8,
+            minutes: (time / 60 % 60) as u8,
+            seconds: (time % 60) as u8,
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        let mut days: u64 = (1970..self.year)
+            .map(|year| if Self::is_leap_year(year) { 366 } else { 365 })
+            .sum();
+        days += (1..self.month)
+            .map(|month| Self::days_in_month(self.year, month) as u64)
+            .sum::<u64>();
+        days += self.day as u64 - 1;
+
+        days * SECONDS_PER_DAY
+            + self.hours as u64 * 3600
+            + self.minutes as u64 * 60
+            + self.seconds as u64
+    }
+
+    /// Day of the week, 0 is Sunday.
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        ((self.timestamp() / SECONDS_PER_DAY + 4) % 7) as u8
+    }
+}
+
+impl FromStr for DateTime {
+    type Err = String;
+
+    /// Parse a date as `YYYY-MM-DDTHH:MM:SS`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid date '{}', expected YYYY-MM-DDTHH:MM:SS", value);
+        let (date, time) = value.split_once('T').ok_or_else(error)?;
+        let date: Vec<&str> = date.split('-').collect();
+        let time: Vec<&str> = time.split(':').collect();
+        if date.len() != 3 || time.len() != 3 {
+            return Err(error());
+        }
+        let datetime = Self {
+            year: date[0].parse().map_err(|_| error())?,
+            month: date[1].parse().map_err(|_| error())?,
+            day: date[2].parse().map_err(|_| error())?,
+            hours: time[0].parse().map_err(|_| error())?,
+            minutes: time[1].parse().map_err(|_| error())?,
+            seconds: time[2].parse().map_err(|_| error())?,
+        };
+        if datetime.year < 1970
+            || !(1..=12).contains(&datetime.month)
+            || datetime.day == 0
+            || datetime.day > Self::days_in_month(datetime.year, datetime.month)
+            || datetime.hours > 23
+            || datetime.minutes > 59
+            || datetime.seconds > 59
+        {
+            return Err(error());
+        }
+
+        Ok(datetime)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hours, self.minutes, self.seconds
+        )
+    }
+}
+
+/*
+ * Rtc
+ * Real time clock following the CPU cycles at the given frequency rather
+ * than the host clock, its time only changes when the CPU runs. It starts on
+ * 2000-01-01T00:00:00. The read only registers are binary values:
+ *   0: seconds, 1: minutes, 2: hours, 3: day of month (1-31),
+ *   4: month (1-12), 5-6: year (little endian), 7: day of week (0 = Sunday)
+ * Writes are ignored.
+ */
+#[derive(Debug)]
+pub struct Rtc {
+    frequency: u64,
+    timestamp: u64,
+    origin: Option<u64>,
+    now: Option<u64>,
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new(1_000_000)
+    }
+}
+
+impl Rtc {
+    pub fn new(frequency: u64) -> Self {
+        assert!(frequency > 0, "the RTC frequency cannot be null");
+
+        Self {
+            frequency,
+            timestamp: DateTime::from_str("2000-01-01T00:00:00").unwrap().timestamp(),
+            origin: None,
+            now: None,
+        }
+    }
+
+    /// Pin the clock to the given time, it goes on from there.
+    pub fn set_time(&mut self, datetime: DateTime) {
+        self.timestamp = datetime.timestamp();
+        self.origin = self.now;
+    }
+
+    pub fn get_time(&self) -> DateTime {
+        let elapsed = match (self.origin, self.now) {
+            (Some(origin), Some(now)) => (now - origin) / self.frequency,
+            _ => 0,
+        };
+
+        DateTime::from_timestamp(self.timestamp + elapsed)
+    }
+
+    fn registers(&self) -> [u8; 8] {
+        let time = self.get_time();
+        let year = time.year.to_le_bytes();
+
+        [
+            time.seconds,
+            time.minutes,
+            time.hours,
+            time.day,
+            time.month,
+            year[0],
+            year[1],
+            time.weekday(),
+        ]
+    }
+}
+
+impl AddressableIO for Rtc {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok(self.registers()[addr..addr + len].to_vec())
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        8
+    }
+
+    fn kind(&self) -> &str {
+        "RTC"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        Some(self)
+    }
+
+    fn tick(&mut self, cycle_count: u64) {
+        // the cycle counter was reset, keep the time reached so far
+        if self.now.is_some_and(|now| cycle_count < now) {
+            self.timestamp = self.get_time().timestamp();
+            self.origin = None;
+        }
+        // cycles elapsed before the first tick do not count
+        self.origin.get_or_insert(cycle_count);
+        self.now = Some(cycle_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datetime() {
+        let datetime: DateTime = "2024-02-29T13:45:07".parse().unwrap();
+        assert_eq!(1709214307, datetime.timestamp());
+        assert_eq!(datetime, DateTime::from_timestamp(1709214307));
+        assert_eq!("2024-02-29T13:45:07", datetime.to_string());
+        // Thursday
+        assert_eq!(4, datetime.weekday());
+        assert_eq!(DateTime::from_timestamp(0), "1970-01-01T00:00:00".parse().unwrap());
+
+        assert!("2023-02-29T00:00:00".parse::<DateTime>().is_err());
+        assert!("2024-13-01T00:00:00".parse::<DateTime>().is_err());
+        assert!("2024-01-01T24:00:00".parse::<DateTime>().is_err());
+        assert!("2024-01-01 00:00:00".parse::<DateTime>().is_err());
+    }
+
+    #[test]
+    fn test_rtc() {
+        let mut rtc = Rtc::new(1000);
+        assert_eq!("2000-01-01T00:00:00", rtc.get_time().to_string());
+        rtc.tick(5000);
+        rtc.set_time("2024-12-31T23:59:58".parse().unwrap());
+        rtc.tick(6999);
+        assert_eq!(
+            vec![59, 59, 23, 31, 12, 0xE8, 0x07, 2],
+            rtc.read(0, 8).unwrap()
+        );
+        rtc.tick(7000);
+        assert_eq!(
+            vec![0, 0, 0, 1, 1, 0xE9, 0x07, 3],
+            rtc.read(0, 8).unwrap()
+        );
+        // writes are ignored
+        rtc.write_byte(0, 0x12).unwrap();
+        assert_eq!(0, rtc.read_byte(0).unwrap());
+        // cycle counter reset
+        rtc.tick(0);
+        rtc.tick(1000);
+        assert_eq!(1, rtc.read_byte(0).unwrap());
+    }
+}
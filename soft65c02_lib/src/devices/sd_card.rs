@@ -0,0 +1,334 @@
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{self, SeekFrom};
+use std::path::Path;
+
+use super::spi::SpiSlave;
+use super::{DiskImage, BLOCK_SIZE};
+
+const R1_IDLE: u8 = 0x01;
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+const R1_PARAMETER_ERROR: u8 = 0x40;
+const DATA_TOKEN: u8 = 0xFE;
+const DATA_ACCEPTED: u8 = 0x05;
+const DATA_WRITE_ERROR: u8 = 0x0D;
+const DATA_ERROR_TOKEN: u8 = 0x01;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CardState {
+    Command,
+    WriteToken(u32),
+    WriteData(u32),
+}
+
+/*
+ * SdCard
+ * SD card in SPI mode backed by a disk image. It answers the commands needed
+ * to initialize a SDHC card (CMD0, CMD8, CMD55 + ACMD41, CMD58) and to read
+ * or write single blocks (CMD17, CMD24). Blocks are 512 bytes and addressed
+ * by their number. CRCs are not checked.
+ */
+pub struct SdCard {
+    image: Box<dyn DiskImage>,
+    blocks: u32,
+    idle: bool,
+    app_command: bool,
+    state: CardState,
+    command: Vec<u8>,
+    data: Vec<u8>,
+    responses: VecDeque<u8>,
+}
+
+impl SdCard {
+    pub fn new(mut image: impl DiskImage + 'static) -> io::Result<Self> {
+        let size = image.seek(SeekFrom::End(0))?;
+
+        Ok(Self {
+            image: Box::new(image),
+            blocks: (size / BLOCK_SIZE as u64) as u32,
+            idle: true,
+            app_command: false,
+            state: CardState::Command,
+            command: Vec::with_capacity(6),
+            data: Vec::with_capacity(BLOCK_SIZE + 2),
+            responses: VecDeque::new(),
+        })
+    }
+
+    /// Open a disk image file in read-write mode.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        Self::new(file)
+    }
+
+    pub fn get_blocks(&self) -> u32 {
+        self.blocks
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    fn r1(&self) -> u8 {
+        if self.idle {
+            R1_IDLE
+        } else {
+            0x00
+        }
+    }
+
+    fn read_block(&mut self, block: u32) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; BLOCK_SIZE];
+        self.image
+            .seek(SeekFrom::Start(block as u64 * BLOCK_SIZE as u64))?;
+        self.image.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+
+    fn write_block(&mut self, block: u32, data: &[u8]) -> io::Result<()> {
+        self.image
+            .seek(SeekFrom::Start(block as u64 * BLOCK_SIZE as u64))?;
+        self.image.write_all(data)?;
+        self.image.flush()
+    }
+
+    fn execute(&mut self) -> Vec<u8> {
+        let index = self.command[0] & 0x3F;
+        let argument = u32::from_be_bytes([
+            self.command[1],
+            self.command[2],
+            self.command[3],
+            self.command[4],
+        ]);
+        let app_command = std::mem::take(&mut self.app_command);
+
+        match (app_command, index) {
+            (_, 0) => {
+                self.idle = true;
+                vec![R1_IDLE]
+            }
+            (false, 8) => vec![
+                self.r1(),
+                0x00,
+                0x00,
+                (argument >> 8) as u8 & 0x0F,
+                argument as u8,
+            ],
+            (false, 55) => {
+                self.app_command = true;
+                vec![self.r1()]
+            }
+            (true, 41) => {
+                self.idle = false;
+                vec![0x00]
+            }
+            // card powered up with high capacity (block addressing)
+            (false, 58) => vec![self.r1(), 0xC0, 0xFF, 0x80, 0x00],
+            (false, 16) if argument as usize == BLOCK_SIZE => vec![self.r1()],
+            (false, 16) => vec![self.r1() | R1_PARAMETER_ERROR],
+            (false, 17) | (false, 24) if self.idle => vec![R1_IDLE | R1_ILLEGAL_COMMAND],
+            (false, 17) | (false, 24) if argument >= self.blocks => vec![R1_PARAMETER_ERROR],
+            (false, 17) => match self.read_block(argument) {
+                Ok(data) => {
+                    let mut response = vec![0x00, 0xFF, DATA_TOKEN];
+                    response.extend(data);
+                    response.extend([0xFF, 0xFF]);
+                    response
+                }
+                Err(_) => vec![0x00, 0xFF, DATA_ERROR_TOKEN],
+            },
+            (false, 24) => {
+                self.state = CardState::WriteToken(argument);
+                vec![0x00]
+            }
+            _ => vec![self.r1() | R1_ILLEGAL_COMMAND],
+        }
+    }
+
+    fn receive_command(&mut self, value: u8) {
+        if self.command.is_empty() && value & 0xC0 != 0x40 {
+            return;
+        }
+        self.command.push(value);
+        if self.command.len() == 6 {
+            let response = self.execute();
+            self.command.clear();
+            self.responses.clear();
+            // one byte of Ncr before the card answers
+            self.responses.push_back(0xFF);
+            self.responses.extend(response);
+        }
+    }
+}
+
+impl SpiSlave for SdCard {
+    fn select(&mut self, selected: bool) {
+        if !selected {
+            self.command.clear();
+            self.data.clear();
+            self.responses.clear();
+            self.state = CardState::Command;
+        }
+    }
+
+    fn shift_out(&mut self) -> u8 {
+        self.responses.pop_front().unwrap_or(0xFF)
+    }
+
+    fn shift_in(&mut self, value: u8) {
+        match self.state {
+            CardState::Command => self.receive_command(value),
+            CardState::WriteToken(block) => {
+                if value == DATA_TOKEN {
+                    self.data.clear();
+                    self.state = CardState::WriteData(block);
+                }
+            }
+            CardState::WriteData(block) => {
+                self.data.push(value);
+                // data block followed by its CRC
+                if self.data.len() == BLOCK_SIZE + 2 {
+                    let data = std::mem::take(&mut self.data);
+                    let token = match self.write_block(block, &data[..BLOCK_SIZE]) {
+                        Ok(()) => DATA_ACCEPTED,
+                        Err(_) => DATA_WRITE_ERROR,
+                    };
+                    self.responses.clear();
+                    self.responses.extend([token, 0x00]);
+                    self.state = CardState::Command;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::{Cursor, Read, Seek, Write};
+    use std::rc::Rc;
+
+    /*
+     * In-memory image whose content stays reachable once given to the card.
+     */
+    #[derive(Clone)]
+    struct SharedImage(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+    impl Read for SharedImage {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.borrow_mut().read(buf)
+        }
+    }
+
+    impl Write for SharedImage {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for SharedImage {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.borrow_mut().seek(pos)
+        }
+    }
+
+    fn get_image() -> SharedImage {
+        let mut data = vec![0; BLOCK_SIZE * 4];
+        for (block, chunk) in data.chunks_mut(BLOCK_SIZE).enumerate() {
+            chunk.fill(block as u8 + 0xA0);
+        }
+
+        SharedImage(Rc::new(RefCell::new(Cursor::new(data))))
+    }
+
+    fn command(card: &mut SdCard, index: u8, argument: u32, len: usize) -> Vec<u8> {
+        card.shift_in(0x40 | index);
+        for byte in argument.to_be_bytes() {
+            card.shift_in(byte);
+        }
+        card.shift_in(0x95);
+        (0..len).map(|_| card.transfer(0xFF)).collect()
+    }
+
+    fn initialize(card: &mut SdCard) {
+        assert_eq!(vec![0xFF, 0x01], command(card, 0, 0, 2));
+        assert_eq!(
+            vec![0xFF, 0x01, 0x00, 0x00, 0x01, 0xAA],
+            command(card, 8, 0x1AA, 6)
+        );
+        assert_eq!(vec![0xFF, 0x01], command(card, 55, 0, 2));
+        assert_eq!(vec![0xFF, 0x00], command(card, 41, 0x4000_0000, 2));
+        assert!(!card.is_idle());
+    }
+
+    #[test]
+    fn test_initialize() {
+        let mut card = SdCard::new(get_image()).unwrap();
+        assert_eq!(4, card.get_blocks());
+        assert!(card.is_idle());
+        // ACMD41 is not known without CMD55
+        assert_eq!(vec![0xFF, 0x05], command(&mut card, 41, 0, 2));
+        initialize(&mut card);
+        assert_eq!(
+            vec![0xFF, 0x00, 0xC0, 0xFF, 0x80, 0x00],
+            command(&mut card, 58, 0, 6)
+        );
+        assert_eq!(vec![0xFF, 0x04], command(&mut card, 63, 0, 2));
+    }
+
+    #[test]
+    fn test_read_block() {
+        let mut card = SdCard::new(get_image()).unwrap();
+        // not initialized yet
+        assert_eq!(vec![0xFF, 0x05], command(&mut card, 17, 0, 2));
+        initialize(&mut card);
+        let response = command(&mut card, 17, 2, 4 + BLOCK_SIZE + 2);
+        assert_eq!(vec![0xFF, 0x00, 0xFF, 0xFE], response[..4]);
+        assert!(response[4..4 + BLOCK_SIZE].iter().all(|b| *b == 0xA2));
+        assert_eq!(vec![0xFF, 0xFF], response[4 + BLOCK_SIZE..]);
+        assert_eq!(0xFF, card.transfer(0xFF));
+        // out of the image
+        assert_eq!(vec![0xFF, 0x40], command(&mut card, 17, 4, 2));
+    }
+
+    #[test]
+    fn test_write_block() {
+        let image = get_image();
+        let mut card = SdCard::new(image.clone()).unwrap();
+        initialize(&mut card);
+        assert_eq!(vec![0xFF, 0x00], command(&mut card, 24, 1, 2));
+        card.transfer(0xFF);
+        card.transfer(DATA_TOKEN);
+        for index in 0..BLOCK_SIZE {
+            card.transfer(index as u8);
+        }
+        card.transfer(0xFF);
+        card.transfer(0xFF);
+        assert_eq!(DATA_ACCEPTED, card.transfer(0xFF) & 0x1F);
+        assert_eq!(0x00, card.transfer(0xFF));
+        assert_eq!(0xFF, card.transfer(0xFF));
+
+        let data = image.0.borrow().get_ref().clone();
+        assert!(data[..BLOCK_SIZE].iter().all(|b| *b == 0xA0));
+        assert_eq!(0x00, data[BLOCK_SIZE]);
+        assert_eq!(0xFF, data[BLOCK_SIZE * 2 - 1]);
+        assert!(data[BLOCK_SIZE * 2..].iter().all(|b| *b == 0xA2 || *b == 0xA3));
+    }
+
+    #[test]
+    fn test_deselect_aborts() {
+        let mut card = SdCard::new(get_image()).unwrap();
+        card.shift_in(0x40);
+        card.shift_in(0x00);
+        card.select(false);
+        card.select(true);
+        assert_eq!(vec![0xFF, 0x01], command(&mut card, 0, 0, 2));
+    }
+}
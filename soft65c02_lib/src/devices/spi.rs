@@ -0,0 +1,267 @@
+use std::any::Any;
+
+use crate::memory::{AddressableIO, MemoryError};
+
+/*
+ * SpiSlave
+ * A device on the SPI bus, seen one byte at a time. The byte shifted out by
+ * the slave is requested before the byte sent by the master is known so it
+ * can only depend on the bytes previously received.
+ */
+pub trait SpiSlave {
+    /// The chip select line changed, `selected` is true when it is asserted.
+    fn select(&mut self, _selected: bool) {}
+
+    /// The byte the slave shifts out during the next transfer.
+    fn shift_out(&mut self) -> u8;
+
+    /// The byte received from the master at the end of a transfer.
+    fn shift_in(&mut self, value: u8);
+
+    /// Exchange a whole byte, used by byte oriented SPI controllers.
+    fn transfer(&mut self, value: u8) -> u8 {
+        let output = self.shift_out();
+        self.shift_in(value);
+
+        output
+    }
+}
+
+/*
+ * SpiPins
+ * Bits of the port the SPI lines are wired to. Chip select is active low.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpiPins {
+    pub sck: u8,
+    pub mosi: u8,
+    pub miso: u8,
+    pub cs: u8,
+}
+
+impl Default for SpiPins {
+    fn default() -> Self {
+        Self {
+            sck: 0,
+            mosi: 1,
+            miso: 7,
+            cs: 2,
+        }
+    }
+}
+
+impl SpiPins {
+    fn mask(pin: u8) -> u8 {
+        1 << pin
+    }
+}
+
+/*
+ * SpiBitBang
+ * A one byte I/O port with SPI lines wired to its pins, as firmwares drive a
+ * SD card through a VIA port. The bus works in mode 0: the master sets MOSI
+ * while SCK is low, both sides sample on the rising edge and the slave sets
+ * its next MISO bit on the falling edge. Reading the port returns the last
+ * value written with the MISO bit of the slave.
+ */
+pub struct SpiBitBang {
+    pins: SpiPins,
+    slave: Box<dyn SpiSlave>,
+    port: u8,
+    input: u8,
+    input_bits: u8,
+    output: u8,
+    output_mask: u8,
+    reload_output: bool,
+}
+
+impl SpiBitBang {
+    pub fn new(pins: SpiPins, slave: impl SpiSlave + 'static) -> Self {
+        Self {
+            port: SpiPins::mask(pins.cs),
+            pins,
+            slave: Box::new(slave),
+            input: 0,
+            input_bits: 0,
+            output: 0xFF,
+            output_mask: 0x80,
+            reload_output: false,
+        }
+    }
+
+    pub fn get_pins(&self) -> SpiPins {
+        self.pins
+    }
+
+    pub fn is_selected(&self) -> bool {
+        self.port & SpiPins::mask(self.pins.cs) == 0
+    }
+
+    fn miso(&self) -> bool {
+        !self.is_selected() || self.output & self.output_mask != 0
+    }
+
+    fn read_port(&self) -> u8 {
+        let miso = SpiPins::mask(self.pins.miso);
+        if self.miso() {
+            self.port | miso
+        } else {
+            self.port & !miso
+        }
+    }
+
+    fn write_port(&mut self, value: u8) {
+        let changed = self.port ^ value;
+        self.port = value;
+
+        if changed & SpiPins::mask(self.pins.cs) != 0 {
+            let selected = self.is_selected();
+            self.slave.select(selected);
+            self.input_bits = 0;
+            self.reload_output = false;
+            if selected {
+                self.output = self.slave.shift_out();
+                self.output_mask = 0x80;
+            }
+        }
+        if !self.is_selected() || changed & SpiPins::mask(self.pins.sck) == 0 {
+            return;
+        }
+
+        if value & SpiPins::mask(self.pins.sck) != 0 {
+            let mosi = (value & SpiPins::mask(self.pins.mosi) != 0) as u8;
+            self.input = (self.input << 1) | mosi;
+            self.input_bits += 1;
+            if self.input_bits == 8 {
+                self.slave.shift_in(self.input);
+                self.input_bits = 0;
+                self.reload_output = true;
+            }
+        } else if self.reload_output {
+            self.output = self.slave.shift_out();
+            self.output_mask = 0x80;
+            self.reload_output = false;
+        } else if self.input_bits > 0 {
+            self.output_mask >>= 1;
+        }
+    }
+}
+
+impl AddressableIO for SpiBitBang {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok(vec![self.read_port(); len])
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        for value in data {
+            self.write_port(*value);
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        1
+    }
+
+    fn kind(&self) -> &str {
+        "SPI"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /*
+     * Sends back the bytes it receives, the first byte sent is 0xA5.
+     */
+    struct EchoSlave {
+        received: Rc<RefCell<Vec<u8>>>,
+        selected: Rc<RefCell<Vec<bool>>>,
+        next: u8,
+    }
+
+    impl SpiSlave for EchoSlave {
+        fn select(&mut self, selected: bool) {
+            self.selected.borrow_mut().push(selected);
+        }
+
+        fn shift_out(&mut self) -> u8 {
+            self.next
+        }
+
+        fn shift_in(&mut self, value: u8) {
+            self.received.borrow_mut().push(value);
+            self.next = value;
+        }
+    }
+
+    fn bit_bang(port: &mut SpiBitBang, value: u8) -> u8 {
+        let mut result = 0;
+        for bit in (0..8).rev() {
+            let mosi = ((value >> bit) & 1) << 1;
+            port.write_byte(0, mosi).unwrap();
+            port.write_byte(0, mosi | 0x01).unwrap();
+            result = (result << 1) | (port.read_byte(0).unwrap() >> 7);
+        }
+        port.write_byte(0, 0x00).unwrap();
+
+        result
+    }
+
+    #[test]
+    fn test_bit_bang() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let selected = Rc::new(RefCell::new(Vec::new()));
+        let slave = EchoSlave {
+            received: received.clone(),
+            selected: selected.clone(),
+            next: 0xA5,
+        };
+        let mut port = SpiBitBang::new(SpiPins::default(), slave);
+        assert!(!port.is_selected());
+        assert_eq!(0x84, port.read_byte(0).unwrap());
+
+        // assert chip select, SCK low
+        port.write_byte(0, 0x00).unwrap();
+        assert!(port.is_selected());
+        assert_eq!(0xA5, bit_bang(&mut port, 0x3C));
+        assert_eq!(0x3C, bit_bang(&mut port, 0x81));
+        assert_eq!(0x81, bit_bang(&mut port, 0xFF));
+        port.write_byte(0, 0x04).unwrap();
+
+        assert_eq!(vec![0x3C, 0x81, 0xFF], *received.borrow());
+        assert_eq!(vec![true, false], *selected.borrow());
+        // clocks without chip select are ignored
+        for _ in 0..8 {
+            port.write_byte(0, 0x05).unwrap();
+            port.write_byte(0, 0x04).unwrap();
+        }
+        assert_eq!(3, received.borrow().len());
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut slave = EchoSlave {
+            received: Rc::new(RefCell::new(Vec::new())),
+            selected: Rc::new(RefCell::new(Vec::new())),
+            next: 0x00,
+        };
+        assert_eq!(0x00, slave.transfer(0x12));
+        assert_eq!(0x12, slave.transfer(0x34));
+    }
+}
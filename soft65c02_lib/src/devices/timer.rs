@@ -0,0 +1,193 @@
+use std::any::Any;
+
+use crate::memory::{AddressableIO, MemoryError};
+
+pub const REGISTER_RELOAD: usize = 0;
+pub const REGISTER_COUNTER: usize = 2;
+pub const REGISTER_CONTROL: usize = 4;
+pub const REGISTER_STATUS: usize = 5;
+
+pub const CONTROL_ENABLE: u8 = 0x01;
+pub const CONTROL_IRQ_ENABLE: u8 = 0x02;
+pub const CONTROL_ONE_SHOT: u8 = 0x04;
+
+pub const STATUS_EXPIRED: u8 = 0x80;
+
+/*
+ * Timer
+ * Programmable interval timer counting CPU cycles, so tests do not depend on
+ * the host speed. Its registers are:
+ *   0-1: reload value in cycles (little endian), 0 means 65536
+ *   2-3: cycles left before the timer expires (little endian, read only)
+ *   4:   control, bit 0 starts the timer, bit 1 enables the IRQ, bit 2
+ *        stops the timer once expired instead of reloading it
+ *   5:   status, bit 7 is set when the timer expires, writing any value
+ *        clears it
+ * Writing the control register with the enable bit set restarts the count.
+ * The IRQ line is held while the expired bit is set.
+ */
+#[derive(Debug, Default)]
+pub struct Timer {
+    reload: [u8; 2],
+    control: u8,
+    status: u8,
+    now: Option<u64>,
+    deadline: Option<u64>,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.control & CONTROL_ENABLE != 0
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.status & STATUS_EXPIRED != 0
+    }
+
+    fn period(&self) -> u64 {
+        match u16::from_le_bytes(self.reload) {
+            0 => 0x10000,
+            reload => reload as u64,
+        }
+    }
+
+    /*
+     * Cycles left before expiry, the whole period while the timer has not
+     * seen any cycle yet.
+     */
+    pub fn get_counter(&self) -> u64 {
+        match (self.deadline, self.now) {
+            (Some(deadline), Some(now)) if self.is_enabled() => deadline - now,
+            _ => self.period(),
+        }
+    }
+
+    fn register(&self, offset: usize) -> u8 {
+        match offset {
+            REGISTER_RELOAD => self.reload[0],
+            1 => self.reload[1],
+            REGISTER_COUNTER => self.get_counter() as u8,
+            3 => (self.get_counter() >> 8) as u8,
+            REGISTER_CONTROL => self.control,
+            _ => self.status,
+        }
+    }
+}
+
+impl AddressableIO for Timer {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok((addr..addr + len).map(|offset| self.register(offset)).collect())
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        for (offset, value) in (location..).zip(data.iter()) {
+            match offset {
+                REGISTER_RELOAD | 1 => self.reload[offset] = *value,
+                REGISTER_CONTROL => {
+                    self.control = *value;
+                    self.deadline = self.now.map(|now| now + self.period());
+                }
+                REGISTER_STATUS => self.status = 0x00,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        6
+    }
+
+    fn kind(&self) -> &str {
+        "timer"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn irq(&self) -> bool {
+        self.control & CONTROL_IRQ_ENABLE != 0 && self.is_expired()
+    }
+
+    fn tick(&mut self, cycle_count: u64) {
+        // the cycle counter was reset, the count starts over
+        if self.now.is_some_and(|now| cycle_count < now) {
+            self.deadline = None;
+        }
+        self.now = Some(cycle_count);
+        if !self.is_enabled() {
+            return;
+        }
+        let mut deadline = *self.deadline.get_or_insert(cycle_count + self.period());
+
+        while deadline <= cycle_count {
+            self.status |= STATUS_EXPIRED;
+            if self.control & CONTROL_ONE_SHOT != 0 {
+                self.control &= !CONTROL_ENABLE;
+                break;
+            }
+            deadline += self.period();
+        }
+        self.deadline = Some(deadline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periodic() {
+        let mut timer = Timer::new();
+        timer.write(REGISTER_RELOAD, &[0x00, 0x01]).unwrap();
+        timer.tick(1000);
+        timer.write_byte(REGISTER_CONTROL, CONTROL_ENABLE | CONTROL_IRQ_ENABLE).unwrap();
+        assert_eq!(vec![0x00, 0x01], timer.read(REGISTER_COUNTER, 2).unwrap());
+
+        timer.tick(1100);
+        assert_eq!(156, timer.get_counter());
+        assert!(!timer.irq());
+        timer.tick(1256);
+        assert!(timer.irq());
+        assert_eq!(STATUS_EXPIRED, timer.read_byte(REGISTER_STATUS).unwrap());
+        assert_eq!(256, timer.get_counter());
+
+        // clearing the status releases the IRQ line
+        timer.write_byte(REGISTER_STATUS, 0x00).unwrap();
+        assert!(!timer.irq());
+        // several periods in one instruction
+        timer.tick(1256 + 600);
+        assert!(timer.irq());
+        assert_eq!(168, timer.get_counter());
+    }
+
+    #[test]
+    fn test_one_shot() {
+        let mut timer = Timer::new();
+        timer.write(REGISTER_RELOAD, &[0x10, 0x00]).unwrap();
+        timer.write_byte(REGISTER_CONTROL, CONTROL_ENABLE | CONTROL_ONE_SHOT).unwrap();
+        // the count starts with the first cycle seen
+        timer.tick(50);
+        assert_eq!(16, timer.get_counter());
+        timer.tick(70);
+        assert!(timer.is_expired());
+        assert!(!timer.is_enabled());
+        // IRQ not enabled
+        assert!(!timer.irq());
+        timer.tick(200);
+        assert_eq!(STATUS_EXPIRED, timer.read_byte(REGISTER_STATUS).unwrap());
+    }
+}
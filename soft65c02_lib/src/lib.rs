@@ -1,5 +1,7 @@
 mod addressing_mode;
 mod cpu_instruction;
+pub mod devices;
+pub mod machines;
 pub mod memory;
 mod processing_unit;
 mod registers;
@@ -0,0 +1,308 @@
+use std::any::Any;
+use std::cell::Cell;
+
+use crate::devices::{AsciiKeyboard, Console};
+use crate::memory::{AddressableIO, MemoryError, MemoryStack, ROM};
+
+pub const KEYBOARD_ADDR: usize = 0xC000;
+pub const SOFT_SWITCHES_ADDR: usize = 0xC030;
+pub const CONSOLE_ADDR: usize = 0xC0F0;
+pub const MONITOR_ROM_ADDR: usize = 0xF800;
+
+pub const TEXT_PAGE1_ADDR: usize = 0x0400;
+pub const TEXT_PAGE2_ADDR: usize = 0x0800;
+pub const TEXT_COLUMNS: usize = 40;
+pub const TEXT_ROWS: usize = 24;
+
+/// Monitor entry points provided by the ROM stub.
+pub const HOME: usize = 0xFC58;
+pub const RDKEY: usize = 0xFD0C;
+pub const KEYIN: usize = 0xFD1B;
+pub const CROUT: usize = 0xFD8E;
+pub const PRBYTE: usize = 0xFDDA;
+pub const PRHEX: usize = 0xFDE3;
+pub const COUT: usize = 0xFDED;
+pub const COUT1: usize = 0xFDF0;
+pub const MONZ: usize = 0xFF69;
+
+const IRQ_HANDLER: usize = 0xFA40;
+
+const SWITCH_TEXT: u8 = 0x01;
+const SWITCH_MIXED: u8 = 0x02;
+const SWITCH_PAGE2: u8 = 0x04;
+const SWITCH_HIRES: u8 = 0x08;
+
+/*
+ * SoftSwitches
+ * Apple II soft switches from $C030 to $C05F, they are flipped by any access,
+ * read or write, and read as 0. $C030-$C03F toggles the speaker, $C050-$C057
+ * select text or graphics, mixed mode, page 2 and high resolution (even
+ * addresses clear the switch, odd addresses set it), $C058-$C05F drive the 4
+ * annunciators the same way.
+ */
+#[derive(Debug)]
+pub struct SoftSwitches {
+    switches: Cell<u8>,
+    annunciators: Cell<u8>,
+    speaker_clicks: Cell<u64>,
+}
+
+impl Default for SoftSwitches {
+    fn default() -> Self {
+        Self {
+            switches: Cell::new(SWITCH_TEXT),
+            annunciators: Cell::new(0x00),
+            speaker_clicks: Cell::new(0),
+        }
+    }
+}
+
+impl SoftSwitches {
+    pub fn is_text(&self) -> bool {
+        self.switches.get() & SWITCH_TEXT != 0
+    }
+
+    pub fn is_mixed(&self) -> bool {
+        self.switches.get() & SWITCH_MIXED != 0
+    }
+
+    pub fn is_page2(&self) -> bool {
+        self.switches.get() & SWITCH_PAGE2 != 0
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.switches.get() & SWITCH_HIRES != 0
+    }
+
+    /// State of the annunciators, bit n for annunciator n.
+    pub fn get_annunciators(&self) -> u8 {
+        self.annunciators.get()
+    }
+
+    pub fn get_speaker_clicks(&self) -> u64 {
+        self.speaker_clicks.get()
+    }
+
+    fn access(&self, offset: usize) {
+        let flip = |cell: &Cell<u8>, bit: u8| {
+            if offset & 1 == 0 {
+                cell.set(cell.get() & !bit);
+            } else {
+                cell.set(cell.get() | bit);
+            }
+        };
+        match offset {
+            0x00..=0x0F => self.speaker_clicks.set(self.speaker_clicks.get() + 1),
+            0x20..=0x27 => flip(&self.switches, 1 << ((offset - 0x20) / 2)),
+            0x28..=0x2F => flip(&self.annunciators, 1 << ((offset - 0x28) / 2)),
+            _ => (),
+        }
+    }
+}
+
+impl AddressableIO for SoftSwitches {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        let data = self.peek(addr, len)?;
+        (addr..addr + len).for_each(|offset| self.access(offset));
+
+        Ok(data)
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        (location..location + data.len()).for_each(|offset| self.access(offset));
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        0x30
+    }
+
+    fn peek(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+
+        Ok(vec![0x00; len])
+    }
+
+    fn kind(&self) -> &str {
+        "soft switches"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+/*
+ * Build the monitor ROM stub mapped from $F800. Only the usual entry points
+ * are implemented, COUT writes the character without its high bit to the
+ * console port instead of the screen. Every other byte is an RTS so calls to
+ * missing routines return at once. The reset vector points to a loop on MONZ
+ * which stops the run, the IRQ handler jumps through $03FE as on the real
+ * machine.
+ */
+pub fn monitor_rom() -> Vec<u8> {
+    let mut rom = vec![0x60; 0x800];
+    let mut put = |address: usize, code: &[u8]| {
+        let offset = address - MONITOR_ROM_ADDR;
+        rom[offset..offset + code.len()].copy_from_slice(code);
+    };
+    // blank the text page 1 with spaces, cursor home
+    put(HOME, &[
+        0xA9, 0xA0, 0xA2, 0x00, 0x9D, 0x00, 0x04, 0x9D, 0x00, 0x05, 0x9D, 0x00, 0x06, 0x9D, 0x00,
+        0x07, 0xE8, 0xD0, 0xF1, 0x86, 0x24, 0x86, 0x25, 0x60,
+    ]);
+    // wait for a key, acknowledge it and return it in A
+    put(RDKEY, &[0xAD, 0x00, 0xC0, 0x10, 0xFB, 0x2C, 0x10, 0xC0, 0x60]);
+    put(KEYIN, &[0xAD, 0x00, 0xC0, 0x10, 0xFB, 0x2C, 0x10, 0xC0, 0x60]);
+    put(CROUT, &[0xA9, 0x8D, 0x4C, 0xED, 0xFD]);
+    // PRBYTE, PRHEX and PRHEXZ fall through COUT as in the real ROM
+    put(PRBYTE, &[
+        0x48, 0x4A, 0x4A, 0x4A, 0x4A, 0x20, 0xE5, 0xFD, 0x68, 0x29, 0x0F, 0x09, 0xB0, 0xC9, 0xBA,
+        0x90, 0x02, 0x69, 0x06,
+    ]);
+    put(COUT, &[0x4C, 0xF0, 0xFD]);
+    put(COUT1, &[0x48, 0x29, 0x7F, 0x8D, 0xF0, 0xC0, 0x68, 0x60]);
+    put(IRQ_HANDLER, &[0x6C, 0xFE, 0x03]);
+    put(MONZ, &[0x4C, 0x69, 0xFF]);
+    put(0xFFFA, &[0x40, 0xFA, 0x69, 0xFF, 0x40, 0xFA]);
+
+    rom
+}
+
+/*
+ * Map the Apple II devices on the memory: keyboard at $C000 (strobe cleared
+ * at $C010), soft switches, the console port at $C0F0 and the monitor ROM
+ * stub. The text pages stay in RAM.
+ */
+pub fn install(memory: &mut MemoryStack) {
+    memory.add_subsystem("KEYBOARD", KEYBOARD_ADDR, AsciiKeyboard::new());
+    memory.add_subsystem("SOFT SWITCHES", SOFT_SWITCHES_ADDR, SoftSwitches::default());
+    memory.add_subsystem("CONSOLE", CONSOLE_ADDR, Console::new());
+    memory.add_subsystem("MONITOR ROM", MONITOR_ROM_ADDR, ROM::new(monitor_rom()));
+}
+
+/// Address of a row of the text screen, rows are interleaved by thirds.
+pub fn text_row_address(page2: bool, row: usize) -> usize {
+    let base = if page2 { TEXT_PAGE2_ADDR } else { TEXT_PAGE1_ADDR };
+
+    base + (row % 8) * 0x80 + (row / 8) * TEXT_COLUMNS
+}
+
+/// Character shown for a byte of the text page, inverse and flashing
+/// characters are shown as normal ones.
+pub fn screen_char(byte: u8) -> char {
+    let code = match byte {
+        0x00..=0x7F if byte & 0x3F < 0x20 => (byte & 0x3F) + 0x40,
+        0x00..=0x7F => byte & 0x3F,
+        _ if byte & 0x7F < 0x20 => (byte & 0x7F) + 0x40,
+        _ => byte & 0x7F,
+    };
+
+    code as char
+}
+
+/*
+ * Decode the text page shown by the soft switches (page 1 when there are
+ * none) into its 24 lines.
+ */
+pub fn text_screen(memory: &MemoryStack) -> Result<Vec<String>, MemoryError> {
+    let page2 = memory
+        .find_device::<SoftSwitches>()
+        .is_some_and(|switches| switches.is_page2());
+
+    (0..TEXT_ROWS)
+        .map(|row| {
+            let bytes = memory.peek(text_row_address(page2, row), TEXT_COLUMNS)?;
+            Ok(bytes.into_iter().map(screen_char).collect())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::Keyboard;
+    use crate::{execute_step, Registers};
+
+    #[test]
+    fn test_text_screen() {
+        assert_eq!(0x0400, text_row_address(false, 0));
+        assert_eq!(0x0480, text_row_address(false, 1));
+        assert_eq!(0x0428, text_row_address(false, 8));
+        assert_eq!(0x07D0, text_row_address(false, 23));
+        assert_eq!(0x0BD0, text_row_address(true, 23));
+
+        let mut memory = MemoryStack::new_with_ram();
+        install(&mut memory);
+        memory.write(0x0400, &[0xA0; 0x400]).unwrap();
+        // normal, inverse and flashing characters
+        memory.write(0x0480, &[0xC8, 0xC9, 0xA0, 0x01, 0x42, 0x21]).unwrap();
+        let screen = text_screen(&memory).unwrap();
+        assert_eq!(24, screen.len());
+        assert!(screen[1].starts_with("HI AB!  "));
+        assert_eq!(" ".repeat(40), screen[0]);
+
+        // reading $C055 shows page 2
+        memory.read_byte(0xC055).unwrap();
+        memory.write(0x0880, b"PAGE 2").unwrap();
+        assert!(text_screen(&memory).unwrap()[1].starts_with("PAGE 2"));
+    }
+
+    #[test]
+    fn test_screen_char() {
+        assert_eq!('@', screen_char(0x80));
+        assert_eq!('A', screen_char(0x81));
+        assert_eq!('_', screen_char(0x9F));
+        assert_eq!(' ', screen_char(0xA0));
+        assert_eq!('A', screen_char(0xC1));
+        assert_eq!('a', screen_char(0xE1));
+        // inverse and flashing
+        assert_eq!('A', screen_char(0x01));
+        assert_eq!('!', screen_char(0x61));
+    }
+
+    #[test]
+    fn test_soft_switches() {
+        let mut switches = SoftSwitches::default();
+        assert!(switches.is_text());
+        switches.read_byte(0x20).unwrap();
+        switches.write_byte(0x27, 0x00).unwrap();
+        switches.read_byte(0x2B).unwrap();
+        switches.read(0x00, 2).unwrap();
+        assert!(!switches.is_text());
+        assert!(switches.is_hires());
+        assert!(!switches.is_page2());
+        assert_eq!(0x02, switches.get_annunciators());
+        assert_eq!(2, switches.get_speaker_clicks());
+        // peeking has no side effect
+        switches.peek(0x21, 1).unwrap();
+        assert!(!switches.is_text());
+    }
+
+    #[test]
+    fn test_monitor_rom() {
+        let mut memory = MemoryStack::new_with_ram();
+        install(&mut memory);
+        // 1000: LDA #$C8; JSR COUT; LDA #$3F; JSR PRBYTE; JSR CROUT; JSR RDKEY; JMP MONZ
+        memory
+            .write(0x1000, &[
+                0xA9, 0xC8, 0x20, 0xED, 0xFD, 0xA9, 0x3F, 0x20, 0xDA, 0xFD, 0x20, 0x8E, 0xFD, 0x20,
+                0x0C, 0xFD, 0x4C, 0x69, 0xFF,
+            ])
+            .unwrap();
+        memory.find_device_mut::<AsciiKeyboard>().unwrap().type_keys(b"Y", 10);
+        let mut registers = Registers::new_initialized(0x1000);
+        while registers.command_pointer != MONZ {
+            execute_step(&mut registers, &mut memory).unwrap();
+        }
+
+        assert_eq!("H3F\n", memory.find_device::<Console>().unwrap().text());
+        assert_eq!(b'Y' | 0x80, registers.accumulator);
+    }
+}
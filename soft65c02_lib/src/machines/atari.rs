@@ -0,0 +1,310 @@
+use std::any::Any;
+use std::collections::VecDeque;
+
+use crate::devices::{Console, RegisterRecorder};
+use crate::memory::{little_endian, AddressableIO, DmaRequest, MemoryError, MemoryStack, ROM};
+
+pub const GTIA_ADDR: usize = 0xD000;
+pub const POKEY_ADDR: usize = 0xD200;
+pub const PIA_ADDR: usize = 0xD300;
+pub const ANTIC_ADDR: usize = 0xD400;
+pub const CIO_ADDR: usize = 0xD1F0;
+pub const CONSOLE_ADDR: usize = 0xD1F8;
+pub const OS_ROM_ADDR: usize = 0xE000;
+
+/// OS vectors provided by the ROM stub.
+pub const CIOV: usize = 0xE456;
+pub const SIOV: usize = 0xE459;
+pub const SETVBV: usize = 0xE45C;
+pub const SYSVBV: usize = 0xE45F;
+pub const XITVBV: usize = 0xE462;
+pub const WARMSV: usize = 0xE474;
+pub const COLDSV: usize = 0xE477;
+
+/// DOS vector, program run address and init address in RAM.
+pub const DOSVEC: usize = 0x000A;
+pub const RUNAD: usize = 0x02E0;
+pub const INITAD: usize = 0x02E2;
+pub const IOCB_ADDR: usize = 0x0340;
+pub const DCB_ADDR: usize = 0x0300;
+
+const CIO_STUB: usize = 0xE4A0;
+const SIO_STUB: usize = 0xE4B0;
+const XITVBV_STUB: usize = 0xE4C0;
+const RTI_STUB: usize = 0xE4C6;
+
+/// Atari end of line character.
+pub const EOL: u8 = 0x9B;
+
+pub const STATUS_SUCCESS: u8 = 1;
+pub const STATUS_INVALID_COMMAND: u8 = 132;
+pub const STATUS_EOF: u8 = 136;
+pub const STATUS_TIMEOUT: u8 = 138;
+
+const COMMAND_GET_RECORD: u8 = 0x05;
+const COMMAND_GET_CHARS: u8 = 0x07;
+const COMMAND_PUT_RECORD: u8 = 0x09;
+const COMMAND_PUT_CHARS: u8 = 0x0B;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CioStage {
+    Idle,
+    Iocb,
+    Buffer { command: u8 },
+}
+
+/*
+ * Cio
+ * Port the CIOV stub calls, it performs the call through DMA: it reads the
+ * IOCB given by X (written at offset 1, A being latched at offset 0 first),
+ * sends the characters put on any channel to the console port, then stores
+ * the status in the IOCB. Reading offset 1 returns the status of the last
+ * call. OPEN, CLOSE and special commands succeed, reads return an end of file.
+ */
+#[derive(Debug)]
+pub struct Cio {
+    accumulator: u8,
+    iocb: usize,
+    status: u8,
+    stage: CioStage,
+    requests: VecDeque<DmaRequest>,
+}
+
+impl Default for Cio {
+    fn default() -> Self {
+        Self {
+            accumulator: 0x00,
+            iocb: 0,
+            status: STATUS_SUCCESS,
+            stage: CioStage::Idle,
+            requests: VecDeque::new(),
+        }
+    }
+}
+
+impl Cio {
+    pub fn get_status(&self) -> u8 {
+        self.status
+    }
+
+    fn print(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            let byte = if *byte == EOL { b'\n' } else { *byte };
+            self.requests.push_back(DmaRequest::Write(CONSOLE_ADDR, vec![byte]));
+        }
+    }
+
+    fn finish(&mut self, status: u8) {
+        self.status = status;
+        self.stage = CioStage::Idle;
+        self.requests
+            .push_back(DmaRequest::Write(IOCB_ADDR + self.iocb + 3, vec![status]));
+    }
+
+    fn call(&mut self, iocb: &[u8]) {
+        let command = iocb[2];
+        let buffer = little_endian(iocb[4..6].to_vec());
+        let length = little_endian(iocb[8..10].to_vec());
+        match command {
+            COMMAND_PUT_RECORD | COMMAND_PUT_CHARS if length == 0 => {
+                self.print(&[self.accumulator]);
+                self.finish(STATUS_SUCCESS);
+            }
+            COMMAND_PUT_RECORD | COMMAND_PUT_CHARS => {
+                self.stage = CioStage::Buffer { command };
+                self.requests.push_back(DmaRequest::Read(buffer, length));
+            }
+            COMMAND_GET_RECORD | COMMAND_GET_CHARS => self.finish(STATUS_EOF),
+            0x00..=0x02 => self.finish(STATUS_INVALID_COMMAND),
+            _ => self.finish(STATUS_SUCCESS),
+        }
+    }
+}
+
+impl AddressableIO for Cio {
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        if addr + len > self.get_size() {
+            return Err(MemoryError::ReadOverflow(len, addr));
+        }
+        let registers = [self.accumulator, self.status];
+
+        Ok(registers[addr..addr + len].to_vec())
+    }
+
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if location + data.len() > self.get_size() {
+            return Err(MemoryError::WriteOverflow(data.len(), location));
+        }
+        for (offset, value) in (location..).zip(data.iter()) {
+            if offset == 0 {
+                self.accumulator = *value;
+            } else {
+                self.iocb = (*value & 0x70) as usize;
+                self.stage = CioStage::Iocb;
+                self.requests.push_back(DmaRequest::Read(IOCB_ADDR + self.iocb, 16));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> usize {
+        2
+    }
+
+    fn kind(&self) -> &str {
+        "CIO"
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+
+    fn take_dma_request(&mut self) -> Option<DmaRequest> {
+        self.requests.pop_front()
+    }
+
+    fn dma_complete(&mut self, result: Result<Vec<u8>, MemoryError>) {
+        let stage = self.stage;
+        match (stage, result) {
+            (CioStage::Idle, _) => (),
+            (CioStage::Iocb, Ok(iocb)) => self.call(&iocb),
+            (CioStage::Buffer { command }, Ok(data)) => {
+                let data = match data.iter().position(|byte| *byte == EOL) {
+                    Some(end) if command == COMMAND_PUT_RECORD => &data[..=end],
+                    _ => &data[..],
+                };
+                self.print(data);
+                self.finish(STATUS_SUCCESS);
+            }
+            (_, Err(_)) => {
+                self.requests.clear();
+                self.status = STATUS_INVALID_COMMAND;
+                self.stage = CioStage::Idle;
+            }
+        }
+    }
+}
+
+/*
+ * Build the OS ROM stub mapped from $E000. The vector table holds CIOV, which
+ * goes to the CIO port, SIOV, which answers a timeout as no peripheral is
+ * connected, SYSVBV and XITVBV, which leave the VBI, and WARMSV and COLDSV,
+ * which loop on themselves so the run stops there. Every other byte is an
+ * RTS, SETVBV included.
+ */
+pub fn os_rom() -> Vec<u8> {
+    let mut rom = vec![0x60; 0x2000];
+    let mut put = |address: usize, code: &[u8]| {
+        let offset = address - OS_ROM_ADDR;
+        rom[offset..offset + code.len()].copy_from_slice(code);
+    };
+    put(CIOV, &[0x4C, 0xA0, 0xE4]);
+    put(SIOV, &[0x4C, 0xB0, 0xE4]);
+    put(SYSVBV, &[0x4C, 0xC0, 0xE4]);
+    put(XITVBV, &[0x4C, 0xC0, 0xE4]);
+    put(WARMSV, &[0x4C, 0x74, 0xE4]);
+    put(COLDSV, &[0x4C, 0x77, 0xE4]);
+    // STA CIO; STX CIO+1; LDY CIO+1; RTS
+    put(CIO_STUB, &[0x8D, 0xF0, 0xD1, 0x8E, 0xF1, 0xD1, 0xAC, 0xF1, 0xD1, 0x60]);
+    // LDY #138; STY DSTATS; RTS
+    put(SIO_STUB, &[0xA0, STATUS_TIMEOUT, 0x8C, 0x03, 0x03, 0x60]);
+    // PLA; TAY; PLA; TAX; PLA; RTI
+    put(XITVBV_STUB, &[0x68, 0xA8, 0x68, 0xAA, 0x68, 0x40]);
+    put(RTI_STUB, &[0x40]);
+    put(0xFFFA, &[0xC6, 0xE4, 0x77, 0xE4, 0xC6, 0xE4]);
+
+    rom
+}
+
+/*
+ * Map the Atari 8-bit devices on the memory: GTIA, POKEY and PIA pages as
+ * recorders reading $FF until written, ANTIC as a recorder reading 0, the CIO
+ * and console ports and the OS ROM stub. DOSVEC points to WARMSV.
+ */
+pub fn install(memory: &mut MemoryStack) -> Result<(), MemoryError> {
+    memory.add_subsystem("GTIA", GTIA_ADDR, RegisterRecorder::new(0x100, 0xFF));
+    memory.add_subsystem("CIO", CIO_ADDR, Cio::default());
+    memory.add_subsystem("CONSOLE", CONSOLE_ADDR, Console::new());
+    memory.add_subsystem("POKEY", POKEY_ADDR, RegisterRecorder::new(0x100, 0xFF));
+    memory.add_subsystem("PIA", PIA_ADDR, RegisterRecorder::new(0x100, 0xFF));
+    memory.add_subsystem("ANTIC", ANTIC_ADDR, RegisterRecorder::new(0x100, 0x00));
+    memory.add_subsystem("OS ROM", OS_ROM_ADDR, ROM::new(os_rom()));
+
+    memory.write(DOSVEC, &[WARMSV as u8, (WARMSV >> 8) as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{execute_step, Registers};
+
+    fn run(memory: &mut MemoryStack, program: &[u8]) -> Registers {
+        memory.write(0x2000, program).unwrap();
+        let mut registers = Registers::new_initialized(0x2000);
+        while registers.command_pointer != WARMSV {
+            execute_step(&mut registers, memory).unwrap();
+        }
+
+        registers
+    }
+
+    #[test]
+    fn test_cio() {
+        let mut memory = MemoryStack::new_with_ram();
+        install(&mut memory).unwrap();
+        memory.write(0x3000, b"HELLO\x9bIGNORED").unwrap();
+        // IOCB 0: put record from $3000, 64 bytes at most
+        memory.write(0x0342, &[COMMAND_PUT_RECORD, 0x00, 0x00, 0x30]).unwrap();
+        memory.write(0x0348, &[0x40, 0x00]).unwrap();
+        // IOCB 1: put chars with a 0 length prints A
+        memory.write(0x0352, &[COMMAND_PUT_CHARS]).unwrap();
+        memory.write(0x0358, &[0x00, 0x00]).unwrap();
+        // LDX #0; JSR CIOV; LDA #'!'; LDX #$10; JSR CIOV; STY $80; JMP WARMSV
+        let registers = run(&mut memory, &[
+            0xA2, 0x00, 0x20, 0x56, 0xE4, 0xA9, 0x21, 0xA2, 0x10, 0x20, 0x56, 0xE4, 0x84, 0x80,
+            0x4C, 0x74, 0xE4,
+        ]);
+
+        assert_eq!("HELLO\n!", memory.find_device::<Console>().unwrap().text());
+        assert_eq!(STATUS_SUCCESS, memory.read_byte(0x80).unwrap());
+        assert_eq!(STATUS_SUCCESS, memory.read_byte(0x0353).unwrap());
+        assert_eq!(0x21, registers.accumulator);
+        assert_eq!(0x10, registers.register_x);
+    }
+
+    #[test]
+    fn test_cio_get_and_sio() {
+        let mut memory = MemoryStack::new_with_ram();
+        install(&mut memory).unwrap();
+        memory.write(0x0342, &[COMMAND_GET_RECORD]).unwrap();
+        // LDX #0; JSR CIOV; STY $80; JSR SIOV; STY $81; JMP WARMSV
+        let registers = run(&mut memory, &[
+            0xA2, 0x00, 0x20, 0x56, 0xE4, 0x84, 0x80, 0x20, 0x59, 0xE4, 0x84, 0x81, 0x4C, 0x74,
+            0xE4,
+        ]);
+
+        assert_eq!(STATUS_EOF, memory.read_byte(0x80).unwrap());
+        assert_eq!(STATUS_TIMEOUT, memory.read_byte(0x81).unwrap());
+        assert_eq!(STATUS_TIMEOUT, memory.read_byte(0x0303).unwrap());
+        assert!(registers.n_flag_is_set());
+    }
+
+    #[test]
+    fn test_hardware_recorders() {
+        let mut memory = MemoryStack::new_with_ram();
+        install(&mut memory).unwrap();
+        assert_eq!(vec![0x74, 0xE4], memory.read(DOSVEC, 2).unwrap());
+        // LDA #$94; STA COLBK; LDA CONSOL; STA WSYNC; JMP WARMSV
+        run(&mut memory, &[
+            0xA9, 0x94, 0x8D, 0x1A, 0xD0, 0xAD, 0x1F, 0xD0, 0x8D, 0x0A, 0xD4, 0x4C, 0x74, 0xE4,
+        ]);
+        let gtia = memory.find_named_device::<RegisterRecorder>("GTIA").unwrap();
+        let antic = memory.find_named_device::<RegisterRecorder>("ANTIC").unwrap();
+
+        assert_eq!(0x94, memory.read_byte(0xD01A).unwrap());
+        assert_eq!(Some(0x94), gtia.last_write(0x1A));
+        assert_eq!(1, gtia.get_register_log().len());
+        assert_eq!(Some(0xFF), antic.last_write(0x0A));
+    }
+}
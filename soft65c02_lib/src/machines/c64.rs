@@ -0,0 +1,238 @@
+use crate::devices::{AsciiKeyboard, Console};
+use crate::memory::{AddressableIO, MemoryError, MemoryStack, ROM};
+use crate::{CPUError, Registers};
+
+pub const KEYBOARD_ADDR: usize = 0xDE00;
+pub const CONSOLE_ADDR: usize = 0xDE20;
+pub const KERNAL_ROM_ADDR: usize = 0xE000;
+
+/// KERNAL entry points serviced by host calls.
+pub const CINT: usize = 0xFF81;
+pub const SETLFS: usize = 0xFFBA;
+pub const SETNAM: usize = 0xFFBD;
+pub const OPEN: usize = 0xFFC0;
+pub const CLOSE: usize = 0xFFC3;
+pub const CHKIN: usize = 0xFFC6;
+pub const CHKOUT: usize = 0xFFC9;
+pub const CLRCHN: usize = 0xFFCC;
+pub const CHRIN: usize = 0xFFCF;
+pub const CHROUT: usize = 0xFFD2;
+pub const RDTIM: usize = 0xFFDE;
+pub const STOP: usize = 0xFFE1;
+pub const GETIN: usize = 0xFFE4;
+pub const CLALL: usize = 0xFFE7;
+
+/// BASIC warm start, the ROM stub loops there.
+pub const READY: usize = 0xE37B;
+
+/// Keyboard buffer and its number of keys in RAM.
+pub const KEYD: usize = 0x0277;
+pub const NDX: usize = 0x00C6;
+/// IRQ vector in RAM the interrupt handler jumps through.
+pub const CINV: usize = 0x0314;
+
+/// PETSCII carriage return.
+pub const RETURN: u8 = 0x0D;
+
+/// CPU cycles of a jiffy, the clock read by RDTIM ticks 60 times a second.
+pub const CYCLES_PER_JIFFY: u64 = 17045;
+
+const IRQ_HANDLER: usize = 0xFF48;
+const IRQ_EXIT: usize = 0xEA81;
+const NMI_HANDLER: usize = 0xFE43;
+
+/*
+ * Take a key from the keyboard buffer in RAM as the KERNAL does, then from
+ * the keyboard device. Typed keys are turned into the unshifted PETSCII
+ * letters.
+ */
+fn next_key(memory: &mut MemoryStack) -> Result<Option<u8>, MemoryError> {
+    let count = memory.read_byte(NDX)? as usize;
+    if count > 0 {
+        let buffer = memory.read(KEYD, count)?;
+        memory.write(KEYD, &buffer[1..])?;
+        memory.write_byte(NDX, (count - 1) as u8)?;
+
+        return Ok(Some(buffer[0]));
+    }
+    let key = memory.read_byte(KEYBOARD_ADDR)?;
+    if key & 0x80 == 0 {
+        return Ok(None);
+    }
+    memory.read_byte(KEYBOARD_ADDR + 0x10)?;
+
+    Ok(Some((key & 0x7F).to_ascii_uppercase()))
+}
+
+fn return_byte(registers: &mut Registers, value: u8) {
+    registers.accumulator = value;
+    registers.set_z_flag(value == 0);
+    registers.set_n_flag(value & 0x80 != 0);
+    registers.set_c_flag(false);
+}
+
+fn chrout(registers: &mut Registers, memory: &mut MemoryStack) -> Result<(), CPUError> {
+    memory.write_byte(CONSOLE_ADDR, registers.accumulator)?;
+    registers.set_c_flag(false);
+
+    Ok(())
+}
+
+fn chrin(registers: &mut Registers, memory: &mut MemoryStack) -> Result<(), CPUError> {
+    let key = next_key(memory)?.unwrap_or(RETURN);
+    return_byte(registers, key);
+
+    Ok(())
+}
+
+fn getin(registers: &mut Registers, memory: &mut MemoryStack) -> Result<(), CPUError> {
+    let key = next_key(memory)?.unwrap_or(0x00);
+    return_byte(registers, key);
+
+    Ok(())
+}
+
+fn rdtim(registers: &mut Registers, _memory: &mut MemoryStack) -> Result<(), CPUError> {
+    let jiffies = (registers.cycle_count / CYCLES_PER_JIFFY).to_le_bytes();
+    registers.accumulator = jiffies[0];
+    registers.register_x = jiffies[1];
+    registers.register_y = jiffies[2];
+
+    Ok(())
+}
+
+fn stop(registers: &mut Registers, _memory: &mut MemoryStack) -> Result<(), CPUError> {
+    // the STOP key is never pressed
+    registers.set_z_flag(false);
+    registers.set_c_flag(false);
+
+    Ok(())
+}
+
+fn success(registers: &mut Registers, _memory: &mut MemoryStack) -> Result<(), CPUError> {
+    registers.set_c_flag(false);
+
+    Ok(())
+}
+
+pub type KernalRoutine = fn(&mut Registers, &mut MemoryStack) -> Result<(), CPUError>;
+
+/*
+ * Host calls installed on the KERNAL jump table. CHROUT prints the PETSCII
+ * character in A on the console port. GETIN returns the next key in A or 0
+ * when none is waiting, CHRIN returns a carriage return instead. RDTIM
+ * returns the jiffy clock deduced from the cycle count. STOP tells the STOP
+ * key is not pressed. The channel and file routines succeed doing nothing.
+ * Every routine but RDTIM returns with the carry clear.
+ */
+pub fn kernal_routines() -> Vec<(usize, KernalRoutine)> {
+    vec![
+        (CINT, success as KernalRoutine),
+        (SETLFS, success),
+        (SETNAM, success),
+        (OPEN, success),
+        (CLOSE, success),
+        (CHKIN, success),
+        (CHKOUT, success),
+        (CLRCHN, success),
+        (CHRIN, chrin),
+        (CHROUT, chrout),
+        (RDTIM, rdtim),
+        (STOP, stop),
+        (GETIN, getin),
+        (CLALL, success),
+    ]
+}
+
+/*
+ * Build the KERNAL ROM stub mapped from $E000. The routines of the jump table
+ * are host calls, the stub holds an RTS everywhere but READY, which loops on
+ * itself so a program jumping back to BASIC ends the run, and the interrupt
+ * handlers. The reset vector points to READY, the IRQ handler saves the
+ * registers and jumps through CINV which points to the exit restoring them.
+ */
+pub fn kernal_rom() -> Vec<u8> {
+    let mut rom = vec![0x60; 0x2000];
+    let mut put = |address: usize, code: &[u8]| {
+        let offset = address - KERNAL_ROM_ADDR;
+        rom[offset..offset + code.len()].copy_from_slice(code);
+    };
+    put(READY, &[0x4C, 0x7B, 0xE3]);
+    put(IRQ_EXIT, &[0x68, 0xA8, 0x68, 0xAA, 0x68, 0x40]);
+    put(NMI_HANDLER, &[0x40]);
+    put(IRQ_HANDLER, &[0x48, 0x8A, 0x48, 0x98, 0x48, 0x6C, 0x14, 0x03]);
+    put(0xFFFA, &[0x43, 0xFE, 0x7B, 0xE3, 0x48, 0xFF]);
+
+    rom
+}
+
+/*
+ * Map the C64 profile on the memory: an ASCII keyboard and the console port
+ * in the I/O expansion area and the KERNAL ROM stub, then install the KERNAL
+ * routines as host calls. The rest, BASIC ROM included, stays in RAM.
+ */
+pub fn install(memory: &mut MemoryStack) -> Result<(), MemoryError> {
+    memory.add_subsystem("KEYBOARD", KEYBOARD_ADDR, AsciiKeyboard::new());
+    memory.add_subsystem("CONSOLE", CONSOLE_ADDR, Console::new());
+    memory.add_subsystem("KERNAL ROM", KERNAL_ROM_ADDR, ROM::new(kernal_rom()));
+    for (address, routine) in kernal_routines() {
+        memory.add_host_call(address, routine);
+    }
+    memory.write(CINV, &[IRQ_EXIT as u8, (IRQ_EXIT >> 8) as u8])?;
+    memory.write_byte(NDX, 0x00)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::Keyboard;
+    use crate::execute_step;
+
+    #[test]
+    fn test_kernal_routines() {
+        let mut memory = MemoryStack::new_with_ram();
+        install(&mut memory).unwrap();
+        // a key waits in the keyboard buffer, another one is typed
+        memory.write(KEYD, &[0x51]).unwrap();
+        memory.write_byte(NDX, 0x01).unwrap();
+        memory.find_device_mut::<AsciiKeyboard>().unwrap().type_keys(b"y", 10);
+        // 1000: LDX #$00; LDA $1030,X; BEQ +6; JSR CHROUT; INX; BNE -11
+        // 100D: JSR GETIN; STA $80; JSR GETIN; BEQ -5; STA $81; JSR GETIN; STA $82; JMP READY
+        memory
+            .write(0x1000, &[
+                0xA2, 0x00, 0xBD, 0x30, 0x10, 0xF0, 0x06, 0x20, 0xD2, 0xFF, 0xE8, 0xD0, 0xF5, 0x20,
+                0xE4, 0xFF, 0x85, 0x80, 0x20, 0xE4, 0xFF, 0xF0, 0xFB, 0x85, 0x81, 0x20, 0xE4, 0xFF,
+                0x85, 0x82, 0x4C, 0x7B, 0xE3,
+            ])
+            .unwrap();
+        memory.write(0x1030, b"HI\r\x00").unwrap();
+        let mut registers = Registers::new_initialized(0x1000);
+        while registers.command_pointer != READY {
+            execute_step(&mut registers, &mut memory).unwrap();
+        }
+
+        assert_eq!("HI\n", memory.find_device::<Console>().unwrap().text());
+        assert_eq!(vec![0x51, 0x59, 0x00], memory.read(0x80, 3).unwrap());
+        assert_eq!(0x00, memory.read_byte(NDX).unwrap());
+        assert_eq!(0xFF, registers.stack_pointer);
+    }
+
+    #[test]
+    fn test_rdtim() {
+        let mut memory = MemoryStack::new_with_ram();
+        install(&mut memory).unwrap();
+        // 1000: JSR $1010, 1010: JMP RDTIM
+        memory.write(0x1000, &[0x20, 0x10, 0x10]).unwrap();
+        memory.write(0x1010, &[0x4C, 0xDE, 0xFF]).unwrap();
+        let mut registers = Registers::new_initialized(0x1000);
+        registers.cycle_count = CYCLES_PER_JIFFY * 0x010203;
+        for _ in 0..3 {
+            execute_step(&mut registers, &mut memory).unwrap();
+        }
+
+        assert_eq!((0x03, 0x02, 0x01), (registers.accumulator, registers.register_x, registers.register_y));
+        assert_eq!(0x1003, registers.command_pointer);
+    }
+}
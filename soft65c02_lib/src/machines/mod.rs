@@ -0,0 +1,8 @@
+/*
+ * Machines
+ * Profiles mapping the devices and ROM stubs of a given computer on the
+ * memory, so programs written for it can run headlessly.
+ */
+pub mod apple2;
+pub mod atari;
+pub mod c64;
@@ -2,28 +2,201 @@ use std::error;
 use std::fmt;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum MemoryAccess {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub enum MemoryError {
-    ReadOverflow(usize, usize),  // read len, address
-    WriteOverflow(usize, usize), // write len, address
-    Other(usize, &'static str),  // address, error message
+    ReadOverflow(usize, usize),              // read len, address
+    WriteOverflow(usize, usize),             // write len, address
+    Unmapped(MemoryAccess, usize, usize),    // access, len, address
+    WriteProtected(usize, usize),            // write len, address
+    Device(usize, String),                   // address, error message
+    Other(usize, &'static str),              // address, error message
+    Context {
+        error: Box<MemoryError>,
+        subsystem: Option<String>,
+        instruction: Option<(usize, String)>, // command pointer, instruction
+    },
+}
+
+/*
+ * Errors raised by a memory subsystem only know about addresses relative to
+ * this subsystem. While bubbling up, they are decorated with the name of the
+ * subsystem and the instruction that triggered them so a failure can be
+ * reported like "STA $C000 at #0x1234 wrote 1 byte(s) to read-only memory at
+ * #0xC000 in subsystem 'BASIC'".
+ */
+impl MemoryError {
+    /*
+     * Attach the subsystem's name to the error and make its address absolute.
+     * When the error already comes from a nested subsystem, the innermost name
+     * is kept.
+     */
+    pub fn in_subsystem(self, name: &str, start_address: usize) -> MemoryError {
+        match self.rebase(start_address) {
+            MemoryError::Context {
+                error,
+                subsystem,
+                instruction,
+            } => MemoryError::Context {
+                error,
+                subsystem: subsystem.or_else(|| Some(name.to_owned())),
+                instruction,
+            },
+            error => MemoryError::Context {
+                error: Box::new(error),
+                subsystem: Some(name.to_owned()),
+                instruction: None,
+            },
+        }
+    }
+
+    /*
+     * Attach the instruction being executed and its address to the error.
+     */
+    pub fn at_instruction(self, command_pointer: usize, instruction: &str) -> MemoryError {
+        let instruction = Some((command_pointer, instruction.to_owned()));
+
+        match self {
+            MemoryError::Context {
+                error, subsystem, ..
+            } => MemoryError::Context {
+                error,
+                subsystem,
+                instruction,
+            },
+            error => MemoryError::Context {
+                error: Box::new(error),
+                subsystem: None,
+                instruction,
+            },
+        }
+    }
+
+    /*
+     * The error stripped from its context.
+     */
+    pub fn cause(&self) -> &MemoryError {
+        match self {
+            MemoryError::Context { error, .. } => error.cause(),
+            error => error,
+        }
+    }
+
+    pub fn address(&self) -> usize {
+        match self.cause() {
+            MemoryError::ReadOverflow(_, addr)
+            | MemoryError::WriteOverflow(_, addr)
+            | MemoryError::Unmapped(_, _, addr)
+            | MemoryError::WriteProtected(_, addr)
+            | MemoryError::Device(addr, _)
+            | MemoryError::Other(addr, _) => *addr,
+            MemoryError::Context { .. } => unreachable!("the cause of an error has no context"),
+        }
+    }
+
+    pub fn subsystem(&self) -> Option<&str> {
+        match self {
+            MemoryError::Context { subsystem, .. } => subsystem.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn command_pointer(&self) -> Option<usize> {
+        match self {
+            MemoryError::Context {
+                instruction: Some((cp, _)),
+                ..
+            } => Some(*cp),
+            _ => None,
+        }
+    }
+
+    fn rebase(self, offset: usize) -> MemoryError {
+        self.relocate(&|addr| addr + offset)
+    }
+
+    /*
+     * Translate the address of the error, used by the mirrored and masked
+     * subsystems to give back the address accessed before it was decoded.
+     */
+    pub(crate) fn relocate(self, relocate: &impl Fn(usize) -> usize) -> MemoryError {
+        match self {
+            MemoryError::ReadOverflow(len, addr) => MemoryError::ReadOverflow(len, relocate(addr)),
+            MemoryError::WriteOverflow(len, addr) => MemoryError::WriteOverflow(len, relocate(addr)),
+            MemoryError::Unmapped(access, len, addr) => {
+                MemoryError::Unmapped(access, len, relocate(addr))
+            }
+            MemoryError::WriteProtected(len, addr) => {
+                MemoryError::WriteProtected(len, relocate(addr))
+            }
+            MemoryError::Device(addr, msg) => MemoryError::Device(relocate(addr), msg),
+            MemoryError::Other(addr, msg) => MemoryError::Other(relocate(addr), msg),
+            MemoryError::Context {
+                error,
+                subsystem,
+                instruction,
+            } => MemoryError::Context {
+                error: Box::new(error.relocate(relocate)),
+                subsystem,
+                instruction,
+            },
+        }
+    }
 }
 
 impl fmt::Display for MemoryError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
             MemoryError::ReadOverflow(read_len, addr) => write!(
                 f,
                 "Could not READ {} bytes at address 0x{:04X}",
                 read_len, addr
             ),
-            MemoryError::WriteOverflow(read_len, addr) => write!(
+            MemoryError::WriteOverflow(write_len, addr) => write!(
                 f,
                 "Could not WRITE {} bytes at address 0x{:04X}",
-                read_len, addr
+                write_len, addr
             ),
+            MemoryError::Unmapped(MemoryAccess::Read, len, addr) => write!(
+                f,
+                "read {} byte(s) of unmapped memory at #0x{:04X}",
+                len, addr
+            ),
+            MemoryError::Unmapped(MemoryAccess::Write, len, addr) => write!(
+                f,
+                "wrote {} byte(s) to unmapped memory at #0x{:04X}",
+                len, addr
+            ),
+            MemoryError::WriteProtected(len, addr) => write!(
+                f,
+                "wrote {} byte(s) to read-only memory at #0x{:04X}",
+                len, addr
+            ),
+            MemoryError::Device(addr, err_msg) => {
+                write!(f, "device failure at #0x{:04X}: {}", addr, err_msg)
+            }
             MemoryError::Other(addr, err_msg) => {
                 write!(f, "Memory error @{:04X} with message: {}", addr, err_msg)
             }
+            MemoryError::Context {
+                error,
+                subsystem,
+                instruction,
+            } => {
+                if let Some((cp, instruction)) = instruction {
+                    write!(f, "{} at #0x{:04X} ", instruction, cp)?;
+                }
+                write!(f, "{}", error)?;
+                if let Some(name) = subsystem {
+                    write!(f, " in subsystem '{}'", name)?;
+                }
+
+                Ok(())
+            }
         }
     }
 }
@@ -33,3 +206,52 @@ impl error::Error for MemoryError {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_subsystem() {
+        let error = MemoryError::WriteProtected(1, 0x0010).in_subsystem("BASIC", 0xC000);
+
+        assert_eq!(MemoryError::WriteProtected(1, 0xC010), *error.cause());
+        assert_eq!(0xC010, error.address());
+        assert_eq!(Some("BASIC"), error.subsystem());
+        assert_eq!(None, error.command_pointer());
+        assert_eq!(
+            "wrote 1 byte(s) to read-only memory at #0xC010 in subsystem 'BASIC'",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_nested_subsystems() {
+        let error = MemoryError::Device(0x0002, "not ready".to_owned())
+            .in_subsystem("VIA", 0x0100)
+            .in_subsystem("IO", 0x8000);
+
+        assert_eq!(0x8102, error.address());
+        assert_eq!(Some("VIA"), error.subsystem());
+    }
+
+    #[test]
+    fn test_at_instruction() {
+        let error = MemoryError::WriteProtected(1, 0x0000)
+            .in_subsystem("BASIC", 0xC000)
+            .at_instruction(0x1234, "STA $C000");
+
+        assert_eq!(Some(0x1234), error.command_pointer());
+        assert_eq!(Some("BASIC"), error.subsystem());
+        assert_eq!(
+            "STA $C000 at #0x1234 wrote 1 byte(s) to read-only memory at #0xC000 in subsystem 'BASIC'",
+            error.to_string()
+        );
+
+        let error = MemoryError::Unmapped(MemoryAccess::Read, 2, 0x4000).at_instruction(0x0800, "LDA ($20)");
+        assert_eq!(
+            "LDA ($20) at #0x0800 read 2 byte(s) of unmapped memory at #0x4000",
+            error.to_string()
+        );
+    }
+}
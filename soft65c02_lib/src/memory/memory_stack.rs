@@ -1,12 +1,54 @@
 use super::*;
+use crate::processing_unit::{CPUError, HostCall, HostCallRecord};
+use crate::registers::Registers;
 use range_map::Range;
+use std::cell::{Cell, RefCell};
 use std::cmp;
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 
+/*
+ * AddressDecoder
+ * Real boards often decode only part of the address lines, an I/O chip then
+ * appears several times in the window it is wired to. The decoder turns an
+ * offset in the subsystem's window into an address inside the subsystem.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressDecoder {
+    /// Every address of the window maps to its own byte in the subsystem.
+    Full,
+    /// The subsystem is repeated every N bytes over the window.
+    Mirror(usize),
+    /// Only the address lines set in the mask are decoded.
+    Mask(usize),
+}
+
+impl AddressDecoder {
+    pub fn decode(&self, offset: usize) -> usize {
+        match *self {
+            Self::Full => offset,
+            Self::Mirror(period) => offset % period,
+            Self::Mask(mask) => offset & mask,
+        }
+    }
+
+    /*
+     * Return how many bytes starting at the given offset are mapped to
+     * consecutive addresses in the subsystem.
+     */
+    fn contiguous_len(&self, offset: usize, len: usize) -> usize {
+        match *self {
+            Self::Full => len,
+            Self::Mirror(period) => cmp::min(period - offset % period, len),
+            Self::Mask(_) => cmp::min(1, len),
+        }
+    }
+}
+
 struct Subsystem {
     subsystem: Box<dyn AddressableIO>,
     address_range: Range<usize>,
+    decoder: AddressDecoder,
     name: String,
 }
 
@@ -18,33 +60,159 @@ impl Subsystem {
     ) -> Subsystem {
         let sub_len = subsystem.get_size();
 
+        Subsystem::new_decoded(name, start_address, sub_len, AddressDecoder::Full, subsystem)
+    }
+
+    pub fn new_decoded(
+        name: &str,
+        start_address: usize,
+        window_size: usize,
+        decoder: AddressDecoder,
+        subsystem: impl AddressableIO + 'static,
+    ) -> Subsystem {
         Subsystem {
             name: name.to_owned(),
             subsystem: Box::new(subsystem),
             address_range: Range {
                 start: start_address,
-                end: start_address + sub_len,
+                end: start_address + window_size,
             },
+            decoder,
         }
     }
 
     pub fn contains(&self, addr: usize) -> bool {
         self.address_range.contains(addr)
     }
+
+    fn read_decoded(
+        &self,
+        addr: usize,
+        len: usize,
+        read: impl Fn(usize, usize) -> Result<Vec<u8>, MemoryError>,
+    ) -> Result<Vec<u8>, MemoryError> {
+        if self.decoder == AddressDecoder::Full {
+            return read(addr, len);
+        }
+        let mut results: Vec<u8> = Vec::with_capacity(len);
+        let mut offset = addr;
+
+        while offset < addr + len {
+            let chunk_len = self.decoder.contiguous_len(offset, addr + len - offset);
+            let mut chunk = read(self.decoder.decode(offset), chunk_len)
+                .map_err(|e| self.undecode(e, offset))?;
+            results.append(&mut chunk);
+            offset += chunk_len;
+        }
+
+        Ok(results)
+    }
+
+    fn info(&self) -> SubsystemInfo {
+        SubsystemInfo {
+            name: self.name.clone(),
+            start: self.address_range.start,
+            end: self.address_range.end - 1,
+            kind: self.kind().to_owned(),
+            decoder: self.decoder,
+            read_only: self.is_read_only(),
+        }
+    }
+
+    /*
+     * Give back the address in the window of an error raised by the underlying
+     * memory at the decoded address of `location`, the chunks read or written
+     * at once being contiguous in both.
+     */
+    fn undecode(&self, error: MemoryError, location: usize) -> MemoryError {
+        let decoded = self.decoder.decode(location);
+
+        error.relocate(&|addr| addr + location - decoded)
+    }
+
+    /*
+     * Decorate an error raised by the underlying memory with the name of the
+     * subsystem and the absolute address.
+     */
+    fn context(&self, error: MemoryError) -> MemoryError {
+        error.in_subsystem(&self.name, self.address_range.start)
+    }
 }
 
+/*
+ * Addresses given to a Subsystem are relative to the start of its window,
+ * they are decoded before being handed to the underlying memory.
+ */
 impl AddressableIO for Subsystem {
     fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
-        self.subsystem.read(addr, len)
+        self.read_decoded(addr, len, |addr, len| self.subsystem.read(addr, len))
     }
 
     fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
-        self.subsystem.write(location, data)
+        if self.decoder == AddressDecoder::Full {
+            return self.subsystem.write(location, data);
+        }
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let chunk_len = self
+                .decoder
+                .contiguous_len(location + offset, data.len() - offset);
+            self.subsystem
+                .write(
+                    self.decoder.decode(location + offset),
+                    &data[offset..offset + chunk_len],
+                )
+                .map_err(|e| self.undecode(e, location + offset))?;
+            offset += chunk_len;
+        }
+
+        Ok(())
     }
 
     fn get_size(&self) -> usize {
         self.subsystem.get_size()
     }
+
+    fn read_byte(&self, addr: usize) -> Result<u8, MemoryError> {
+        self.subsystem
+            .read_byte(self.decoder.decode(addr))
+            .map_err(|e| self.undecode(e, addr))
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.subsystem.is_read_only()
+    }
+
+    fn peek(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        self.read_decoded(addr, len, |addr, len| self.subsystem.peek(addr, len))
+    }
+
+    fn kind(&self) -> &str {
+        self.subsystem.kind()
+    }
+
+    fn irq(&self) -> bool {
+        self.subsystem.irq()
+    }
+
+    fn tick(&mut self, cycle_count: u64) {
+        self.subsystem.tick(cycle_count)
+    }
+
+    fn take_dma_request(&mut self) -> Option<DmaRequest> {
+        self.subsystem.take_dma_request()
+    }
+
+    fn dma_complete(&mut self, result: Result<Vec<u8>, MemoryError>) {
+        self.subsystem.dma_complete(result)
+    }
+
+    fn write_byte(&mut self, location: usize, value: u8) -> Result<(), MemoryError> {
+        self.subsystem
+            .write_byte(self.decoder.decode(location), value)
+            .map_err(|e| self.undecode(e, location))
+    }
 }
 
 impl fmt::Debug for Subsystem {
@@ -56,14 +224,107 @@ impl fmt::Debug for Subsystem {
             self.address_range.start,
             self.address_range.end - 1,
             self.get_size()
-        )
+        )?;
+
+        match self.decoder {
+            AddressDecoder::Full => Ok(()),
+            AddressDecoder::Mirror(period) => write!(f, ", mirrored every {} bytes", period),
+            AddressDecoder::Mask(mask) => write!(f, ", address mask = #0x{:04X}", mask),
+        }
     }
 }
 
+/*
+ * SubsystemInfo
+ * Description of a subsystem as it was added to the memory stack, addresses
+ * are inclusive.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsystemInfo {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+    pub kind: String,
+    pub decoder: AddressDecoder,
+    pub read_only: bool,
+}
+
+/*
+ * MemoryRegion
+ * A range of addresses of the effective memory map, it is answered by the
+ * subsystem of the given index in the stack or by nothing. Other subsystems
+ * mapped in this range are shadowed. Addresses are inclusive.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub start: usize,
+    pub end: usize,
+    pub subsystem: Option<usize>,
+    pub shadowed: Vec<usize>,
+}
+
+const PAGE_SIZE: usize = 0x100;
+const PAGE_COUNT: usize = (MEMMAX + 1) / PAGE_SIZE;
+
+/*
+ * PageEntry
+ * A page entirely covered by a single subsystem is dispatched without walking
+ * the address map: the entry holds the subsystem index and the offset of the
+ * page in the subsystem's window.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PageEntry {
+    sub_index: usize,
+    offset: usize,
+}
+
+/*
+ * UnmappedPolicy
+ * What happens when the CPU accesses an address no subsystem answers to. On
+ * real hardware, nothing drives the data bus and reads return whatever value
+ * was last present on it.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnmappedPolicy {
+    /// Accessing unallocated memory is an error.
+    #[default]
+    Error,
+    /// Reads return the last value seen on the data bus, writes are ignored.
+    OpenBus,
+    /// Reads return the given byte, writes are ignored.
+    Fixed(u8),
+    /// Same as OpenBus but every access is reported as a warning.
+    Warn,
+}
+
+/*
+ * ReadOnlyPolicy
+ * What happens when the CPU writes to a read-only subsystem. Some machines
+ * write to ROM space on purpose to trigger external latches.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadOnlyPolicy {
+    /// The subsystem decides, a ROM returns an error.
+    #[default]
+    Error,
+    /// The write is silently ignored.
+    Ignore,
+    /// The write is ignored and reported as a warning.
+    Warn,
+}
+
 #[derive(Debug, Default)]
 pub struct MemoryStack {
     stack: Vec<Subsystem>,
+    devices: Vec<usize>, // subsystems other than RAM and ROM, ticked and polled for IRQ after each instruction
     address_map: BTreeMap<usize, usize>,
+    pages: Vec<Option<PageEntry>>,
+    unmapped_policy: UnmappedPolicy,
+    read_only_policy: ReadOnlyPolicy,
+    data_bus: Cell<u8>,
+    warnings: RefCell<Vec<String>>,
+    host_calls: BTreeMap<usize, HostCall>,
+    host_call_records: BTreeMap<usize, Vec<HostCallRecord>>,
 }
 
 impl MemoryStack {
@@ -74,27 +335,75 @@ impl MemoryStack {
         memory_stack
     }
 
-    /*
-     * The idea here is to ease the read & write operations.
-     * An address_map is created to present only visible portions of subsystems' address range.
-     * Once this is done, the only thing to do to read or write is to split the reads accross the
-     * different subsystems.
-     */
     pub fn add_subsystem(
         &mut self,
         name: &str,
         start_address: usize,
         memory: impl AddressableIO + 'static,
     ) {
-        let end_address = start_address + memory.get_size();
         let sub = Subsystem::new(name, start_address, memory);
+        self.push_subsystem(sub);
+    }
+
+    /*
+     * Map a subsystem over a window larger than itself, the subsystem is
+     * repeated every `get_size()` bytes until the end of the window.
+     */
+    pub fn add_mirrored_subsystem(
+        &mut self,
+        name: &str,
+        start_address: usize,
+        window_size: usize,
+        memory: impl AddressableIO + 'static,
+    ) {
+        let period = memory.get_size();
+        assert!(period > 0, "cannot mirror subsystem '{}' of size 0", name);
+        let sub = Subsystem::new_decoded(
+            name,
+            start_address,
+            window_size,
+            AddressDecoder::Mirror(period),
+            memory,
+        );
+        self.push_subsystem(sub);
+    }
+
+    /*
+     * Map a subsystem over a window where only the address lines present in
+     * the mask are decoded. The mask applies to the offset in the window.
+     */
+    pub fn add_masked_subsystem(
+        &mut self,
+        name: &str,
+        start_address: usize,
+        window_size: usize,
+        mask: usize,
+        memory: impl AddressableIO + 'static,
+    ) {
+        let sub = Subsystem::new_decoded(
+            name,
+            start_address,
+            window_size,
+            AddressDecoder::Mask(mask),
+            memory,
+        );
+        self.push_subsystem(sub);
+    }
+
+    /*
+     * The idea here is to ease the read & write operations.
+     * An address_map is created to present only visible portions of subsystems' address range.
+     * Once this is done, the only thing to do to read or write is to split the reads accross the
+     * different subsystems.
+     */
+    fn push_subsystem(&mut self, sub: Subsystem) {
+        let start_address = sub.address_range.start;
+        let end_address = sub.address_range.end;
         let mut address_map: BTreeMap<usize, usize> = BTreeMap::new();
         address_map.insert(end_address, self.stack.len());
-        let mut keys: Vec<usize> = vec![];
-        self.address_map.keys().for_each(|x| keys.push(*x));
 
         if start_address != 0 {
-            for (sub_index, sub) in self.stack.iter().enumerate() {
+            for (sub_index, sub) in self.stack.iter().enumerate().rev() {
                 if sub.contains(start_address - 1) {
                     address_map.insert(start_address, sub_index);
                     break;
@@ -108,7 +417,381 @@ impl MemoryStack {
             }
         }
         self.address_map = address_map;
+        if !matches!(sub.kind(), "RAM" | "ROM") {
+            self.devices.push(self.stack.len());
+        }
         self.stack.push(sub);
+        self.update_pages();
+    }
+
+    /*
+     * Pages shared by several subsystems or containing unallocated memory have
+     * no entry, accesses to them go through the address map.
+     */
+    fn update_pages(&mut self) {
+        self.pages = (0..PAGE_COUNT)
+            .map(|page| {
+                let page_start = page * PAGE_SIZE;
+                let (&addr_split, &sub_index) =
+                    self.address_map.range(page_start + 1..).next()?;
+                let substart = self.stack[sub_index].address_range.start;
+
+                if substart <= page_start && addr_split >= page_start + PAGE_SIZE {
+                    Some(PageEntry {
+                        sub_index,
+                        offset: page_start - substart,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    /*
+     * Return the subsystem index and the address in its window when the whole
+     * [addr, addr + len) range lies in a page owned by a single subsystem.
+     */
+    fn lookup_page(&self, addr: usize, len: usize) -> Option<(usize, usize)> {
+        let in_page = addr % PAGE_SIZE;
+        if in_page + len > PAGE_SIZE {
+            return None;
+        }
+        let entry = (*self.pages.get(addr / PAGE_SIZE)?)?;
+
+        Some((entry.sub_index, entry.offset + in_page))
+    }
+
+    pub fn set_unmapped_policy(&mut self, policy: UnmappedPolicy) {
+        self.unmapped_policy = policy;
+    }
+
+    pub fn get_unmapped_policy(&self) -> UnmappedPolicy {
+        self.unmapped_policy
+    }
+
+    pub fn set_read_only_policy(&mut self, policy: ReadOnlyPolicy) {
+        self.read_only_policy = policy;
+    }
+
+    pub fn get_read_only_policy(&self) -> ReadOnlyPolicy {
+        self.read_only_policy
+    }
+
+    /*
+     * Return the warnings raised by the policies since the last call.
+     */
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    fn warn(&self, message: String) {
+        self.warnings.borrow_mut().push(message);
+    }
+
+    fn drive_bus(&self, data: &[u8]) {
+        if let Some(&value) = data.last() {
+            self.data_bus.set(value);
+        }
+    }
+
+    fn read_unmapped(
+        &self,
+        addr: usize,
+        len: usize,
+        error: MemoryError,
+        peek: bool,
+    ) -> Result<Vec<u8>, MemoryError> {
+        let value = match self.unmapped_policy {
+            UnmappedPolicy::Error => return Err(error),
+            UnmappedPolicy::OpenBus => self.data_bus.get(),
+            UnmappedPolicy::Fixed(value) => value,
+            UnmappedPolicy::Warn if peek => self.data_bus.get(),
+            UnmappedPolicy::Warn => {
+                let value = self.data_bus.get();
+                self.warn(format!(
+                    "read {} byte(s) of unallocated memory at #0x{:04X}, got 0x{:02X}",
+                    len, addr, value
+                ));
+                value
+            }
+        };
+
+        Ok(vec![value; len])
+    }
+
+    /*
+     * Peeking reads the subsystems without triggering their side effects and
+     * leaves the data bus and the warnings untouched.
+     */
+    fn read_subsystems(&self, addr: usize, len: usize, peek: bool) -> Result<Vec<u8>, MemoryError> {
+        let read = |sub: &Subsystem, location: usize, len: usize| {
+            let result = if peek {
+                sub.peek(location, len)
+            } else {
+                sub.read(location, len)
+            };
+            let data = result.map_err(|e| sub.context(e))?;
+            if !peek {
+                self.drive_bus(&data);
+            }
+
+            Ok::<Vec<u8>, MemoryError>(data)
+        };
+
+        if let Some((sub_index, location)) = self.lookup_page(addr, len) {
+            return read(&self.stack[sub_index], location, len);
+        }
+        let end = addr + len;
+        let mut results: Vec<u8> = Vec::with_capacity(len);
+        let mut tmpaddr = addr;
+        for (&addr_split, &sub_index) in self.address_map.range(addr + 1..) {
+            if tmpaddr >= end {
+                break;
+            }
+            let substart = self.stack[sub_index].address_range.start;
+            if substart > tmpaddr {
+                let hole_len = cmp::min(substart, end) - tmpaddr;
+                let error = MemoryError::Unmapped(MemoryAccess::Read, hole_len, tmpaddr);
+                results.append(&mut self.read_unmapped(tmpaddr, hole_len, error, peek)?);
+                tmpaddr += hole_len;
+                if tmpaddr >= end {
+                    break;
+                }
+            }
+            let sublen = cmp::min(addr_split, end) - tmpaddr;
+            results.append(&mut read(&self.stack[sub_index], tmpaddr - substart, sublen)?);
+            tmpaddr += sublen;
+        }
+        // there is still memory to read but no remaining subsystems
+        if tmpaddr < end {
+            let error = MemoryError::ReadOverflow(end - tmpaddr, tmpaddr);
+            results.append(&mut self.read_unmapped(tmpaddr, end - tmpaddr, error, peek)?);
+        }
+
+        Ok(results)
+    }
+
+    fn write_unmapped(
+        &self,
+        addr: usize,
+        len: usize,
+        error: MemoryError,
+    ) -> Result<(), MemoryError> {
+        match self.unmapped_policy {
+            UnmappedPolicy::Error => Err(error),
+            UnmappedPolicy::OpenBus | UnmappedPolicy::Fixed(_) => Ok(()),
+            UnmappedPolicy::Warn => {
+                self.warn(format!(
+                    "write of {} byte(s) to unallocated memory at #0x{:04X} ignored",
+                    len, addr
+                ));
+                Ok(())
+            }
+        }
+    }
+
+    fn write_subsystem(
+        &mut self,
+        sub_index: usize,
+        location: usize,
+        data: &[u8],
+    ) -> Result<(), MemoryError> {
+        let sub = &self.stack[sub_index];
+        if sub.is_read_only() {
+            match self.read_only_policy {
+                ReadOnlyPolicy::Error => (),
+                ReadOnlyPolicy::Ignore => return Ok(()),
+                ReadOnlyPolicy::Warn => {
+                    self.warn(format!(
+                        "write of {} byte(s) to read-only subsystem '{}' at #0x{:04X} ignored",
+                        data.len(),
+                        sub.name,
+                        sub.address_range.start + location
+                    ));
+                    return Ok(());
+                }
+            }
+        }
+
+        let sub = &mut self.stack[sub_index];
+        sub.write(location, data).map_err(|e| sub.context(e))?;
+        self.run_dma(sub_index);
+
+        Ok(())
+    }
+
+    /*
+     * Perform the transfers requested by a device, the outcome is handed
+     * back to the device so it can report failures in its status.
+     */
+    fn run_dma(&mut self, sub_index: usize) {
+        while let Some(request) = self.stack[sub_index].take_dma_request() {
+            let result = match request {
+                DmaRequest::Write(address, data) => self.write(address, &data).map(|_| Vec::new()),
+                DmaRequest::Read(address, len) => self.read(address, len),
+            };
+            self.stack[sub_index].dma_complete(result);
+        }
+    }
+
+    /*
+     * Tell if any device holds the IRQ line.
+     */
+    pub fn irq_pending(&self) -> bool {
+        self.devices.iter().any(|&sub_index| self.stack[sub_index].irq())
+    }
+
+    /*
+     * Return the last added device of the given type.
+     */
+    pub fn find_device<T: Any>(&self) -> Option<&T> {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|sub| sub.subsystem.as_any()?.downcast_ref::<T>())
+    }
+
+    /*
+     * Return the last added device of the given type with the given subsystem
+     * name, to tell apart several devices of the same type.
+     */
+    pub fn find_named_device<T: Any>(&self, name: &str) -> Option<&T> {
+        self.stack
+            .iter()
+            .rev()
+            .filter(|sub| sub.name == name)
+            .find_map(|sub| sub.subsystem.as_any()?.downcast_ref::<T>())
+    }
+
+    /*
+     * Return the last added device of the given type, mutably.
+     */
+    pub fn find_device_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.stack
+            .iter_mut()
+            .rev()
+            .find_map(|sub| sub.subsystem.as_any_mut()?.downcast_mut::<T>())
+    }
+
+    /*
+     * Let the devices know how many cycles the CPU has run.
+     */
+    pub fn tick(&mut self, cycle_count: u64) {
+        for &sub_index in &self.devices {
+            self.stack[sub_index].tick(cycle_count);
+        }
+    }
+
+    /*
+     * Run the given Rust routine instead of the code at the given address, an
+     * RTS follows. This stands for ROM routines or I/O the memory does not
+     * emulate, a previous host call at this address is replaced.
+     */
+    pub fn add_host_call<F>(&mut self, address: usize, routine: F)
+    where
+        F: FnMut(&mut Registers, &mut MemoryStack) -> Result<(), CPUError> + 'static,
+    {
+        self.host_calls.insert(address, HostCall::new(routine));
+    }
+
+    /// Remove the host call at the given address and its records, tell if
+    /// there was one.
+    pub fn remove_host_call(&mut self, address: usize) -> bool {
+        self.host_call_records.remove(&address);
+        self.host_calls.remove(&address).is_some()
+    }
+
+    pub fn has_host_call(&self, address: usize) -> bool {
+        self.host_calls.contains_key(&address)
+    }
+
+    /// Addresses of the host calls in ascending order.
+    pub fn get_host_call_addresses(&self) -> Vec<usize> {
+        self.host_calls.keys().copied().collect()
+    }
+
+    /*
+     * Keep a record of every call made to the host call at the given address
+     * from now on, previous records are dropped. Recording is off by default
+     * since the KERNAL routines of a long run would pile up records.
+     */
+    pub fn record_host_calls(&mut self, address: usize) {
+        self.host_call_records.insert(address, Vec::new());
+    }
+
+    /// Records of the calls made to the host call at the given address in
+    /// call order, None when the calls are not recorded.
+    pub fn get_host_call_records(&self, address: usize) -> Option<&[HostCallRecord]> {
+        self.host_call_records.get(&address).map(|records| records.as_slice())
+    }
+
+    pub(crate) fn record_host_call(&mut self, registers: &Registers) -> Result<(), MemoryError> {
+        if self.host_call_records.contains_key(&registers.command_pointer) {
+            let record = HostCallRecord::new(registers, self)?;
+            if let Some(records) = self.host_call_records.get_mut(&registers.command_pointer) {
+                records.push(record);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn take_host_call(&mut self, address: usize) -> Option<HostCall> {
+        self.host_calls.remove(&address)
+    }
+
+    pub(crate) fn restore_host_call(&mut self, address: usize, host_call: HostCall) {
+        self.host_calls.entry(address).or_insert(host_call);
+    }
+
+    pub fn get_subsystems(&self) -> Vec<SubsystemInfo> {
+        self.stack.iter().map(|sub| sub.info()).collect()
+    }
+
+    /*
+     * Return the effective address map: which subsystem answers to each
+     * range of addresses, including the unallocated ones.
+     */
+    pub fn get_memory_map(&self) -> Vec<MemoryRegion> {
+        let mut regions: Vec<MemoryRegion> = Vec::new();
+        let mut start = 0;
+
+        for (&addr_split, &sub_index) in self.address_map.iter() {
+            let substart = self.stack[sub_index].address_range.start;
+            if substart > start {
+                regions.push(self.memory_region(start, substart - 1, None));
+                start = substart;
+            }
+            regions.push(self.memory_region(start, addr_split - 1, Some(sub_index)));
+            start = addr_split;
+        }
+        if start <= MEMMAX {
+            regions.push(self.memory_region(start, MEMMAX, None));
+        }
+
+        regions
+    }
+
+    fn memory_region(&self, start: usize, end: usize, subsystem: Option<usize>) -> MemoryRegion {
+        let shadowed = self
+            .stack
+            .iter()
+            .enumerate()
+            .filter(|(sub_index, sub)| {
+                Some(*sub_index) != subsystem
+                    && sub.address_range.start <= end
+                    && sub.address_range.end > start
+            })
+            .map(|(sub_index, _)| sub_index)
+            .collect();
+
+        MemoryRegion {
+            start,
+            end,
+            subsystem,
+            shadowed,
+        }
     }
 
     pub fn get_subsystems_info(&self) -> Vec<String> {
@@ -124,51 +807,49 @@ impl MemoryStack {
 
 impl AddressableIO for MemoryStack {
     fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
-        let mut results: Vec<u8> = vec![];
-        let mut tmplen = len;
-        let mut tmpaddr = addr;
-        for (&addr_split, &sub_index) in &self.address_map {
-            if addr_split >= tmpaddr {
-                let sublen = cmp::min(addr_split - tmpaddr, tmplen);
-                let substart = self.stack[sub_index].address_range.start;
-                if substart > tmpaddr {
-                    return Err(MemoryError::Other(tmpaddr, "reading unallocated memory"));
-                }
-                let mut subr = self.stack[sub_index].read(tmpaddr - substart, sublen)?;
-                results.append(&mut subr);
-                tmplen -= sublen;
-                tmpaddr += sublen;
-            }
-            if addr_split > addr + len {
-                break;
-            }
-        }
-        // there is still memory to read but no remaining subsystems
-        if tmplen > 0 {
-            return Err(MemoryError::ReadOverflow(tmplen, tmpaddr));
-        }
+        self.read_subsystems(addr, len, false)
+    }
 
-        Ok(results)
+    fn peek(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        self.read_subsystems(addr, len, true)
     }
 
     fn write(&mut self, addr: usize, data: &[u8]) -> Result<(), MemoryError> {
-        let len = data.len();
-        let mut data = data.to_vec();
-        let mut tmplen = len;
+        self.drive_bus(data);
+        if let Some((sub_index, location)) = self.lookup_page(addr, data.len()) {
+            return self.write_subsystem(sub_index, location, data);
+        }
+        let end = addr + data.len();
         let mut tmpaddr = addr;
-        for (&addr_split, &sub_index) in &self.address_map {
-            if addr_split > tmpaddr {
-                let sublen = cmp::min(addr_split - tmpaddr, tmplen);
-                let data_left = data.split_off(sublen);
-                let substart = self.stack[sub_index].address_range.start;
-                self.stack[sub_index].write(tmpaddr - substart, &data)?;
-                data = data_left;
-                tmplen -= sublen;
-                tmpaddr += sublen;
-            }
-            if addr_split >= addr + len {
+        let split_points: Vec<(usize, usize)> = self
+            .address_map
+            .range(addr + 1..)
+            .map(|(&addr_split, &sub_index)| (addr_split, sub_index))
+            .collect();
+        for (addr_split, sub_index) in split_points {
+            if tmpaddr >= end {
                 break;
             }
+            let substart = self.stack[sub_index].address_range.start;
+            if substart > tmpaddr {
+                let hole_end = cmp::min(substart, end);
+                let error =
+                    MemoryError::Unmapped(MemoryAccess::Write, hole_end - tmpaddr, tmpaddr);
+                self.write_unmapped(tmpaddr, hole_end - tmpaddr, error)?;
+                tmpaddr = hole_end;
+                if tmpaddr >= end {
+                    break;
+                }
+            }
+            let subend = cmp::min(addr_split, end);
+            let subdata = &data[tmpaddr - addr..subend - addr];
+            self.write_subsystem(sub_index, tmpaddr - substart, subdata)?;
+            tmpaddr = subend;
+        }
+        // there is still data to write but no remaining subsystems
+        if tmpaddr < end {
+            let error = MemoryError::WriteOverflow(end - tmpaddr, tmpaddr);
+            self.write_unmapped(tmpaddr, end - tmpaddr, error)?;
         }
 
         Ok(())
@@ -177,11 +858,35 @@ impl AddressableIO for MemoryStack {
     fn get_size(&self) -> usize {
         MEMMAX
     }
+
+    fn read_byte(&self, addr: usize) -> Result<u8, MemoryError> {
+        let value = match self.lookup_page(addr, 1) {
+            Some((sub_index, location)) => {
+                let sub = &self.stack[sub_index];
+                sub.read_byte(location).map_err(|e| sub.context(e))?
+            }
+            None => self.read(addr, 1)?[0],
+        };
+        self.data_bus.set(value);
+
+        Ok(value)
+    }
+
+    fn write_byte(&mut self, location: usize, value: u8) -> Result<(), MemoryError> {
+        match self.lookup_page(location, 1) {
+            Some((sub_index, sub_location)) => {
+                self.data_bus.set(value);
+                self.write_subsystem(sub_index, sub_location, &[value])
+            }
+            None => self.write(location, &[value]),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::devices::{Rtc, HD44780};
 
     struct FakeMemory {
         size: usize,
@@ -216,6 +921,39 @@ mod tests {
         }
     }
 
+    struct ScratchMemory {
+        bytes: Vec<u8>,
+    }
+
+    impl ScratchMemory {
+        fn new(bytes: Vec<u8>) -> ScratchMemory {
+            ScratchMemory { bytes }
+        }
+    }
+
+    impl AddressableIO for ScratchMemory {
+        fn get_size(&self) -> usize {
+            self.bytes.len()
+        }
+
+        fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+            if addr + len > self.bytes.len() {
+                Err(MemoryError::ReadOverflow(len, addr))
+            } else {
+                Ok(self.bytes[addr..addr + len].to_vec())
+            }
+        }
+
+        fn write(&mut self, addr: usize, data: &[u8]) -> Result<(), MemoryError> {
+            if addr + data.len() > self.bytes.len() {
+                Err(MemoryError::WriteOverflow(data.len(), addr))
+            } else {
+                self.bytes[addr..addr + data.len()].copy_from_slice(data);
+                Ok(())
+            }
+        }
+    }
+
     fn init_memory() -> MemoryStack {
         let mut memory_stack = MemoryStack::default();
         memory_stack.add_subsystem("RAM", 0x0000, RAM::default());
@@ -275,9 +1013,9 @@ mod tests {
         let mut memory_stack = init_memory();
         let data: Vec<u8> = vec![0xff, 0xae, 0x81];
         match memory_stack.write(0xBFFE, &data) {
-            Err(MemoryError::Other(addr, msg)) => {
-                assert_eq!(0x0000, addr);
-                assert_eq!("trying to write in a read-only memory".to_owned(), msg);
+            Err(e) => {
+                assert_eq!(MemoryError::WriteProtected(1, 0xC000), *e.cause());
+                assert_eq!(Some("ROM"), e.subsystem());
             }
             v => panic!("it should return the expected error, got {:?}", v),
         };
@@ -321,4 +1059,383 @@ mod tests {
             _ => panic!("that was not the expected error"),
         }
     }
+
+    #[test]
+    fn test_add_mirrored_subsystem() {
+        let mut memory_stack = init_memory();
+        memory_stack.add_mirrored_subsystem(
+            "VIA",
+            0xC000,
+            0x100,
+            ScratchMemory::new(vec![0x00, 0x01, 0x02, 0x03]),
+        );
+        let output = memory_stack.get_subsystems_info();
+        assert_eq!(
+            "#2: Subsystem VIA         , address range=#0xC000 → #0xC0FF, size = 4 bytes, mirrored every 4 bytes",
+            output[2]
+        );
+        assert_eq!(vec![0x00, 0x01, 0x02, 0x03], memory_stack.read(0xC000, 4).unwrap());
+        assert_eq!(vec![0x01, 0x02, 0x03, 0x00, 0x01], memory_stack.read(0xC0F5, 5).unwrap());
+        assert_eq!(vec![0x03, 0xae, 0xae], memory_stack.read(0xC0FF, 3).unwrap());
+    }
+
+    #[test]
+    fn test_write_mirrored_subsystem() {
+        let mut memory_stack = init_memory();
+        memory_stack.add_mirrored_subsystem(
+            "VIA",
+            0x8000,
+            0x100,
+            ScratchMemory::new(vec![0x00; 16]),
+        );
+        memory_stack.write(0x80F2, &[0xaa, 0xbb]).unwrap();
+        assert_eq!(vec![0xaa, 0xbb], memory_stack.read(0x8002, 2).unwrap());
+        assert_eq!(vec![0xaa, 0xbb], memory_stack.read(0x8012, 2).unwrap());
+        memory_stack.write(0x800F, &[0x01, 0x02]).unwrap();
+        assert_eq!(vec![0x02, 0x00, 0xaa], memory_stack.read(0x80A0, 3).unwrap());
+        assert_eq!(vec![0x01], memory_stack.read(0x80EF, 1).unwrap());
+        // the RAM behind the window is untouched
+        assert_eq!(vec![0x00, 0x00], memory_stack.read(0x7FFE, 2).unwrap());
+        assert_eq!(vec![0x00], memory_stack.read(0x8100, 1).unwrap());
+    }
+
+    #[test]
+    fn test_mirrored_subsystem_error_address() {
+        let mut memory_stack = init_memory();
+        memory_stack.add_mirrored_subsystem("ROM", 0x8000, 0x100, ROM::new(vec![0x00; 16]));
+
+        let error = memory_stack.write(0x80F2, &[0xaa]).unwrap_err();
+        assert_eq!(MemoryError::WriteProtected(1, 0x80F2), *error.cause());
+        assert_eq!(Some("ROM"), error.subsystem());
+        let error = memory_stack.write_byte(0x8033, 0xaa).unwrap_err();
+        assert_eq!(MemoryError::WriteProtected(1, 0x8033), *error.cause());
+    }
+
+    #[test]
+    fn test_add_masked_subsystem() {
+        let mut memory_stack = init_memory();
+        // A2 is not decoded: registers 0-3 appear at 0-3 and 4-7
+        memory_stack.add_masked_subsystem(
+            "ACIA",
+            0x9000,
+            0x10,
+            0x0B,
+            ScratchMemory::new((0..16).collect()),
+        );
+        let output = memory_stack.get_subsystems_info();
+        assert_eq!(
+            "#2: Subsystem ACIA        , address range=#0x9000 → #0x900F, size = 16 bytes, address mask = #0x000B",
+            output[2]
+        );
+        assert_eq!(
+            vec![0x00, 0x01, 0x02, 0x03, 0x00, 0x01, 0x02, 0x03, 0x08, 0x09],
+            memory_stack.read(0x9000, 10).unwrap()
+        );
+        memory_stack.write(0x9005, &[0xff]).unwrap();
+        assert_eq!(vec![0xff], memory_stack.read(0x9001, 1).unwrap());
+    }
+
+    #[test]
+    fn test_subsystem_added_inside_mirror_window() {
+        let mut memory_stack = init_memory();
+        memory_stack.add_mirrored_subsystem(
+            "VIA",
+            0xC000,
+            0x100,
+            ScratchMemory::new(vec![0x01, 0x02]),
+        );
+        memory_stack.add_subsystem("ACIA", 0xC010, ScratchMemory::new(vec![0xff; 4]));
+        assert_eq!(
+            vec![0x01, 0x02, 0xff, 0xff, 0xff, 0xff, 0x01, 0x02],
+            memory_stack.read(0xC00E, 8).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_pages() {
+        let mut memory_stack = init_memory();
+        assert_eq!(Some(PageEntry { sub_index: 0, offset: 0xBF00 }), memory_stack.pages[0xBF]);
+        assert_eq!(Some(PageEntry { sub_index: 1, offset: 0x0000 }), memory_stack.pages[0xC0]);
+        assert_eq!(Some(PageEntry { sub_index: 1, offset: 0x3F00 }), memory_stack.pages[0xFF]);
+        memory_stack.add_subsystem("DUMMY", 0xC010, FakeMemory::new(0x10, 0x42));
+        assert_eq!(None, memory_stack.pages[0xC0]);
+        assert_eq!(Some(PageEntry { sub_index: 1, offset: 0x0100 }), memory_stack.pages[0xC1]);
+    }
+
+    #[test]
+    fn test_pages_with_unallocated_memory() {
+        let mut memory_stack = MemoryStack::default();
+        memory_stack.add_subsystem("DUMMY", 0x0000, FakeMemory::new(0x1080, 0));
+        assert_eq!(Some(PageEntry { sub_index: 0, offset: 0x0F00 }), memory_stack.pages[0x0F]);
+        assert_eq!(None, memory_stack.pages[0x10]);
+        assert_eq!(None, memory_stack.pages[0x11]);
+        assert!(memory_stack.read_byte(0x1100).is_err());
+    }
+
+    #[test]
+    fn test_read_byte_overlapping_subsystems() {
+        let mut memory_stack = init_memory();
+        memory_stack.add_subsystem("DUMMY", 0xC010, FakeMemory::new(0x10, 0x42));
+        assert_eq!(0x00, memory_stack.read_byte(0xBFFF).unwrap());
+        assert_eq!(0xae, memory_stack.read_byte(0xC00F).unwrap());
+        assert_eq!(0x42, memory_stack.read_byte(0xC010).unwrap());
+        assert_eq!(0x42, memory_stack.read_byte(0xC01F).unwrap());
+        assert_eq!(0xae, memory_stack.read_byte(0xC020).unwrap());
+        assert_eq!(
+            vec![0xae, 0x42, 0x42],
+            memory_stack.read(0xC00F, 3).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_byte_overlapping_subsystems() {
+        let mut memory_stack = init_memory();
+        memory_stack.write_byte(0xBFFF, 0x12).unwrap();
+        assert_eq!(0x12, memory_stack.read_byte(0xBFFF).unwrap());
+        assert!(memory_stack.write_byte(0xC000, 0x12).is_err());
+        memory_stack.add_mirrored_subsystem(
+            "VIA",
+            0xB000,
+            0x100,
+            ScratchMemory::new(vec![0x00; 16]),
+        );
+        memory_stack.write_byte(0xB0F3, 0x34).unwrap();
+        assert_eq!(0x34, memory_stack.read_byte(0xB003).unwrap());
+        assert_eq!(vec![0x34], memory_stack.read(0xB013, 1).unwrap());
+    }
+
+    fn init_memory_with_hole() -> MemoryStack {
+        let mut memory_stack = MemoryStack::default();
+        memory_stack.add_subsystem("DUMMY", 0x0000, ScratchMemory::new(vec![0x12; 0x1000]));
+        memory_stack.add_subsystem("DUMMY", 0x2000, ScratchMemory::new(vec![0x34; 0x1000]));
+
+        memory_stack
+    }
+
+    #[test]
+    fn test_unmapped_policy_error() {
+        let mut memory_stack = init_memory_with_hole();
+        assert_eq!(
+            Err(MemoryError::Unmapped(MemoryAccess::Read, 1, 0x1000)),
+            memory_stack.read(0x0FFF, 2)
+        );
+        assert_eq!(
+            Err(MemoryError::Unmapped(MemoryAccess::Write, 1, 0x1000)),
+            memory_stack.write(0x0FFF, &[0x00, 0x00])
+        );
+        assert_eq!(
+            Err(MemoryError::WriteOverflow(0x10, 0x3000)),
+            memory_stack.write(0x2FF0, &[0x00; 0x20])
+        );
+    }
+
+    #[test]
+    fn test_unmapped_policy_open_bus() {
+        let mut memory_stack = init_memory_with_hole();
+        memory_stack.set_unmapped_policy(UnmappedPolicy::OpenBus);
+        assert_eq!(vec![0x12, 0x12, 0x12], memory_stack.read(0x0FFF, 3).unwrap());
+        assert_eq!(0x34, memory_stack.read_byte(0x2000).unwrap());
+        assert_eq!(0x34, memory_stack.read_byte(0x1800).unwrap());
+        memory_stack.write(0x1800, &[0x56]).unwrap();
+        assert_eq!(vec![0x56, 0x34], memory_stack.read(0x1FFF, 2).unwrap());
+        assert_eq!(vec![0x34, 0x34], memory_stack.read(0x2FFF, 2).unwrap());
+        assert!(memory_stack.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_unmapped_policy_fixed() {
+        let mut memory_stack = init_memory_with_hole();
+        memory_stack.set_unmapped_policy(UnmappedPolicy::Fixed(0xFF));
+        assert_eq!(vec![0x12, 0xFF, 0xFF], memory_stack.read(0x0FFF, 3).unwrap());
+        assert_eq!(0xFF, memory_stack.read_byte(0x1000).unwrap());
+        memory_stack.write_byte(0x1000, 0x00).unwrap();
+        assert_eq!(0xFF, memory_stack.read_byte(0x1000).unwrap());
+    }
+
+    #[test]
+    fn test_unmapped_policy_warn() {
+        let mut memory_stack = init_memory_with_hole();
+        memory_stack.set_unmapped_policy(UnmappedPolicy::Warn);
+        assert_eq!(vec![0x12, 0x12], memory_stack.read(0x0FFF, 2).unwrap());
+        memory_stack.write_byte(0x1800, 0x00).unwrap();
+        assert_eq!(
+            vec![
+                "read 1 byte(s) of unallocated memory at #0x1000, got 0x12".to_string(),
+                "write of 1 byte(s) to unallocated memory at #0x1800 ignored".to_string(),
+            ],
+            memory_stack.take_warnings()
+        );
+        assert!(memory_stack.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_read_only_policy() {
+        let mut memory_stack = init_memory();
+        assert!(memory_stack.write_byte(0xC000, 0x00).is_err());
+        memory_stack.set_read_only_policy(ReadOnlyPolicy::Ignore);
+        memory_stack.write_byte(0xC000, 0x00).unwrap();
+        memory_stack.write(0xBFFF, &[0x01, 0x02]).unwrap();
+        assert_eq!(vec![0x01, 0xae], memory_stack.read(0xBFFF, 2).unwrap());
+        assert!(memory_stack.take_warnings().is_empty());
+        memory_stack.set_read_only_policy(ReadOnlyPolicy::Warn);
+        memory_stack.write(0xBFFF, &[0x03, 0x04, 0x05]).unwrap();
+        assert_eq!(vec![0x03, 0xae, 0xae], memory_stack.read(0xBFFF, 3).unwrap());
+        assert_eq!(
+            vec!["write of 2 byte(s) to read-only subsystem 'ROM' at #0xC000 ignored".to_string()],
+            memory_stack.take_warnings()
+        );
+    }
+
+    struct ReadCounter {
+        reads: Cell<usize>,
+    }
+
+    impl AddressableIO for ReadCounter {
+        fn get_size(&self) -> usize {
+            4
+        }
+
+        fn read(&self, _addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(vec![self.reads.get() as u8; len])
+        }
+
+        fn peek(&self, _addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+            Ok(vec![self.reads.get() as u8; len])
+        }
+
+        fn write(&mut self, _addr: usize, _data: &[u8]) -> Result<(), MemoryError> {
+            Ok(())
+        }
+
+        fn kind(&self) -> &str {
+            "counter"
+        }
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut memory_stack = init_memory_with_hole();
+        memory_stack.add_mirrored_subsystem(
+            "COUNTER",
+            0x2000,
+            0x10,
+            ReadCounter {
+                reads: Cell::new(0),
+            },
+        );
+        assert_eq!(vec![0x01], memory_stack.read(0x2000, 1).unwrap());
+        assert_eq!(vec![0x01, 0x01], memory_stack.peek(0x2006, 2).unwrap());
+        assert_eq!(vec![0x02], memory_stack.read(0x2000, 1).unwrap());
+        assert!(memory_stack.peek(0x0FFF, 2).is_err());
+        memory_stack.set_unmapped_policy(UnmappedPolicy::Warn);
+        assert_eq!(vec![0x12, 0x02], memory_stack.peek(0x0FFF, 2).unwrap());
+        assert!(memory_stack.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_get_subsystems() {
+        let mut memory_stack = init_memory();
+        memory_stack.add_masked_subsystem(
+            "VIA",
+            0x8000,
+            0x100,
+            0x0F,
+            ReadCounter {
+                reads: Cell::new(0),
+            },
+        );
+        let subsystems = memory_stack.get_subsystems();
+        assert_eq!(3, subsystems.len());
+        assert_eq!(
+            SubsystemInfo {
+                name: "ROM".to_string(),
+                start: 0xC000,
+                end: 0xFFFF,
+                kind: "ROM".to_string(),
+                decoder: AddressDecoder::Full,
+                read_only: true,
+            },
+            subsystems[1]
+        );
+        assert_eq!("counter", subsystems[2].kind);
+        assert_eq!(AddressDecoder::Mask(0x0F), subsystems[2].decoder);
+        assert!(!subsystems[2].read_only);
+    }
+
+    #[test]
+    fn test_get_memory_map() {
+        let mut memory_stack = init_memory_with_hole();
+        memory_stack.add_subsystem("ROM", 0x2800, ROM::new(vec![0x00; 0x1000]));
+        let map = memory_stack.get_memory_map();
+        assert_eq!(
+            vec![
+                MemoryRegion {
+                    start: 0x0000,
+                    end: 0x0FFF,
+                    subsystem: Some(0),
+                    shadowed: vec![],
+                },
+                MemoryRegion {
+                    start: 0x1000,
+                    end: 0x1FFF,
+                    subsystem: None,
+                    shadowed: vec![],
+                },
+                MemoryRegion {
+                    start: 0x2000,
+                    end: 0x27FF,
+                    subsystem: Some(1),
+                    shadowed: vec![],
+                },
+                MemoryRegion {
+                    start: 0x2800,
+                    end: 0x37FF,
+                    subsystem: Some(2),
+                    shadowed: vec![1],
+                },
+                MemoryRegion {
+                    start: 0x3800,
+                    end: 0xFFFF,
+                    subsystem: None,
+                    shadowed: vec![],
+                },
+            ],
+            map
+        );
+        let map = init_memory().get_memory_map();
+        assert_eq!(2, map.len());
+        assert_eq!((0xC000, 0xFFFF, Some(1)), (map[1].start, map[1].end, map[1].subsystem));
+        assert_eq!(vec![0], map[1].shadowed);
+    }
+
+    #[test]
+    fn test_find_device() {
+        let mut memory_stack = init_memory();
+        assert!(memory_stack.find_device::<HD44780>().is_none());
+        memory_stack.add_subsystem("LCD", 0x8000, HD44780::new(20, 4));
+        memory_stack.add_subsystem("LCD2", 0x8002, HD44780::default());
+
+        assert_eq!((16, 2), memory_stack.find_device::<HD44780>().unwrap().get_dimensions());
+        assert_eq!((20, 4), memory_stack.find_named_device::<HD44780>("LCD").unwrap().get_dimensions());
+        assert!(memory_stack.find_named_device::<HD44780>("RAM").is_none());
+        assert!(memory_stack.find_device::<RAM>().is_none());
+    }
+
+    #[test]
+    fn test_tick() {
+        let mut memory_stack = init_memory();
+        assert!(memory_stack.devices.is_empty());
+        memory_stack.add_subsystem("RTC", 0x8000, Rtc::new(10));
+        assert_eq!(vec![2], memory_stack.devices);
+        memory_stack.tick(100);
+        memory_stack
+            .find_device_mut::<Rtc>()
+            .unwrap()
+            .set_time("2024-01-01T00:00:00".parse().unwrap());
+        memory_stack.tick(150);
+
+        assert_eq!(5, memory_stack.read_byte(0x8000).unwrap());
+        assert!(memory_stack.find_device_mut::<HD44780>().is_none());
+    }
 }
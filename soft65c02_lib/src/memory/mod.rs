@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::fmt;
 
 mod error;
@@ -5,8 +6,10 @@ mod memory_stack;
 mod ram;
 mod rom;
 
-pub use error::MemoryError;
-pub use memory_stack::MemoryStack;
+pub use error::{MemoryAccess, MemoryError};
+pub use memory_stack::{
+    AddressDecoder, MemoryRegion, MemoryStack, ReadOnlyPolicy, SubsystemInfo, UnmappedPolicy,
+};
 pub use ram::RAM;
 pub use rom::ROM;
 
@@ -30,6 +33,75 @@ pub trait AddressableIO {
     fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError>;
     fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError>;
     fn get_size(&self) -> usize;
+
+    /// Read a single byte, implementors may override it to avoid allocating.
+    fn read_byte(&self, addr: usize) -> Result<u8, MemoryError> {
+        Ok(self.read(addr, 1)?[0])
+    }
+
+    /// Write a single byte, implementors may override it to avoid allocating.
+    fn write_byte(&mut self, location: usize, value: u8) -> Result<(), MemoryError> {
+        self.write(location, &[value])
+    }
+
+    /// Tell if writes to this memory are rejected, see `ReadOnlyPolicy`.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// Read without side effects, devices whose registers change when read
+    /// must override it.
+    fn peek(&self, addr: usize, len: usize) -> Result<Vec<u8>, MemoryError> {
+        self.read(addr, len)
+    }
+
+    /// The kind of memory, displayed when inspecting the memory map.
+    fn kind(&self) -> &str {
+        "device"
+    }
+
+    /// Give access to the concrete type of a device, see
+    /// `MemoryStack::find_device`.
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
+
+    /// Mutable counterpart of `as_any`, see `MemoryStack::find_device_mut`.
+    fn as_any_mut(&mut self) -> Option<&mut dyn Any> {
+        None
+    }
+
+    /// Called after each instruction with the CPU cycle count so devices can
+    /// follow time deterministically.
+    fn tick(&mut self, _cycle_count: u64) {}
+
+    /// Tell if the device holds the IRQ line low.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Transfer the device wants the memory stack to perform, checked after
+    /// each write to the device.
+    fn take_dma_request(&mut self) -> Option<DmaRequest> {
+        None
+    }
+
+    /// Outcome of the last DMA request, the bytes read for a
+    /// `DmaRequest::Read`.
+    fn dma_complete(&mut self, _result: Result<Vec<u8>, MemoryError>) {}
+}
+
+/*
+ * DmaRequest
+ * Devices do not see the memory stack, they ask it to move data on their
+ * behalf.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DmaRequest {
+    /// Write the bytes to memory starting at the given address.
+    Write(usize, Vec<u8>),
+    /// Read the given number of bytes starting at the given address.
+    Read(usize, usize),
 }
 
 /*
@@ -48,35 +120,56 @@ pub trait DisplayBackend: AddressableIO + Send {
     fn get_input_events(&mut self) -> Vec<u32>;
 }
 
+/*
+ * DebugIO
+ * Inspection helpers available on every memory, they rely on `peek` so
+ * dumping a device does not trigger its side effects.
+ */
 pub trait DebugIO: AddressableIO {
-    fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
-        todo!()
-        /*
-               let mut line = String::new();
-               let address = 0;
-               let end = self.get_size();
-               let bytes = self.read(0, end).unwrap();
-
-               while address < end {
-                   if address % 16 == 0 {
-                       write!(f, "{}", line).unwrap();
-                       line = format!("#{:04X}: ", address);
-                   } else if address % 8 == 0 {
-                       line = format!("{} ", line);
-                   }
-
-                   line = format!("{} {:02x}", line, bytes[address]);
-               }
-
-               write!(f, "{}", line)
-        */
+    /// Dump `len` bytes starting at `addr`, 16 bytes per line.
+    fn hexdump(&self, addr: usize, len: usize) -> Result<Vec<String>, MemoryError> {
+        let bytes = self.peek(addr, len)?;
+        let lines = bytes
+            .chunks(16)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let mut line = format!("#0x{:04X}:", addr + index * 16);
+                for (offset, byte) in chunk.iter().enumerate() {
+                    if offset == 8 {
+                        line.push(' ');
+                    }
+                    line.push_str(&format!(" {:02x}", byte));
+                }
+
+                line
+            })
+            .collect();
+
+        Ok(lines)
     }
 }
 
+impl<T: AddressableIO + ?Sized> DebugIO for T {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_hexdump() {
+        let mut ram = RAM::default();
+        let data: Vec<u8> = (0..20).collect();
+        ram.write(0x1000, &data).unwrap();
+
+        assert_eq!(
+            vec![
+                "#0x1000: 00 01 02 03 04 05 06 07  08 09 0a 0b 0c 0d 0e 0f".to_string(),
+                "#0x1010: 10 11 12 13".to_string(),
+            ],
+            ram.hexdump(0x1000, 20).unwrap()
+        );
+    }
+
     #[test]
     fn test_little_endian() {
         assert_eq!(0x1234, little_endian(vec![0x34, 0x12]));
@@ -35,9 +35,28 @@ impl AddressableIO for RAM {
     fn get_size(&self) -> usize {
         self.ram.len()
     }
-}
 
-impl DebugIO for RAM {}
+    fn kind(&self) -> &str {
+        "RAM"
+    }
+
+    fn read_byte(&self, addr: usize) -> Result<u8, MemoryError> {
+        self.ram
+            .get(addr)
+            .copied()
+            .ok_or(MemoryError::ReadOverflow(1, addr))
+    }
+
+    fn write_byte(&mut self, location: usize, value: u8) -> Result<(), MemoryError> {
+        match self.ram.get_mut(location) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            }
+            None => Err(MemoryError::WriteOverflow(1, location)),
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
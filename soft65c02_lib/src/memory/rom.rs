@@ -19,16 +19,26 @@ impl AddressableIO for ROM {
         }
     }
 
-    fn write(&mut self, location: usize, _data: &[u8]) -> Result<(), MemoryError> {
-        Err(MemoryError::Other(
-            location,
-            "trying to write in a read-only memory",
-        ))
+    fn write(&mut self, location: usize, data: &[u8]) -> Result<(), MemoryError> {
+        Err(MemoryError::WriteProtected(data.len(), location))
     }
 
     fn get_size(&self) -> usize {
         self.rom.len()
     }
-}
 
-impl DebugIO for ROM {}
+    fn kind(&self) -> &str {
+        "ROM"
+    }
+
+    fn read_byte(&self, addr: usize) -> Result<u8, MemoryError> {
+        self.rom
+            .get(addr)
+            .copied()
+            .ok_or(MemoryError::ReadOverflow(1, addr))
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+}
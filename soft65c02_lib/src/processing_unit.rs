@@ -1,23 +1,111 @@
 use super::addressing_mode::*;
 use super::cpu_instruction::microcode;
-use super::cpu_instruction::{CPUInstruction, LogLine};
+use super::cpu_instruction::{CPUInstruction, LogLine, RegisterState};
 use super::memory::MemoryStack as Memory;
-use super::memory::{AddressableIO, MemoryError};
+use super::memory::{little_endian, AddressableIO, MemoryError};
 use super::registers::Registers;
 use crate::cpu_instruction::microcode::MicrocodeError;
+use crate::cpu_instruction::INTERRUPT_VECTOR_ADDR;
 use std::convert::From;
 use std::error::Error;
 use std::fmt;
 use std::result::Result;
 
+const RTS_OPCODE: u8 = 0x60;
+
+/*
+ * HostCall
+ * Rust routine standing for the subroutine at an address, see
+ * `MemoryStack::add_host_call`. It gets the registers and the memory when the
+ * CPU is about to fetch from that address, an RTS follows.
+ */
+pub struct HostCall(Box<HostRoutine>);
+
+type HostRoutine = dyn FnMut(&mut Registers, &mut Memory) -> Result<(), CPUError>;
+
+impl HostCall {
+    pub fn new<F>(routine: F) -> Self
+    where
+        F: FnMut(&mut Registers, &mut Memory) -> Result<(), CPUError> + 'static,
+    {
+        Self(Box::new(routine))
+    }
+
+    pub fn call(&mut self, registers: &mut Registers, memory: &mut Memory) -> Result<(), CPUError> {
+        (self.0)(registers, memory)
+    }
+}
+
+impl fmt::Debug for HostCall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HostCall")
+    }
+}
+
+/// Size of the memory saved with a host call record: the zero page and the
+/// stack page, where subroutines get their arguments.
+pub const HOST_CALL_RECORD_SIZE: usize = 0x0200;
+
+/*
+ * HostCallRecord
+ * State of the CPU when a recorded host call was reached, see
+ * `MemoryStack::record_host_calls`: the registers, the cycle count and the
+ * content of the zero page and of the stack page.
+ */
+#[derive(Debug, Clone)]
+pub struct HostCallRecord {
+    pub registers: RegisterState,
+    pub cycle_count: u64,
+    pub memory: Vec<u8>,
+}
+
+impl HostCallRecord {
+    pub fn new(registers: &Registers, memory: &Memory) -> Result<Self, MemoryError> {
+        Ok(Self {
+            registers: RegisterState::new(registers),
+            cycle_count: registers.cycle_count,
+            memory: memory.peek(0x0000, HOST_CALL_RECORD_SIZE)?,
+        })
+    }
+
+    /// Byte saved at the given address, None out of the saved pages.
+    pub fn get_byte(&self, address: usize) -> Option<u8> {
+        self.memory.get(address).copied()
+    }
+}
+
+/*
+ * Number of operand bytes following an opcode. The 65C02 opcode map is
+ * regular enough for this to be deduced from the opcode's columns, only the
+ * instructions reading their operands are fetched this way.
+ */
+fn operands_length(opcode: u8) -> usize {
+    match (opcode >> 4, opcode & 0x0f) {
+        (0x0 | 0x4 | 0x6, 0x0) => 0,
+        (0x2, 0x0) => 2,
+        (_, 0x0..=0x2 | 0x4..=0x7) => 1,
+        (high, 0x9) if high % 2 == 0 => 1,
+        (_, 0x3 | 0x8 | 0xa | 0xb) => 0,
+        _ => 2,
+    }
+}
+
 pub fn resolve_opcode(address: usize, opcode: u8, memory: &Memory) -> Result<CPUInstruction, CPUError> {
     use microcode as mc;
     use AddressingMode as AM;
     use CPUInstruction as instr;
 
-    let (op1, op2) = {
-        let y = memory.read(address + 1, 2)?;
-        ([y[0]], [y[0], y[1]])
+    let (op1, op2) = match operands_length(opcode) {
+        0 => ([0x00], [0x00, 0x00]),
+        1 => {
+            let byte = memory.read_byte(address + 1)?;
+            ([byte], [byte, 0x00])
+        }
+        _ => {
+            let lsb = memory.read_byte(address + 1)?;
+            let msb = memory.read_byte(address + 2)?;
+            ([lsb], [lsb, msb])
+        }
     };
     let instruction = match opcode {
         0x00 => instr::new(address, opcode, "BRK", AM::Implied, mc::brk),
@@ -454,20 +542,82 @@ pub fn resolve_opcode(address: usize, opcode: u8, memory: &Memory) -> Result<CPU
     Ok(instruction)
 }
 
+/*
+ * Enter the interrupt service routine: the return address and the status
+ * register with the break bit cleared are pushed on the stack.
+ */
+fn interrupt(registers: &mut Registers, memory: &mut Memory) -> Result<(), CPUError> {
+    let bytes = usize::to_le_bytes(registers.command_pointer);
+    registers.stack_push(memory, bytes[1])?;
+    registers.stack_push(memory, bytes[0])?;
+    registers.stack_push(memory, registers.get_status_register() & 0b11101111)?;
+    registers.command_pointer = little_endian(memory.read(INTERRUPT_VECTOR_ADDR, 2)?);
+    registers.set_i_flag(true);
+    registers.set_d_flag(false);
+    registers.add_cycles(7);
+
+    Ok(())
+}
+
+/*
+ * Run the host call at the command pointer if there is one, then return from
+ * it with an RTS. The call is taken out of the memory while it runs so it can
+ * use the memory, it is put back unless it was replaced meanwhile.
+ */
+fn execute_host_call(registers: &mut Registers, memory: &mut Memory) -> Result<Option<LogLine>, CPUError> {
+    let address = registers.command_pointer;
+    let mut host_call = match memory.take_host_call(address) {
+        Some(host_call) => host_call,
+        None => return Ok(None),
+    };
+    memory.record_host_call(registers)?;
+    let result = host_call.call(registers, memory);
+    memory.restore_host_call(address, host_call);
+    result.map_err(|e| e.at_instruction(address, "host call"))?;
+    let cpu_instruction = resolve_opcode(address, RTS_OPCODE, memory)?;
+    let mut log_line = cpu_instruction.execute(memory, registers)?;
+    log_line.outcome = format!("[host call]{}", log_line.outcome);
+    registers.add_cycles(cpu_instruction.cycles.get());
+    memory.tick(registers.cycle_count);
+
+    Ok(Some(log_line))
+}
+
+/*
+ * Execute the instruction at the command pointer. When a device holds the IRQ
+ * line and interrupts are enabled, the CPU jumps to the interrupt handler
+ * first and the returned log line is the one of its first instruction. A host
+ * call at the command pointer runs instead of the instruction.
+ */
 pub fn execute_step(registers: &mut Registers, memory: &mut Memory) -> Result<LogLine, CPUError> {
+    if !registers.i_flag_is_set() && memory.irq_pending() {
+        interrupt(registers, memory)?;
+    }
+    if let Some(log_line) = execute_host_call(registers, memory)? {
+        return Ok(log_line);
+    }
     let cpu_instruction = read_step(registers.command_pointer, memory)?;
     
     // Execute the instruction first
-    let log_line = cpu_instruction.execute(memory, registers)?;
+    let log_line = cpu_instruction
+        .execute(memory, registers)
+        .map_err(|e| {
+            let instruction = format!(
+                "{} {}",
+                cpu_instruction.mnemonic, cpu_instruction.addressing_mode
+            );
+            e.at_instruction(cpu_instruction.address, instruction.trim_end())
+        })?;
     
     // Add all cycles after execution to include any extra cycles added
     registers.add_cycles(cpu_instruction.cycles.get());
+    memory.tick(registers.cycle_count);
     
     Ok(log_line)
 }
 
 pub fn read_step(address: usize, memory: &Memory) -> Result<CPUInstruction, CPUError> {
-    let opcode = memory.read(address, 1)?[0];
+    let opcode = memory.read_byte(address)?;
 
     resolve_opcode(address, opcode, memory)
 }
@@ -522,6 +672,33 @@ pub enum CPUError {
     MicrocodeError(MicrocodeError),
 }
 
+impl CPUError {
+    /*
+     * Attach the instruction being executed to the underlying memory error if
+     * any.
+     */
+    pub fn at_instruction(self, command_pointer: usize, instruction: &str) -> CPUError {
+        match self {
+            CPUError::MemoryError(e) => {
+                CPUError::MemoryError(e.at_instruction(command_pointer, instruction))
+            }
+            CPUError::MicrocodeError(e) => {
+                CPUError::MicrocodeError(e.at_instruction(command_pointer, instruction))
+            }
+        }
+    }
+
+    /*
+     * The memory error that caused the CPU to fail if any.
+     */
+    pub fn memory_error(&self) -> Option<&MemoryError> {
+        match self {
+            CPUError::MemoryError(e) => Some(e),
+            CPUError::MicrocodeError(e) => e.memory_error(),
+        }
+    }
+}
+
 impl Error for CPUError {}
 
 impl fmt::Display for CPUError {
@@ -549,6 +726,28 @@ impl From<MemoryError> for CPUError {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_operands_length() {
+        let memory = Memory::new_with_ram();
+        // 0xcb (WAI) is not supported yet
+        for opcode in (0x00..=0xff).filter(|&opcode| opcode != 0xcb) {
+            let instr = resolve_opcode(0x1000, opcode, &memory).unwrap();
+            assert_eq!(
+                instr.addressing_mode.get_operands().len(),
+                operands_length(opcode),
+                "operands length of opcode 0x{:02x}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_opcode_at_end_of_memory() {
+        let memory = Memory::new_with_ram();
+        let instr: CPUInstruction = resolve_opcode(0xFFFF, 0xca, &memory).unwrap();
+        assert_eq!("DEX".to_owned(), instr.mnemonic);
+    }
+
     #[test]
     fn test_dex() {
         let memory = Memory::new_with_ram();
@@ -569,6 +768,152 @@ mod tests {
         assert_eq!(0x1001, registers.command_pointer);
     }
 
+    #[test]
+    fn test_execute_step_error_context() {
+        let mut memory = Memory::default();
+        memory.add_subsystem("RAM", 0x0000, crate::memory::RAM::default());
+        memory.add_subsystem("BASIC", 0xC000, crate::memory::ROM::new(vec![0x00; 0x2000]));
+        memory.write(0x1234, &[0x8d, 0x00, 0xc0]).unwrap();
+        let mut registers = Registers::new(0x1234);
+
+        let error = execute_step(&mut registers, &mut memory).unwrap_err();
+        let memory_error = error.memory_error().unwrap();
+        assert_eq!(Some(0x1234), memory_error.command_pointer());
+        assert_eq!(Some("BASIC"), memory_error.subsystem());
+        assert_eq!(0xC000, memory_error.address());
+        assert_eq!(
+            "STA $C000 at #0x1234 wrote 1 byte(s) to read-only memory at #0xC000 in subsystem 'BASIC'",
+            memory_error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_execute_step_irq() {
+        use crate::devices::BlockDevice;
+        use std::io::Cursor;
+
+        let mut memory = Memory::new_with_ram();
+        memory.add_subsystem("DISK", 0xB000, BlockDevice::new(Cursor::new(vec![0x00; 512])).unwrap());
+        memory.write(0xFFFE, &[0x00, 0x30]).unwrap();
+        // handler acknowledges the interrupt: LDA $B004
+        memory.write(0x3000, &[0xad, 0x04, 0xb0]).unwrap();
+        memory.write(0x1000, &[0xea, 0xea]).unwrap();
+        let mut registers = Registers::new_initialized(0x1000);
+        registers.set_i_flag(true);
+        // a sector read completes and raises the IRQ
+        memory.write(0xB000, &[0x00, 0x00, 0x00, 0x20, 0x01, 0x01]).unwrap();
+        assert!(memory.irq_pending());
+
+        // interrupts are disabled
+        execute_step(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x1001, registers.command_pointer);
+
+        registers.set_i_flag(false);
+        let log_line = execute_step(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x3000, log_line.address);
+        assert_eq!(0x3003, registers.command_pointer);
+        assert!(registers.i_flag_is_set());
+        assert_eq!(0xfc, registers.stack_pointer);
+        assert_eq!(vec![0x20, 0x01, 0x10], memory.read(0x01fd, 3).unwrap());
+        assert_eq!(2 + 7 + 4, registers.cycle_count);
+        assert!(!memory.irq_pending());
+    }
+
+    #[test]
+    fn test_execute_step_host_call() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let mut memory = Memory::new_with_ram();
+        memory.add_host_call(0x2000, move |registers: &mut Registers, memory: &mut Memory| {
+            registers.accumulator = memory.read_byte(0x0080)? * 2;
+            counter.set(counter.get() + 1);
+            Ok(())
+        });
+        memory.write(0x0080, &[0x21]).unwrap();
+        // JSR $2000; JMP $2000, NOP at $2000
+        memory.write(0x1000, &[0x20, 0x00, 0x20, 0x4c, 0x00, 0x20]).unwrap();
+        memory.write(0x2000, &[0xea]).unwrap();
+        let mut registers = Registers::new_initialized(0x1000);
+
+        execute_step(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x2000, registers.command_pointer);
+        let log_line = execute_step(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x42, registers.accumulator);
+        assert_eq!(0x1003, registers.command_pointer);
+        assert_eq!(0xff, registers.stack_pointer);
+        assert_eq!(12, registers.cycle_count);
+        assert_eq!(
+            "#0x2000: (60)          RTS                      [host call][CP=0x1003][SP=0xff][S=nv-Bdizc][6]",
+            log_line.to_string()
+        );
+        assert_eq!(1, calls.get());
+        assert!(memory.has_host_call(0x2000));
+
+        assert!(memory.remove_host_call(0x2000));
+        assert!(!memory.remove_host_call(0x2000));
+        execute_step(&mut registers, &mut memory).unwrap();
+        execute_step(&mut registers, &mut memory).unwrap();
+        assert_eq!(0x2001, registers.command_pointer);
+        assert_eq!(1, calls.get());
+    }
+
+    #[test]
+    fn test_execute_step_host_call_records() {
+        let mut memory = Memory::new_with_ram();
+        memory.add_host_call(0x2000, |registers, _memory| {
+            registers.accumulator = 0x00;
+            Ok(())
+        });
+        assert!(memory.get_host_call_records(0x2000).is_none());
+        memory.record_host_calls(0x2000);
+        // LDA #$41; STA $80; JSR $2000; LDA #$42; JSR $2000
+        memory
+            .write(0x1000, &[0xa9, 0x41, 0x85, 0x80, 0x20, 0x00, 0x20, 0xa9, 0x42, 0x20, 0x00, 0x20])
+            .unwrap();
+        let mut registers = Registers::new_initialized(0x1000);
+        while registers.command_pointer != 0x100c {
+            execute_step(&mut registers, &mut memory).unwrap();
+        }
+
+        let records = memory.get_host_call_records(0x2000).unwrap();
+        assert_eq!(2, records.len());
+        assert_eq!(0x41, records[0].registers.accumulator);
+        assert_eq!(0x42, records[1].registers.accumulator);
+        assert_eq!(0xfd, records[0].registers.stack_pointer);
+        assert_eq!(2 + 3 + 6, records[0].cycle_count);
+        assert_eq!(Some(0x41), records[1].get_byte(0x0080));
+        // the return address is on the stack
+        assert_eq!(Some(0x10), records[1].get_byte(0x01ff));
+        assert_eq!(None, records[1].get_byte(0x0200));
+        assert_eq!(0x00, registers.accumulator);
+
+        memory.remove_host_call(0x2000);
+        assert!(memory.get_host_call_records(0x2000).is_none());
+    }
+
+    #[test]
+    fn test_execute_step_host_call_error() {
+        let mut memory = Memory::new_with_ram();
+        memory.add_host_call(0x2000, |_registers, memory| {
+            memory.read_byte(0x10000)?;
+            Ok(())
+        });
+        let mut registers = Registers::new_initialized(0x2000);
+
+        let error = execute_step(&mut registers, &mut memory).unwrap_err();
+        assert_eq!(Some(0x2000), error.memory_error().unwrap().command_pointer());
+        assert_eq!(
+            "CPU Error (memory) host call at #0x2000 Could not READ 1 bytes at address 0x10000",
+            error.to_string()
+        );
+        assert_eq!(0x2000, registers.command_pointer);
+        // the host call stays in place
+        assert!(memory.has_host_call(0x2000));
+    }
+
     #[test]
     fn simulate_step_dex() {
         let mut memory = Memory::new_with_ram();
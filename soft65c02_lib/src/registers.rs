@@ -27,6 +27,7 @@ use std::fmt;
 
 pub const STACK_BASE_ADDR: usize = 0x0100;
 
+#[derive(Clone)]
 pub struct Registers {
     pub accumulator: u8,
     pub register_x: u8,
@@ -80,7 +81,7 @@ impl Registers {
         memory: &mut Memory,
         byte: u8,
     ) -> std::result::Result<(), MemoryError> {
-        memory.write(STACK_BASE_ADDR + self.stack_pointer as usize, &[byte])?;
+        memory.write_byte(STACK_BASE_ADDR + self.stack_pointer as usize, byte)?;
         let (sp, _) = self.stack_pointer.overflowing_sub(1);
         self.stack_pointer = sp;
 
@@ -90,7 +91,7 @@ impl Registers {
     pub fn stack_pull(&mut self, memory: &Memory) -> std::result::Result<u8, MemoryError> {
         let (sp, _) = self.stack_pointer.overflowing_add(1);
         self.stack_pointer = sp;
-        Ok(memory.read(STACK_BASE_ADDR + self.stack_pointer as usize, 1)?[0])
+        memory.read_byte(STACK_BASE_ADDR + self.stack_pointer as usize)
     }
 
     pub fn n_flag_is_set(&self) -> bool {
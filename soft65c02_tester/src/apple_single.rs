@@ -52,10 +52,8 @@ impl AppleSingle {
         if bytes.len() < 24 {
             return Err("File too short for header".to_string());
         }
-        for i in 8..24 {
-            if bytes[i] != 0 {
-                return Err(format!("Invalid filler byte at offset {}", i));
-            }
+        if let Some(i) = (8..24).find(|&i| bytes[i] != 0) {
+            return Err(format!("Invalid filler byte at offset {}", i));
         }
 
         // Get number of entries (2 bytes, big-endian)
@@ -126,6 +126,11 @@ impl AtariBinary {
         &self.sections
     }
 
+    /// Sections in file order, for loaders that run the INIT routines.
+    pub fn into_sections(self) -> Vec<Section> {
+        self.sections
+    }
+
     pub fn get_run_address(&self) -> usize {
         self.run_address
     }
@@ -0,0 +1,83 @@
+use crate::commands::MemorySegment;
+use std::path::Path;
+use std::fs::File;
+use std::io::Read;
+use anyhow::anyhow;
+use crate::AppResult;
+
+/// Commodore 64 program file: a 2 bytes little endian load address followed
+/// by the data to load there.
+#[derive(Debug)]
+pub struct C64Program {
+    load_address: usize,
+    data: Vec<u8>,
+}
+
+impl C64Program {
+    pub fn new(bytes: Vec<u8>) -> Result<Self, String> {
+        if bytes.len() < 2 {
+            return Err(format!("PRG file too short: {} byte(s), no load address", bytes.len()));
+        }
+        let load_address = bytes[0] as usize + 256 * (bytes[1] as usize);
+        if load_address + bytes.len() - 2 > 0x10000 {
+            return Err(format!(
+                "PRG data of {} bytes does not fit in memory from #0x{:04X}",
+                bytes.len() - 2,
+                load_address
+            ));
+        }
+
+        Ok(C64Program {
+            load_address,
+            data: bytes[2..].to_vec(),
+        })
+    }
+
+    pub fn get_load_address(&self) -> usize {
+        self.load_address
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_memory_segments(&self) -> Vec<MemorySegment> {
+        vec![MemorySegment {
+            address: self.load_address,
+            data: self.data.clone(),
+        }]
+    }
+
+    /// Creates a C64Program by reading and parsing a file
+    pub fn from_file(path: impl AsRef<Path>) -> AppResult<Self> {
+        let mut f = File::open(path)?;
+        let mut buffer = Vec::new();
+        f.read_to_end(&mut buffer)?;
+        Self::new(buffer).map_err(|e| anyhow!(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_program() {
+        let program = C64Program::new(vec![0x01, 0x08, 0x0b, 0x08, 0x0a, 0x00]).unwrap();
+        assert_eq!(0x0801, program.get_load_address());
+        assert_eq!(&[0x0b, 0x08, 0x0a, 0x00], program.get_data());
+
+        let segments = program.into_memory_segments();
+        assert_eq!(1, segments.len());
+        assert_eq!(0x0801, segments[0].address);
+        assert_eq!(vec![0x0b, 0x08, 0x0a, 0x00], segments[0].data);
+    }
+
+    #[test]
+    fn test_invalid_program() {
+        assert!(C64Program::new(vec![0x01]).unwrap_err().contains("no load address"));
+        assert!(C64Program::new(vec![0xff, 0xff, 0xea, 0xea]).unwrap_err().contains("does not fit"));
+        // a header alone loads nothing
+        assert!(C64Program::new(vec![0x00, 0xc0]).unwrap().get_data().is_empty());
+    }
+}
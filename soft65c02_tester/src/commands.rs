@@ -1,8 +1,19 @@
 use std::{fs::File, io::Read, path::PathBuf};
 
+use anyhow::anyhow;
+use serde::Serialize;
+
 use soft65c02_lib::{execute_step, AddressableIO, LogLine, Memory, Registers};
+use soft65c02_lib::devices::{
+    find_keyboard, AsciiKeyboard, BlockDevice, AY38910, DateTime, MatrixKeyboard, Ps2Keyboard, Rtc, SdCard,
+    SpiBitBang, SpiPins, Timer, HD44780,
+};
+use soft65c02_lib::memory::{AddressDecoder, MemoryError, ReadOnlyPolicy, UnmappedPolicy};
+use soft65c02_lib::machines::{apple2, atari, c64};
 
 use crate::{
+    atari_binary::Section,
+    expression::Expression,
     until_condition::{Assignment, BooleanExpression, Source, RegisterSource},
     SymbolTable,
     Disassembler,
@@ -10,7 +21,13 @@ use crate::{
     utils,
 };
 
-#[derive(Debug, Clone)]
+/*
+ * OutputToken
+ * Outcome of a command sent to the displayers. It serializes as an object
+ * whose "type" is the variant name in snake case and "data" its content.
+ */
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum OutputToken {
     Assertion {
         failure: Option<String>,
@@ -19,18 +36,35 @@ pub enum OutputToken {
     Marker {
         description: String,
     },
+    Skipped {
+        description: String,
+    },
     None,
     Run {
         loglines: Vec<LogLine>,
+        #[serde(skip)]
         symbols: Option<SymbolTable>,
     },
     TerminatedRun {
         loglines: Vec<LogLine>,
+        #[serde(skip)]
         symbols: Option<SymbolTable>,
         reason: String,
     },
+    Call {
+        loglines: Vec<LogLine>,
+        #[serde(skip)]
+        symbols: Option<SymbolTable>,
+        address: usize,
+        cycles: u64,
+    },
     Setup(Vec<String>),
+    Variable {
+        name: String,
+        value: usize,
+    },
     View(Vec<String>),
+    Warning(Vec<String>),
     ControlAction {
         function: ControllableFunction,
         enabled: bool,
@@ -42,7 +76,8 @@ pub trait Command {
 }
 
 // Enum for controllable functions
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ControllableFunction {
     TraceLogging,
 }
@@ -58,27 +93,50 @@ impl std::fmt::Display for ControllableFunction {
 #[derive(Debug)]
 pub enum CliCommand {
     Assert(AssertCommand),
-    Marker(String),
+    Marker { description: String, tags: Vec<String> },
+    Plans(PlanTable),
+    Fuzz(FuzzPlan),
+    Setup(SetupBlock),
+    Teardown(Vec<CliCommand>),
+    Include(PathBuf),
+    Let(LetCommand),
+    Deferred(DeferredCommand),
     Memory(MemoryCommand),
     None,
     Registers(RegisterCommand),
     Run(RunCommand),
+    Call(CallCommand),
+    Mock(MockCommand),
     Disassemble { start: usize, end: usize },
     Enable(ControllableFunction),
     Disable(ControllableFunction),
+    Device(DeviceCommand),
 }
 
 impl Command for CliCommand {
     fn execute(&self, registers: &mut Registers, memory: &mut Memory, symbols: &mut Option<SymbolTable>) -> AppResult<OutputToken> {
         match self {
             Self::Assert(command) => command.execute(registers, memory, symbols),
-            Self::Marker(comment) => Ok(OutputToken::Marker {
-                description: comment.to_owned(),
+            Self::Marker { description, tags: _ } => Ok(OutputToken::Marker {
+                description: description.to_owned(),
             }),
+            // the command iterator expands the tables and reads the included files in place
+            Self::Plans(table) => Err(anyhow!("plans '{}' cannot be expanded here", table.description)),
+            // the executor runs the fuzz plans
+            Self::Fuzz(plan) => Err(anyhow!("fuzz plan '{}' cannot run here", plan.description)),
+            // the executor runs the setup and teardown blocks around each plan
+            Self::Setup(_) => Err(anyhow!("setup block cannot run here")),
+            Self::Teardown(_) => Err(anyhow!("teardown block cannot run here")),
+            Self::Include(path) => Err(anyhow!("file '{}' cannot be included here", path.display())),
+            Self::Let(command) => command.execute(registers, memory, symbols),
+            // the executor parses the deferred commands again when they run
+            Self::Deferred(command) => Err(anyhow!("'{}' cannot be computed here", command.text)),
             Self::Memory(command) => command.execute(registers, memory, symbols),
             Self::None => Ok(OutputToken::None),
             Self::Registers(command) => command.execute(registers, memory, symbols),
             Self::Run(command) => command.execute(registers, memory, symbols),
+            Self::Call(command) => command.execute(registers, memory, symbols),
+            Self::Mock(command) => command.execute(registers, memory, symbols),
             Self::Disassemble { start, end } => {
                 let disassembler = Disassembler::new(memory, symbols);
                 let output = disassembler.disassemble_range(*start, *end)?;
@@ -92,6 +150,7 @@ impl Command for CliCommand {
                 function: function.clone(), 
                 enabled: false 
             }),
+            Self::Device(command) => command.execute(registers, memory, symbols),
         }
     }
 }
@@ -158,7 +217,7 @@ impl Command for RunCommand {
         }
 
         // After stopping, check if we hit any cycle limits
-        if has_cycle_limit && self.continue_condition.was_cycle_limit_hit(registers, memory) {
+        if has_cycle_limit && self.continue_condition.was_cycle_limit_hit(registers) {
             Ok(OutputToken::TerminatedRun {
                 loglines,
                 symbols: symbols.clone(),
@@ -173,6 +232,262 @@ impl Command for RunCommand {
     }
 }
 
+/// Address the routines invoked by `call` return to, the return address
+/// pushed is the one before it.
+pub const CALL_RETURN_ADDRESS: usize = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallLimit {
+    Cycles(u64),
+    Instructions(u64),
+}
+
+pub const DEFAULT_CALL_LIMIT: CallLimit = CallLimit::Cycles(1_000_000);
+
+impl CallLimit {
+    fn is_reached(&self, instructions: usize, cycles: u64) -> bool {
+        match self {
+            Self::Cycles(limit) => cycles >= *limit,
+            Self::Instructions(limit) => instructions as u64 >= *limit,
+        }
+    }
+}
+
+impl std::fmt::Display for CallLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cycles(limit) => write!(f, "{} cycles", limit),
+            Self::Instructions(limit) => write!(f, "{} instructions", limit),
+        }
+    }
+}
+
+/*
+ * CallCommand
+ * Invoke a subroutine as a JSR would: the arguments are set in the registers,
+ * a return address to CALL_RETURN_ADDRESS is pushed and the routine runs until
+ * it returns there with the stack as it was. The command pointer is then put
+ * back. The run terminates when the limit is reached, when the routine stays
+ * on an instruction or when the CPU fails.
+ */
+#[derive(Debug)]
+pub struct CallCommand {
+    pub address: usize,
+    pub arguments: Vec<Assignment>,
+    pub limit: CallLimit,
+}
+
+impl Command for CallCommand {
+    fn execute(&self, registers: &mut Registers, memory: &mut Memory, symbols: &mut Option<SymbolTable>) -> AppResult<OutputToken> {
+        let command_pointer = registers.command_pointer;
+        for argument in &self.arguments {
+            argument.execute(registers, memory)?;
+        }
+        let stack_pointer = registers.stack_pointer;
+        let return_address = CALL_RETURN_ADDRESS - 1;
+        let pushed = registers
+            .stack_push(memory, (return_address >> 8) as u8)
+            .and_then(|_| registers.stack_push(memory, return_address as u8));
+        if let Err(e) = pushed {
+            registers.stack_pointer = stack_pointer;
+            return Err(e.into());
+        }
+        registers.command_pointer = self.address;
+
+        // a routine waiting for an interrupt loops on itself, only the limit stops it
+        let start = registers.cycle_count;
+        let mut loglines: Vec<LogLine> = Vec::new();
+        let failure = loop {
+            if registers.command_pointer == CALL_RETURN_ADDRESS && registers.stack_pointer == stack_pointer {
+                break None;
+            }
+            if self.limit.is_reached(loglines.len(), registers.cycle_count - start) {
+                break Some(format!(
+                    "call to #0x{:04X} did not return within {}", self.address, self.limit
+                ));
+            }
+            match execute_step(registers, memory) {
+                Ok(line) => loglines.push(line),
+                Err(e) => {
+                    break Some(match e.memory_error() {
+                        Some(memory_error) => memory_error.to_string(),
+                        None => e.to_string(),
+                    });
+                }
+            }
+        };
+
+        // the script goes on where it was, even when the routine did not return
+        registers.command_pointer = command_pointer;
+        registers.stack_pointer = stack_pointer;
+        match failure {
+            None => {
+                Ok(OutputToken::Call {
+                    loglines,
+                    symbols: symbols.clone(),
+                    address: self.address,
+                    cycles: registers.cycle_count - start,
+                })
+            }
+            Some(reason) => Ok(OutputToken::TerminatedRun {
+                loglines,
+                symbols: symbols.clone(),
+                reason,
+            }),
+        }
+    }
+}
+
+/*
+ * LetCommand
+ * Set a variable to the value of an expression. It is computed when the
+ * command runs so it can read the registers and the memory, the commands
+ * parsed next get the value.
+ */
+#[derive(Debug)]
+pub struct LetCommand {
+    pub name: String,
+    pub expression: Expression,
+}
+
+impl Command for LetCommand {
+    fn execute(&self, registers: &mut Registers, memory: &mut Memory, _symbols: &mut Option<SymbolTable>) -> AppResult<OutputToken> {
+        let value = self
+            .expression
+            .evaluate(registers, memory)
+            .map_err(|e| anyhow!("Cannot compute variable '{}': {}", self.name, e))?;
+        if value > 0xFFFF {
+            return Err(anyhow!("Value 0x{:X} of variable '{}' is too large for 16-bit value", value, self.name));
+        }
+
+        Ok(OutputToken::Variable { name: self.name.clone(), value })
+    }
+}
+
+/*
+ * DeferredCommand
+ * Command reading a value only known when it runs: the registers, the memory
+ * or a variable whose `let` did not run yet. It is parsed again at that time,
+ * a deferred `let` leaves its variable pending.
+ */
+#[derive(Debug)]
+pub struct DeferredCommand {
+    pub text: String,
+    pub variable: Option<String>,
+}
+
+/*
+ * PlanTable
+ * Marker running the commands following it once per row of values, up to the
+ * next marker or the end of the file. Each row is a plan of its own, the
+ * parameters are set as variables at its start.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanTable {
+    pub description: String,
+    pub tags: Vec<String>,
+    pub parameters: Vec<String>,
+    pub rows: Vec<Vec<usize>>,
+}
+
+impl PlanTable {
+    /// Lines of the script running the given commands once per row, each
+    /// plan is described with the values of its parameters.
+    pub fn expand(&self, commands: &[String]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for row in &self.rows {
+            let values: Vec<String> = self
+                .parameters
+                .iter()
+                .zip(row)
+                .map(|(name, value)| match value {
+                    0..=0xFF => format!("{name}=0x{value:02X}"),
+                    _ => format!("{name}=0x{value:04X}"),
+                })
+                .collect();
+            let tags = match self.tags.is_empty() {
+                true => String::new(),
+                false => format!(" tags({})", self.tags.join(", ")),
+            };
+            lines.push(format!("marker $${} ({})$${tags}", self.description, values.join(", ")));
+            lines.extend(values.iter().map(|value| format!("let {}", value.replacen('=', " = ", 1))));
+            lines.extend(commands.iter().cloned());
+        }
+
+        lines
+    }
+}
+
+/*
+ * SetupBlock
+ * Commands run at the start of each plan following the block. With a
+ * snapshot, the registers and the memory they leave are saved the first time
+ * and restored at the start of the next plans instead of running them again.
+ */
+#[derive(Debug)]
+pub struct SetupBlock {
+    pub commands: Vec<CliCommand>,
+    pub snapshot: bool,
+}
+
+/*
+ * FuzzPlan
+ * Marker running the commands following it many times with random values,
+ * up to the next marker or the end of the file. Each run starts with fresh
+ * registers and memory and draws new values for the `random` keywords.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzPlan {
+    pub description: String,
+    pub tags: Vec<String>,
+    pub runs: usize,
+    pub commands: Vec<String>,
+    /// File holding the plan, the files its commands include are looked for
+    /// relative to it.
+    pub path: Option<PathBuf>,
+}
+
+/*
+ * MockCommand
+ * Replace the subroutine at an address by a host call setting the given
+ * registers, a JSR or a JMP there returns at once. The calls are recorded so
+ * assertions can count them and check the registers and the zero and stack
+ * pages they were made with.
+ */
+#[derive(Debug)]
+pub struct MockCommand {
+    pub address: usize,
+    pub returns: Vec<Assignment>,
+}
+
+impl Command for MockCommand {
+    fn execute(&self, _registers: &mut Registers, memory: &mut Memory, _symbols: &mut Option<SymbolTable>) -> AppResult<OutputToken> {
+        let returns = self.returns.clone();
+        let address = self.address;
+        memory.add_host_call(address, move |registers, memory| {
+            for assignment in &returns {
+                assignment
+                    .execute(registers, memory)
+                    .map_err(|e| MemoryError::Device(address, e.to_string()))?;
+            }
+            Ok(())
+        });
+        memory.record_host_calls(self.address);
+        let output = if self.returns.is_empty() {
+            format!("mock at #0x{:04X} records its calls", self.address)
+        } else {
+            let values: Vec<String> = self
+                .returns
+                .iter()
+                .map(|assignment| format!("{}={}", assignment.destination, assignment.source))
+                .collect();
+            format!("mock at #0x{:04X} returns {}", self.address, values.join(" "))
+        };
+
+        Ok(OutputToken::Setup(vec![output]))
+    }
+}
+
 impl BooleanExpression {
     fn contains_cycle_limit(&self) -> bool {
         match self {
@@ -187,10 +502,13 @@ impl BooleanExpression {
             Self::Not(expr) => expr.contains_cycle_limit(),
             Self::Value(_) => false,
             Self::MemorySequence(_, _) => false,
+            Self::LcdLine(_, _) => false,
+            Self::SoundLog(_) => false,
+            Self::ScreenText(_) | Self::ConsoleOutput(_) => false,
         }
     }
 
-    fn was_cycle_limit_hit(&self, registers: &Registers, memory: &Memory) -> bool {
+    fn was_cycle_limit_hit(&self, registers: &Registers) -> bool {
         match self {
             // For cycle limits, we want to detect when the condition becomes false
             // This means we've hit the limit
@@ -245,12 +563,12 @@ impl BooleanExpression {
                 false
             }
             Self::And(left, right) => {
-                left.was_cycle_limit_hit(registers, memory) || right.was_cycle_limit_hit(registers, memory)
+                left.was_cycle_limit_hit(registers) || right.was_cycle_limit_hit(registers)
             }
             Self::Or(left, right) => {
-                left.was_cycle_limit_hit(registers, memory) || right.was_cycle_limit_hit(registers, memory)
+                left.was_cycle_limit_hit(registers) || right.was_cycle_limit_hit(registers)
             }
-            Self::Not(expr) => expr.was_cycle_limit_hit(registers, memory),
+            Self::Not(expr) => expr.was_cycle_limit_hit(registers),
             _ => false
         }
     }
@@ -317,6 +635,7 @@ pub struct MemorySegment {
 #[derive(Debug)]
 pub enum MemoryCommand {
     Flush,
+    Map,
     Load { address: usize, filepath: PathBuf },
     Write { address: usize, bytes: Vec<u8> },
     Fill { start: usize, end: usize, value: u8 },
@@ -325,6 +644,8 @@ pub enum MemoryCommand {
     AddSymbol { name: String, value: u16 },
     RemoveSymbol { name: String },
     Show { address: usize, length: usize, width: Option<usize>, description: Option<String> },
+    SetUnmappedPolicy { policy: UnmappedPolicy },
+    SetReadOnlyPolicy { policy: ReadOnlyPolicy },
 }
 
 impl Command for MemoryCommand {
@@ -334,6 +655,38 @@ impl Command for MemoryCommand {
                 *memory = Memory::new_with_ram();
                 Vec::new()
             }
+            Self::Map => {
+                let subsystems = memory.get_subsystems();
+                memory
+                    .get_memory_map()
+                    .iter()
+                    .map(|region| {
+                        let owner = match region.subsystem {
+                            Some(index) => {
+                                let sub = &subsystems[index];
+                                let mut owner = format!("{} ({}", sub.name, sub.kind);
+                                if sub.read_only {
+                                    owner.push_str(", read-only");
+                                }
+                                match sub.decoder {
+                                    AddressDecoder::Full => (),
+                                    AddressDecoder::Mirror(period) => owner.push_str(&format!(", mirrored every {} bytes", period)),
+                                    AddressDecoder::Mask(mask) => owner.push_str(&format!(", address mask = #0x{:04X}", mask)),
+                                }
+                                owner.push(')');
+                                owner
+                            }
+                            None => "unmapped".to_string(),
+                        };
+                        let mut line = format!("#0x{:04X} → #0x{:04X}  {}", region.start, region.end, owner);
+                        if !region.shadowed.is_empty() {
+                            let shadowed: Vec<&str> = region.shadowed.iter().map(|&index| subsystems[index].name.as_str()).collect();
+                            line.push_str(&format!(", shadows {}", shadowed.join(", ")));
+                        }
+                        line
+                    })
+                    .collect()
+            }
             Self::Write { address, bytes } => match bytes.len() {
                 0 => vec!["nothing was written".to_string()],
                 1 => {
@@ -423,7 +776,7 @@ impl Command for MemoryCommand {
                 }
             },
             Self::Show { address, length, width, description } => {
-                let data = memory.read(*address, *length)?;
+                let data = memory.peek(*address, *length)?;
                 let mut output = Vec::new();
                 if let Some(desc) = description {
                     output.push(desc.clone());
@@ -432,6 +785,278 @@ impl Command for MemoryCommand {
                 output.push(format!("\n{}", utils::format_hex_dump_with_width(*address, &data, display_width)));
                 output
             }
+            Self::SetUnmappedPolicy { policy } => {
+                memory.set_unmapped_policy(*policy);
+                let description = match policy {
+                    UnmappedPolicy::Error => "error".to_string(),
+                    UnmappedPolicy::OpenBus => "open bus".to_string(),
+                    UnmappedPolicy::Fixed(value) => format!("fixed value 0x{:02X}", value),
+                    UnmappedPolicy::Warn => "open bus with warnings".to_string(),
+                };
+                vec![format!("unmapped memory policy set to {}", description)]
+            }
+            Self::SetReadOnlyPolicy { policy } => {
+                memory.set_read_only_policy(*policy);
+                let description = match policy {
+                    ReadOnlyPolicy::Error => "error",
+                    ReadOnlyPolicy::Ignore => "ignore",
+                    ReadOnlyPolicy::Warn => "ignore with warnings",
+                };
+                vec![format!("read-only memory policy set to {}", description)]
+            }
+        };
+
+        Ok(OutputToken::Setup(output))
+    }
+}
+
+/// Cycles between two scripted keystrokes, a bit more than a 60Hz frame at
+/// 1MHz so keyboards scanned on the frame interrupt see each key.
+pub const DEFAULT_KEYBOARD_DELAY: u64 = 20000;
+
+/// Frequency of a sound chip declared without a clock, the CPU one.
+pub const DEFAULT_SOUND_CLOCK: u64 = 1_000_000;
+
+/// Sample rate the sound chip is rendered at when none is given.
+pub const DEFAULT_SOUND_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardKind {
+    Ascii,
+    Matrix,
+    Ps2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    Apple,
+    Atari,
+    C64,
+}
+
+/// Cycles an INIT routine of an Atari executable may run before the load is
+/// aborted.
+pub const XEX_INIT_CYCLE_LIMIT: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundFormat {
+    Wav,
+    Log,
+}
+
+#[derive(Debug)]
+pub enum DeviceCommand {
+    AddLcd { address: usize, columns: usize, rows: usize },
+    AddSdCard { address: usize, filepath: PathBuf, pins: SpiPins },
+    AddDisk { address: usize, filepath: PathBuf },
+    AddTimer { address: usize },
+    AddRtc { address: usize },
+    SetRtc { datetime: DateTime },
+    AddKeyboard { address: usize, kind: KeyboardKind },
+    TypeKeys { keys: Vec<u8>, delay: u64 },
+    AddSound { address: usize, clock: u64, sample_rate: u32 },
+    SaveSound { format: SoundFormat, filepath: PathBuf },
+    AddMachine { machine: Machine },
+    LoadXex { sections: Vec<Section> },
+}
+
+impl DeviceCommand {
+    /*
+     * Push the address before WARMSV as a return address, the routine
+     * returning there ends in the WARMSV loop.
+     */
+    fn push_warmsv(registers: &mut Registers, memory: &mut Memory) -> AppResult<()> {
+        let address = atari::WARMSV - 1;
+        registers.stack_push(memory, (address >> 8) as u8)?;
+        registers.stack_push(memory, address as u8)?;
+
+        Ok(())
+    }
+
+    /*
+     * Call an INIT routine as the DOS loader does and run it until it
+     * returns, give the cycles it took.
+     */
+    fn call_init(registers: &mut Registers, memory: &mut Memory, address: usize) -> AppResult<u64> {
+        Self::push_warmsv(registers, memory)?;
+        registers.command_pointer = address;
+        let start = registers.cycle_count;
+        while registers.command_pointer != atari::WARMSV {
+            if registers.cycle_count - start > XEX_INIT_CYCLE_LIMIT {
+                return Err(anyhow!(
+                    "INIT routine at #0x{:04X} did not return after {} cycles", address, XEX_INIT_CYCLE_LIMIT
+                ));
+            }
+            execute_step(registers, memory)
+                .map_err(|e| anyhow!("INIT routine at #0x{:04X} failed: {}", address, e))?;
+        }
+
+        Ok(registers.cycle_count - start)
+    }
+}
+
+impl Command for DeviceCommand {
+    fn execute(&self, registers: &mut Registers, memory: &mut Memory, _symbols: &mut Option<SymbolTable>) -> AppResult<OutputToken> {
+        let output = match self {
+            Self::AddLcd { address, columns, rows } => {
+                memory.add_subsystem("LCD", *address, HD44780::new(*columns, *rows));
+                vec![format!("HD44780 LCD {}x{} added at #0x{:04X}", columns, rows, address)]
+            }
+            Self::AddSdCard { address, filepath, pins } => {
+                let card = SdCard::open(filepath)
+                    .map_err(|e| anyhow!("Could not open SD card image '{}': {}", filepath.display(), e))?;
+                let blocks = card.get_blocks();
+                memory.add_subsystem("SDCARD", *address, SpiBitBang::new(*pins, card));
+                vec![format!(
+                    "SD card '{}' ({} blocks) added at #0x{:04X}, SCK={} MOSI={} MISO={} CS={}",
+                    filepath.display(), blocks, address, pins.sck, pins.mosi, pins.miso, pins.cs
+                )]
+            }
+            Self::AddDisk { address, filepath } => {
+                let disk = BlockDevice::open(filepath)
+                    .map_err(|e| anyhow!("Could not open disk image '{}': {}", filepath.display(), e))?;
+                let blocks = disk.get_blocks();
+                memory.add_subsystem("DISK", *address, disk);
+                vec![format!("disk '{}' ({} sectors) added at #0x{:04X}", filepath.display(), blocks, address)]
+            }
+            Self::AddTimer { address } => {
+                memory.add_subsystem("TIMER", *address, Timer::new());
+                vec![format!("timer added at #0x{:04X}", address)]
+            }
+            Self::AddRtc { address } => {
+                memory.add_subsystem("RTC", *address, Rtc::default());
+                vec![format!("RTC added at #0x{:04X}", address)]
+            }
+            Self::SetRtc { datetime } => {
+                memory
+                    .find_device_mut::<Rtc>()
+                    .ok_or_else(|| anyhow!("No RTC device found, declare one with 'device rtc'"))?
+                    .set_time(*datetime);
+                vec![format!("RTC set to {}", datetime)]
+            }
+            Self::AddKeyboard { address, kind } => {
+                let description = match kind {
+                    KeyboardKind::Ascii => {
+                        memory.add_subsystem("KEYBOARD", *address, AsciiKeyboard::new());
+                        "ASCII"
+                    }
+                    KeyboardKind::Matrix => {
+                        memory.add_subsystem("KEYBOARD", *address, MatrixKeyboard::new());
+                        "matrix"
+                    }
+                    KeyboardKind::Ps2 => {
+                        memory.add_subsystem("KEYBOARD", *address, Ps2Keyboard::default());
+                        "PS/2"
+                    }
+                };
+                vec![format!("{} keyboard added at #0x{:04X}", description, address)]
+            }
+            Self::TypeKeys { keys, delay } => {
+                find_keyboard(memory)
+                    .ok_or_else(|| anyhow!("No keyboard device found, declare one with 'device keyboard'"))?
+                    .type_keys(keys, *delay);
+                vec![format!("{} key(s) queued, one every {} cycles", keys.len(), delay)]
+            }
+            Self::AddSound { address, clock, sample_rate } => {
+                memory.add_subsystem("SOUND", *address, AY38910::new(*clock, *sample_rate));
+                vec![format!(
+                    "AY-3-8910 sound chip added at #0x{:04X}, clocked at {}Hz, rendered at {}Hz",
+                    address, clock, sample_rate
+                )]
+            }
+            Self::SaveSound { format, filepath } => {
+                let chip = memory
+                    .find_device::<AY38910>()
+                    .ok_or_else(|| anyhow!("No sound device found, declare one with 'device sound'"))?;
+                let result = match format {
+                    SoundFormat::Wav => chip.save_wav(filepath),
+                    SoundFormat::Log => std::fs::File::create(filepath)
+                        .and_then(|mut file| chip.write_register_log(&mut file)),
+                };
+                result.map_err(|e| anyhow!("Could not write '{}': {}", filepath.display(), e))?;
+                match format {
+                    SoundFormat::Wav => vec![format!(
+                        "{} samples at {}Hz saved to '{}'",
+                        chip.get_samples().len(), chip.get_sample_rate(), filepath.display()
+                    )],
+                    SoundFormat::Log => vec![format!(
+                        "{} register writes saved to '{}'",
+                        chip.get_register_log().len(), filepath.display()
+                    )],
+                }
+            }
+            Self::AddMachine { machine: Machine::Apple } => {
+                apple2::install(memory);
+                vec![
+                    "Apple II profile installed".to_string(),
+                    format!("keyboard at #0x{:04X}, strobe at #0x{:04X}", apple2::KEYBOARD_ADDR, apple2::KEYBOARD_ADDR + 0x10),
+                    format!("soft switches at #0x{:04X}", apple2::SOFT_SWITCHES_ADDR),
+                    format!("console at #0x{:04X}", apple2::CONSOLE_ADDR),
+                    format!("monitor ROM stub at #0x{:04X}", apple2::MONITOR_ROM_ADDR),
+                ]
+            }
+            Self::AddMachine { machine: Machine::Atari } => {
+                atari::install(memory)?;
+                vec![
+                    "Atari 8-bit profile installed".to_string(),
+                    format!(
+                        "GTIA at #0x{:04X}, POKEY at #0x{:04X}, PIA at #0x{:04X}, ANTIC at #0x{:04X}",
+                        atari::GTIA_ADDR, atari::POKEY_ADDR, atari::PIA_ADDR, atari::ANTIC_ADDR
+                    ),
+                    format!("CIO port at #0x{:04X}, console at #0x{:04X}", atari::CIO_ADDR, atari::CONSOLE_ADDR),
+                    format!("OS ROM stub at #0x{:04X}", atari::OS_ROM_ADDR),
+                ]
+            }
+            Self::AddMachine { machine: Machine::C64 } => {
+                c64::install(memory)?;
+                vec![
+                    "Commodore 64 profile installed".to_string(),
+                    format!("keyboard at #0x{:04X}, console at #0x{:04X}", c64::KEYBOARD_ADDR, c64::CONSOLE_ADDR),
+                    format!(
+                        "KERNAL ROM stub at #0x{:04X}, {} KERNAL routines run by the host",
+                        c64::KERNAL_ROM_ADDR,
+                        c64::kernal_routines().len()
+                    ),
+                ]
+            }
+            Self::LoadXex { sections } => {
+                if memory.find_device::<atari::Cio>().is_none() {
+                    return Err(anyhow!("No Atari profile found, declare one with 'machine atari'"));
+                }
+                let mut output = Vec::new();
+                let mut run_address = None;
+                for section in sections {
+                    match section {
+                        Section::Data { start_address, data } => {
+                            memory.write(*start_address, data)?;
+                            output.push(format!(
+                                "loaded {} bytes at #0x{:04X}", data.len(), start_address
+                            ));
+                        }
+                        Section::Init { init_address } => {
+                            memory.write(atari::INITAD, &[*init_address as u8, (*init_address >> 8) as u8])?;
+                            let cycles = Self::call_init(registers, memory, *init_address)?;
+                            output.push(format!(
+                                "INIT routine at #0x{:04X} returned after {} cycles", init_address, cycles
+                            ));
+                        }
+                        Section::Run { run_address: address } => {
+                            memory.write(atari::RUNAD, &[*address as u8, (*address >> 8) as u8])?;
+                            run_address = Some(*address);
+                        }
+                    }
+                }
+                match run_address {
+                    Some(address) => {
+                        Self::push_warmsv(registers, memory)?;
+                        registers.command_pointer = address;
+                        output.push(format!("CP set to the run address #0x{:04X}", address));
+                    }
+                    None => output.push("no run address".to_string()),
+                }
+
+                output
+            }
         };
 
         Ok(OutputToken::Setup(output))
@@ -642,6 +1267,29 @@ mod run_command_tests {
         assert!(registers.cycle_count >= 0x100, "Should have executed at least 256 cycles");
     }
 
+    #[test]
+    fn test_run_memory_error() {
+        let mut registers = Registers::new_initialized(0x1234);
+        let mut memory = Memory::default();
+        memory.add_subsystem("RAM", 0x0000, soft65c02_lib::memory::RAM::default());
+        memory.add_subsystem("BASIC", 0xC000, soft65c02_lib::memory::ROM::new(vec![0x00; 0x2000]));
+        // LDA #$01 / NOP / NOP / STA $C000
+        memory.write(0x1230, &[0xa9, 0x01, 0xea, 0xea, 0x8d, 0x00, 0xc0]).unwrap();
+        let command = RunCommand {
+            stop_condition: BooleanExpression::Value(false),
+            continue_condition: BooleanExpression::Value(true),
+            start_address: Some(RunAddress::Memory(0x1230)),
+        };
+
+        let error = command.execute(&mut registers, &mut memory, &mut None).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("STA $C000 at #0x1234 wrote 1 byte(s) to read-only memory at #0xC000 in subsystem 'BASIC'"),
+            "{error}"
+        );
+    }
+
     #[test]
     fn test_run_with_cycle_limit() {
         let mut registers = Registers::new_initialized(0x1000);
@@ -870,6 +1518,217 @@ mod run_command_tests {
     }
 }
 
+#[cfg(test)]
+mod call_command_tests {
+    use soft65c02_lib::AddressableIO;
+
+    use super::*;
+
+    fn call(address: usize, arguments: Vec<Assignment>, limit: CallLimit) -> CallCommand {
+        CallCommand { address, arguments, limit }
+    }
+
+    #[test]
+    fn test_call_returns() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        // 2000: JSR $2010; RTS, 2010: CLC; ADC #$01; RTS
+        memory.write(0x2000, &[0x20, 0x10, 0x20, 0x60]).unwrap();
+        memory.write(0x2010, &[0x18, 0x69, 0x01, 0x60]).unwrap();
+        let command = call(
+            0x2000,
+            vec![Assignment::new(Source::Value(0x41), RegisterSource::Accumulator)],
+            DEFAULT_CALL_LIMIT,
+        );
+
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        match token {
+            OutputToken::Call { loglines, address, cycles, .. } => {
+                assert_eq!(5, loglines.len());
+                assert_eq!(0x2000, address);
+                assert_eq!(6 + 2 + 2 + 6 + 6, cycles);
+            }
+            v => panic!("Expected Call token, got {:?}", v),
+        }
+        assert_eq!(0x42, registers.accumulator);
+        assert_eq!(0x1000, registers.command_pointer);
+        assert_eq!(0xff, registers.stack_pointer);
+        assert_eq!(vec![0xfe, 0xff], memory.read(0x01fe, 2).unwrap());
+    }
+
+    #[test]
+    fn test_call_limits() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        // 2000: INX; JMP $2000, 2010: JMP $2010
+        memory.write(0x2000, &[0xe8, 0x4c, 0x00, 0x20]).unwrap();
+        memory.write(0x2010, &[0x4c, 0x10, 0x20]).unwrap();
+
+        let token = call(0x2000, Vec::new(), CallLimit::Instructions(10))
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        assert!(matches!(
+            token,
+            OutputToken::TerminatedRun { loglines, reason, .. }
+                if loglines.len() == 10 && reason == *"call to #0x2000 did not return within 10 instructions"
+        ));
+        assert_eq!(5, registers.register_x);
+
+        registers.cycle_count = 0;
+        let token = call(0x2000, Vec::new(), CallLimit::Cycles(100))
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        assert!(matches!(token, OutputToken::TerminatedRun { .. }));
+        assert!((100..105).contains(&registers.cycle_count));
+
+        assert_eq!((0x1000, 0xff), (registers.command_pointer, registers.stack_pointer));
+
+        // a routine looping on itself, as one waiting for an interrupt, runs up to the limit
+        let token = call(0x2010, Vec::new(), CallLimit::Instructions(20))
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        assert!(matches!(
+            token,
+            OutputToken::TerminatedRun { loglines, reason, .. }
+                if loglines.len() == 20 && reason == *"call to #0x2010 did not return within 20 instructions"
+        ));
+        assert_eq!((0x1000, 0xff), (registers.command_pointer, registers.stack_pointer));
+    }
+}
+
+#[cfg(test)]
+mod let_command_tests {
+    use soft65c02_lib::AddressableIO;
+
+    use super::*;
+    use crate::expression::Operator;
+
+    #[test]
+    fn test_let() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        memory.write(0xfffc, &[0x00, 0x80]).unwrap();
+        registers.register_x = 0x03;
+        // word #0xFFFC + X
+        let command = LetCommand {
+            name: "entry".to_string(),
+            expression: Expression::from_infix(
+                vec![
+                    Expression::Word(Box::new(Expression::Value(0xfffc))),
+                    Expression::Register(RegisterSource::RegisterX),
+                ],
+                vec![Operator::Add],
+            ),
+        };
+
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        assert!(matches!(token, OutputToken::Variable { name, value } if name == *"entry" && value == 0x8003));
+
+        memory.write(0xfffc, &[0xff, 0xff]).unwrap();
+        let message = command.execute(&mut registers, &mut memory, &mut None).unwrap_err().to_string();
+        assert_eq!("Value 0x10002 of variable 'entry' is too large for 16-bit value", message);
+    }
+}
+
+#[cfg(test)]
+mod plan_table_tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let table = PlanTable {
+            description: "increment".to_string(),
+            tags: Vec::new(),
+            parameters: vec!["a_in".to_string(), "expected".to_string()],
+            rows: vec![vec![0x01, 0x02], vec![0x7f, 0x1234]],
+        };
+        let commands = vec!["registers set A=$a_in".to_string(), "assert A=$expected $$A incremented$$".to_string()];
+
+        let lines = table.expand(&commands);
+
+        assert_eq!(
+            vec![
+                "marker $$increment (a_in=0x01, expected=0x02)$$",
+                "let a_in = 0x01",
+                "let expected = 0x02",
+                "registers set A=$a_in",
+                "assert A=$expected $$A incremented$$",
+                "marker $$increment (a_in=0x7F, expected=0x1234)$$",
+                "let a_in = 0x7F",
+                "let expected = 0x1234",
+                "registers set A=$a_in",
+                "assert A=$expected $$A incremented$$",
+            ],
+            lines
+        );
+        let message = CliCommand::Plans(table).execute(&mut Registers::new(0x0000), &mut Memory::new_with_ram(), &mut None).unwrap_err();
+        assert_eq!("plans 'increment' cannot be expanded here", message.to_string());
+    }
+}
+
+#[cfg(test)]
+mod mock_command_tests {
+    use soft65c02_lib::AddressableIO;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_returns() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        // 1000: LDA #$41; JSR $2000; STA $80; LDA #$42; JSR $2000; RTS, 2000: BRK
+        memory
+            .write(0x1000, &[0xa9, 0x41, 0x20, 0x00, 0x20, 0x85, 0x80, 0xa9, 0x42, 0x20, 0x00, 0x20, 0x60])
+            .unwrap();
+        let command = MockCommand {
+            address: 0x2000,
+            returns: vec![
+                Assignment::new(Source::Value(0x00), RegisterSource::Accumulator),
+                Assignment::new(Source::Value(0x07), RegisterSource::RegisterX),
+            ],
+        };
+
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        assert!(matches!(token, OutputToken::Setup(s) if s[0] == *"mock at #0x2000 returns A=0x00 X=0x07"));
+        let token = CallCommand { address: 0x1000, arguments: Vec::new(), limit: DEFAULT_CALL_LIMIT }
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        assert!(matches!(token, OutputToken::Call { .. }));
+
+        assert_eq!((0x00, 0x07), (registers.accumulator, registers.register_x));
+        assert_eq!(0x00, memory.read_byte(0x80).unwrap());
+        let records = memory.get_host_call_records(0x2000).unwrap();
+        assert_eq!(vec![0x41, 0x42], records.iter().map(|r| r.registers.accumulator).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_mock_record() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        let command = MockCommand { address: 0xffd2, returns: Vec::new() };
+
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        assert!(matches!(token, OutputToken::Setup(s) if s[0] == *"mock at #0xFFD2 records its calls"));
+        assert!(memory.has_host_call(0xffd2));
+        assert_eq!(0, memory.get_host_call_records(0xffd2).unwrap().len());
+    }
+
+    #[test]
+    fn test_mock_returns_error() {
+        let mut registers = Registers::new_initialized(0x2000);
+        let mut memory = Memory::new_with_ram();
+        let command = MockCommand {
+            address: 0x2000,
+            returns: vec![Assignment::new(Source::Value(0x100), RegisterSource::Accumulator)],
+        };
+
+        command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        let error = execute_step(&mut registers, &mut memory).unwrap_err();
+        assert!(error.to_string().ends_with("Value 256 cannot fit in 8 bits destination."));
+        assert_eq!(0x2000, registers.command_pointer);
+    }
+}
+
 #[cfg(test)]
 mod register_command_tests {
     use crate::until_condition::{RegisterSource, Source};
@@ -964,6 +1823,8 @@ mod memory_command_tests {
     use soft65c02_lib::AddressableIO;
 
     use super::*;
+    use crate::c64_prg::C64Program;
+    use crate::test_utils::fixture_directory;
 
     #[test]
     fn test_flush_command() {
@@ -977,6 +1838,306 @@ mod memory_command_tests {
         assert!(matches!(token, OutputToken::Setup(s) if s.is_empty()));
     }
 
+    #[test]
+    fn test_policy_commands() {
+        let mut registers = Registers::new_initialized(0x0000);
+        let mut memory = Memory::new_with_ram();
+        let command = MemoryCommand::SetUnmappedPolicy { policy: UnmappedPolicy::Fixed(0xea) };
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+
+        assert!(matches!(token, OutputToken::Setup(v) if v[0] == *"unmapped memory policy set to fixed value 0xEA"));
+        assert_eq!(UnmappedPolicy::Fixed(0xea), memory.get_unmapped_policy());
+
+        let command = MemoryCommand::SetReadOnlyPolicy { policy: ReadOnlyPolicy::Warn };
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+
+        assert!(matches!(token, OutputToken::Setup(v) if v[0] == *"read-only memory policy set to ignore with warnings"));
+        assert_eq!(ReadOnlyPolicy::Warn, memory.get_read_only_policy());
+    }
+
+    #[test]
+    fn test_device_lcd_command() {
+        let mut registers = Registers::new_initialized(0x0000);
+        let mut memory = Memory::new_with_ram();
+        let command = DeviceCommand::AddLcd { address: 0xB000, columns: 20, rows: 4 };
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+
+        assert!(matches!(token, OutputToken::Setup(v) if v[0] == *"HD44780 LCD 20x4 added at #0xB000"));
+        assert_eq!((20, 4), memory.find_device::<HD44780>().unwrap().get_dimensions());
+        assert_eq!(Some("LCD"), memory.get_subsystems().last().map(|sub| sub.name.as_str()));
+    }
+
+    #[test]
+    fn test_device_sdcard_command() {
+        let directory = fixture_directory("sdcard");
+        let filepath = directory.join("card.img");
+        std::fs::write(&filepath, vec![0xA5; 1024]).unwrap();
+        let mut registers = Registers::new_initialized(0x0000);
+        let mut memory = Memory::new_with_ram();
+        let command = DeviceCommand::AddSdCard { address: 0xB000, filepath: filepath.clone(), pins: SpiPins::default() };
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(matches!(token, OutputToken::Setup(v) if v[0].ends_with("(2 blocks) added at #0xB000, SCK=0 MOSI=1 MISO=7 CS=2")));
+        assert!(memory.find_device::<SpiBitBang>().is_some());
+        // CMD0 bit banged through the port, the card answers idle
+        let mut response = Vec::new();
+        memory.write_byte(0xB000, 0x00).unwrap();
+        for byte in [0x40, 0x00, 0x00, 0x00, 0x00, 0x95, 0xFF, 0xFF] {
+            let mut input = 0;
+            for bit in (0..8).rev() {
+                let mosi = ((byte >> bit) & 1) << 1;
+                memory.write_byte(0xB000, mosi).unwrap();
+                memory.write_byte(0xB000, mosi | 0x01).unwrap();
+                input = (input << 1) | (memory.read_byte(0xB000).unwrap() >> 7);
+            }
+            memory.write_byte(0xB000, 0x00).unwrap();
+            response.push(input);
+        }
+        assert_eq!(vec![0xFF, 0x01], response[6..]);
+    }
+
+    #[test]
+    fn test_device_disk_command() {
+        let directory = fixture_directory("disk");
+        let filepath = directory.join("disk.img");
+        std::fs::write(&filepath, vec![0xA5; 1024]).unwrap();
+        let mut registers = Registers::new_initialized(0x0000);
+        let mut memory = Memory::new_with_ram();
+        let command = DeviceCommand::AddDisk { address: 0xB100, filepath: filepath.clone() };
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+
+        assert!(matches!(token, OutputToken::Setup(v) if v[0].ends_with("(2 sectors) added at #0xB100")));
+        // write sector 1 from #0x2000, read it back at #0x3000
+        memory.write(0x2000, &[0x5A; 512]).unwrap();
+        memory.write(0xB100, &[0x01, 0x00, 0x00, 0x20, 0x02]).unwrap();
+        memory.write(0xB102, &[0x00, 0x30, 0x01]).unwrap();
+        let content = std::fs::read(&filepath).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(0x80, memory.read_byte(0xB104).unwrap());
+        assert_eq!(vec![0x5A; 512], memory.read(0x3000, 512).unwrap());
+        assert_eq!(vec![0xA5; 512], content[..512]);
+        assert_eq!(vec![0x5A; 512], content[512..]);
+    }
+
+    #[test]
+    fn test_rtc_set_command() {
+        let mut registers = Registers::new_initialized(0x0000);
+        let mut memory = Memory::new_with_ram();
+        let command = DeviceCommand::SetRtc { datetime: "2024-01-01T00:00:00".parse().unwrap() };
+        assert!(command.execute(&mut registers, &mut memory, &mut None).is_err());
+
+        DeviceCommand::AddRtc { address: 0xB210 }.execute(&mut registers, &mut memory, &mut None).unwrap();
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        assert!(matches!(token, OutputToken::Setup(v) if v[0] == *"RTC set to 2024-01-01T00:00:00"));
+        assert_eq!(vec![0x00, 0x00, 0x00, 0x01, 0x01, 0xE8, 0x07], memory.read(0xB210, 7).unwrap());
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        DeviceCommand::AddTimer { address: 0xB200 }.execute(&mut registers, &mut memory, &mut None).unwrap();
+        // IRQ handler at #0x3000 clears the timer status, main program loops
+        memory.write(0xFFFE, &[0x00, 0x30]).unwrap();
+        memory.write(0x3000, &[0x8d, 0x05, 0xb2, 0xe8, 0x40]).unwrap();
+        memory.write(0x1000, &[0x80, 0xfe]).unwrap();
+        memory.write(0xB200, &[0x64, 0x00]).unwrap();
+        memory.write_byte(0xB204, 0x03).unwrap();
+        registers.set_i_flag(false);
+        for _ in 0..100 {
+            execute_step(&mut registers, &mut memory).unwrap();
+        }
+
+        // the timer expires every 100 cycles, 100 steps run about 330 cycles
+        assert_eq!(3, registers.register_x);
+        assert_eq!(0x1000, registers.command_pointer);
+    }
+
+    #[test]
+    fn test_keyboard_type_command() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        let command = DeviceCommand::TypeKeys { keys: b"HI".to_vec(), delay: 100 };
+        assert!(command.execute(&mut registers, &mut memory, &mut None).is_err());
+
+        DeviceCommand::AddKeyboard { address: 0xC000, kind: KeyboardKind::Ascii }
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        assert!(matches!(token, OutputToken::Setup(v) if v[0] == *"2 key(s) queued, one every 100 cycles"));
+
+        // wait for a key, store it at #0x0200,X and clear the strobe
+        // 1000: LDA $C000; BPL $1000; STA $0200,X; STA $C010; INX; BRA $1000
+        memory
+            .write(0x1000, &[0xad, 0x00, 0xc0, 0x10, 0xfb, 0x9d, 0x00, 0x02, 0x8d, 0x10, 0xc0, 0xe8, 0x80, 0xf2])
+            .unwrap();
+        for _ in 0..200 {
+            execute_step(&mut registers, &mut memory).unwrap();
+        }
+        assert_eq!(vec![b'H' | 0x80, b'I' | 0x80], memory.read(0x0200, 2).unwrap());
+    }
+
+    #[test]
+    fn test_sound_commands() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        let directory = fixture_directory("sound");
+        let filepath = directory.join("sound.wav");
+        let save = DeviceCommand::SaveSound { format: SoundFormat::Wav, filepath: filepath.clone() };
+        assert!(save.execute(&mut registers, &mut memory, &mut None).is_err());
+
+        let command = DeviceCommand::AddSound { address: 0xB300, clock: 1_000_000, sample_rate: 22050 };
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        assert!(matches!(
+            token,
+            OutputToken::Setup(v) if v[0] == *"AY-3-8910 sound chip added at #0xB300, clocked at 1000000Hz, rendered at 22050Hz"
+        ));
+        // 1000: LDA #$0F; STA $B308; NOP
+        memory.write(0x1000, &[0xa9, 0x0f, 0x8d, 0x08, 0xb3, 0xea]).unwrap();
+        for _ in 0..3 {
+            execute_step(&mut registers, &mut memory).unwrap();
+        }
+        let token = save.execute(&mut registers, &mut memory, &mut None).unwrap();
+        let content = std::fs::read(&filepath).unwrap();
+
+        assert!(matches!(token, OutputToken::Setup(v) if v[0].starts_with("0 samples at 22050Hz")));
+        assert_eq!(b"RIFF", &content[0..4]);
+
+        let filepath = filepath.with_extension("log");
+        DeviceCommand::SaveSound { format: SoundFormat::Log, filepath: filepath.clone() }
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        let content = std::fs::read_to_string(&filepath).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!("2: R08 = 0x0F\n", content);
+    }
+
+    #[test]
+    fn test_machine_apple() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        let token = DeviceCommand::AddMachine { machine: Machine::Apple }
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        assert!(matches!(token, OutputToken::Setup(v) if v.len() == 5));
+
+        // 1000: LDX #0; LDA $1010,X; BEQ +6; JSR COUT; INX; BNE $1002; JMP MONZ
+        memory
+            .write(0x1000, &[0xa2, 0x00, 0xbd, 0x10, 0x10, 0xf0, 0x06, 0x20, 0xed, 0xfd, 0xe8, 0xd0, 0xf5, 0x4c, 0x69, 0xff])
+            .unwrap();
+        memory.write(0x1010, &[0xC8, 0xC9, 0x8D, 0x00]).unwrap();
+        let command = RunCommand {
+            stop_condition: BooleanExpression::Value(false),
+            continue_condition: BooleanExpression::Value(true),
+            start_address: None,
+        };
+        command.execute(&mut registers, &mut memory, &mut None).unwrap();
+
+        assert_eq!(0xFF69, registers.command_pointer);
+        assert!(BooleanExpression::ConsoleOutput(b"HI\n".to_vec()).solve(&registers, &memory).is_none());
+    }
+
+    #[test]
+    fn test_machine_c64() {
+        let mut registers = Registers::new_initialized(0xC000);
+        let mut memory = Memory::new_with_ram();
+        DeviceCommand::AddMachine { machine: Machine::C64 }
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        // C000: LDX #0; LDA $C010,X; BEQ +6; JSR CHROUT; INX; BNE $C002; JMP READY
+        let program = C64Program::new(vec![
+            0x00, 0xc0, 0xa2, 0x00, 0xbd, 0x10, 0xc0, 0xf0, 0x06, 0x20, 0xd2, 0xff, 0xe8, 0xd0, 0xf5, 0x4c,
+            0x7b, 0xe3, 0x48, 0x49, 0x0d, 0x00,
+        ])
+        .unwrap();
+        MemoryCommand::LoadSegments { segments: program.into_memory_segments() }
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        RunCommand {
+            stop_condition: BooleanExpression::Value(false),
+            continue_condition: BooleanExpression::Value(true),
+            start_address: None,
+        }
+        .execute(&mut registers, &mut memory, &mut None)
+        .unwrap();
+
+        assert_eq!(c64::READY, registers.command_pointer);
+        assert!(BooleanExpression::ConsoleOutput(b"HI\n".to_vec()).solve(&registers, &memory).is_none());
+    }
+
+    #[test]
+    fn test_load_xex() {
+        let mut registers = Registers::new_initialized(0x1000);
+        let mut memory = Memory::new_with_ram();
+        let command = DeviceCommand::LoadXex {
+            sections: vec![
+                // LDA #$01; STA $80; RTS
+                Section::Data { start_address: 0x2000, data: vec![0xa9, 0x01, 0x85, 0x80, 0x60] },
+                Section::Init { init_address: 0x2000 },
+                // LDA $80; STA $81; RTS
+                Section::Data { start_address: 0x2100, data: vec![0xa5, 0x80, 0x85, 0x81, 0x60] },
+                Section::Run { run_address: 0x2100 },
+            ],
+        };
+        assert!(command.execute(&mut registers, &mut memory, &mut None).is_err());
+
+        DeviceCommand::AddMachine { machine: Machine::Atari }
+            .execute(&mut registers, &mut memory, &mut None)
+            .unwrap();
+        let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
+        assert!(matches!(token, OutputToken::Setup(v) if v[1] == *"INIT routine at #0x2000 returned after 11 cycles"));
+        assert_eq!(0x01, memory.read_byte(0x80).unwrap());
+        assert_eq!(0x00, memory.read_byte(0x81).unwrap());
+        assert_eq!(vec![0x00, 0x21, 0x00, 0x20], memory.read(0x02E0, 4).unwrap());
+        assert_eq!(0x2100, registers.command_pointer);
+
+        RunCommand {
+            stop_condition: BooleanExpression::Value(false),
+            continue_condition: BooleanExpression::Value(true),
+            start_address: None,
+        }
+        .execute(&mut registers, &mut memory, &mut None)
+        .unwrap();
+        assert_eq!(atari::WARMSV, registers.command_pointer);
+        assert_eq!(0x01, memory.read_byte(0x81).unwrap());
+    }
+
+    #[test]
+    fn test_device_sdcard_missing_image() {
+        let mut registers = Registers::new_initialized(0x0000);
+        let mut memory = Memory::new_with_ram();
+        let command = DeviceCommand::AddSdCard { address: 0xB000, filepath: PathBuf::from("/does/not/exist.img"), pins: SpiPins::default() };
+
+        assert!(command.execute(&mut registers, &mut memory, &mut None).is_err());
+    }
+
+    #[test]
+    fn test_map_command() {
+        let mut registers = Registers::new_initialized(0x0000);
+        let mut memory = Memory::default();
+        memory.add_subsystem("RAM", 0x0000, soft65c02_lib::memory::RAM::default());
+        memory.add_subsystem("BASIC", 0xC000, soft65c02_lib::memory::ROM::new(vec![0x00; 0x2000]));
+        memory.add_mirrored_subsystem("IO", 0xD000, 0x100, soft65c02_lib::memory::ROM::new(vec![0x00; 0x10]));
+        let token = MemoryCommand::Map.execute(&mut registers, &mut memory, &mut None).unwrap();
+
+        match token {
+            OutputToken::Setup(lines) => assert_eq!(
+                vec![
+                    "#0x0000 → #0xBFFF  RAM (RAM)".to_string(),
+                    "#0xC000 → #0xCFFF  BASIC (ROM, read-only), shadows RAM".to_string(),
+                    "#0xD000 → #0xD0FF  IO (ROM, read-only, mirrored every 16 bytes), shadows RAM, BASIC".to_string(),
+                    "#0xD100 → #0xDFFF  BASIC (ROM, read-only), shadows RAM".to_string(),
+                    "#0xE000 → #0xFFFF  RAM (RAM)".to_string(),
+                ],
+                lines
+            ),
+            v => panic!("Expected Setup token, got {:?}", v),
+        }
+    }
+
     #[test]
     fn test_write_command() {
         let command = MemoryCommand::Write {
@@ -1170,7 +2331,7 @@ mod memory_command_tests {
         match token {
             OutputToken::Setup(lines) => {
                 assert_eq!(lines.len(), 1);
-                let expected = format!("\n1000 : 48 65 6C 6C 6F 2C 20 57 6F 72 6C 64 21 00 01 FF | Hello, World!...");
+                let expected = "\n1000 : 48 65 6C 6C 6F 2C 20 57 6F 72 6C 64 21 00 01 FF | Hello, World!...".to_string();
                 assert_eq!(lines[0], expected);
             }
             _ => panic!("Expected Setup token"),
@@ -1189,7 +2350,7 @@ mod memory_command_tests {
             OutputToken::Setup(lines) => {
                 assert_eq!(lines.len(), 2);
                 assert_eq!(lines[0], "Showing Hello World");
-                let expected = format!("\n1000 : 48 65 6C 6C 6F 2C 20 57 6F 72 6C 64 21 00 01 FF | Hello, World!...");
+                let expected = "\n1000 : 48 65 6C 6C 6F 2C 20 57 6F 72 6C 64 21 00 01 FF | Hello, World!...".to_string();
                 assert_eq!(lines[1], expected);
             }
             _ => panic!("Expected Setup token"),
@@ -1208,9 +2369,7 @@ mod memory_command_tests {
         let mut memory = Memory::new_with_ram();
         
         // Write test data with printable ASCII characters
-        memory.write(0x1000, &[
-            b'H', b'e', b'l', b'l', b'o', b',', b' ',  // "Hello, "
-        ]).unwrap();
+        memory.write(0x1000, b"Hello, ").unwrap();
 
         let token = command.execute(&mut registers, &mut memory, &mut None).unwrap();
 
@@ -1218,7 +2377,7 @@ mod memory_command_tests {
         match token {
             OutputToken::Setup(lines) => {
                 assert_eq!(lines.len(), 1);
-                let expected = format!("\n1000 : 48 65 6C 6C 6F 2C 20                            | Hello, ");
+                let expected = "\n1000 : 48 65 6C 6C 6F 2C 20                            | Hello, ".to_string();
                 assert_eq!(lines[0], expected);
             }
             _ => panic!("Expected Setup token"),
@@ -1252,7 +2411,7 @@ mod memory_command_tests {
         match token {
             OutputToken::Setup(lines) => {
                 assert_eq!(lines.len(), 1);
-                let expected = format!("\n1000 : 48 65 6C 6C 6F 2C 20 57 6F 72 6C 64 21 00 01 FF | Hello, World!...\n1010 : 54 65 73 74 69 6E 67 20 31 32 33 07 08 09 0A 0B | Testing 123.....");
+                let expected = "\n1000 : 48 65 6C 6C 6F 2C 20 57 6F 72 6C 64 21 00 01 FF | Hello, World!...\n1010 : 54 65 73 74 69 6E 67 20 31 32 33 07 08 09 0A 0B | Testing 123.....".to_string();
                 assert_eq!(lines[0], expected);
             }
             _ => panic!("Expected Setup token"),
@@ -1282,7 +2441,7 @@ mod memory_command_tests {
         match token {
             OutputToken::Setup(lines) => {
                 assert_eq!(lines.len(), 1);
-                let expected = format!("\n1000 : 48 65 6C 6C 6F 2C 20 57 | Hello, W\n1008 : 6F 72 6C 64 21 00 01 FF | orld!...");
+                let expected = "\n1000 : 48 65 6C 6C 6F 2C 20 57 | Hello, W\n1008 : 6F 72 6C 64 21 00 01 FF | orld!...".to_string();
                 assert_eq!(lines[0], expected);
             }
             _ => panic!("Expected Setup token"),
@@ -1314,7 +2473,7 @@ mod memory_command_tests {
             OutputToken::Setup(lines) => {
                 assert_eq!(lines.len(), 2);
                 assert_eq!(lines[0], "Narrow display");
-                let expected = format!("\n2000 : 01 02 03 04 | ....\n2004 : 05 06 07 08 | ....\n2008 : 09 0A 0B 0C | ....");
+                let expected = "\n2000 : 01 02 03 04 | ....\n2004 : 05 06 07 08 | ....\n2008 : 09 0A 0B 0C | ....".to_string();
                 assert_eq!(lines[1], expected);
             }
             _ => panic!("Expected Setup token"),
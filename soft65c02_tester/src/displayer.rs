@@ -312,7 +312,13 @@ where
                     self.output
                         .write_all(format!("📄 {description}\n").as_bytes())?;
                 }
-                OutputToken::Run { loglines, symbols } | OutputToken::TerminatedRun { loglines, symbols, .. } => {
+                OutputToken::Skipped { description } => {
+                    self.output
+                        .write_all(format!("⏭️  {description} (skipped)\n").as_bytes())?;
+                }
+                OutputToken::Run { loglines, symbols }
+                | OutputToken::TerminatedRun { loglines, symbols, .. }
+                | OutputToken::Call { loglines, symbols, .. } => {
                     let mut content = String::new();
                     
                     // Show trace details if trace logging is enabled
@@ -324,6 +330,10 @@ where
                     if let OutputToken::TerminatedRun { reason, .. } = &token {
                         content.push_str(&format!("⛔ Run terminated: {}\n", reason));
                     }
+
+                    if let OutputToken::Call { address, cycles, .. } = &token {
+                        content.push_str(&format!("📞 call #0x{:04X} returned after {} cycles\n", address, cycles));
+                    }
                     
                     // Only write if there's content to write
                     if !content.is_empty() {
@@ -334,6 +344,10 @@ where
                     self.output
                         .write_all(format!("🔧 {}\n", lines.join("\n")).as_bytes())?;
                 }
+                OutputToken::Variable { name, value } if self.verbose => {
+                    self.output
+                        .write_all(format!("🔧 variable {name} = 0x{value:04X}\n").as_bytes())?;
+                }
                 OutputToken::ControlAction { function, enabled } => {
                     // Handle control actions with type safety
                     match function {
@@ -349,6 +363,11 @@ where
                             .write_all(format!("🔧 {} {}\n", function, action).as_bytes())?;
                     }
                 }
+                OutputToken::Warning(lines) => {
+                    for line in lines {
+                        self.output.write_all(format!("⚠️  {}\n", line).as_bytes())?;
+                    }
+                }
                 OutputToken::View(lines) if self.verbose => {
                     for line in lines {
                         self.output.write_all(format!("🔍 {}\n", line).as_bytes())?;
@@ -565,6 +584,27 @@ mod tests {
         assert!(!output.contains("Total cycles:"), "Should not show total cycles for single instruction");
     }
 
+    #[test]
+    fn test_call_shows_cycles() {
+        for verbose in [true, false] {
+            let mut buffer = Vec::new();
+            let mut displayer = CliDisplayer::new(&mut buffer, verbose);
+            let (sender, receiver) = channel();
+            sender.send(OutputToken::Call {
+                loglines: Vec::new(),
+                symbols: None,
+                address: 0x2000,
+                cycles: 42,
+            }).unwrap();
+            drop(sender);
+
+            displayer.display(receiver).unwrap();
+
+            let output = String::from_utf8(buffer).unwrap();
+            assert!(output.contains("📞 call #0x2000 returned after 42 cycles"));
+        }
+    }
+
     #[test]
     fn test_terminated_run_multiple_instructions() {
         let mut buffer = Vec::new();
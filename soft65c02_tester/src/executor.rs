@@ -1,12 +1,23 @@
 use std::{
-    io::{BufRead, Lines},
-    sync::mpsc::Sender,
+    cell::RefCell,
+    fs::File,
+    io::{BufRead, BufReader, Lines},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
 };
 
 use anyhow::anyhow;
-use soft65c02_lib::{Memory, Registers};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use soft65c02_lib::{
+    memory::{ReadOnlyPolicy, UnmappedPolicy},
+    AddressableIO, Memory, Registers,
+};
 
-use crate::{AppResult, CliCommand, CliCommandParser, Command, OutputToken, SymbolTable};
+use crate::{
+    commands::{DeferredCommand, FuzzPlan, PlanTable, SetupBlock},
+    expression::Variables,
+    fuzz::{shrink_candidates, RandomSource, MAX_SHRINK_RUNS},
+    AppResult, CliCommand, CliCommandParser, Command, OutputToken, SymbolTable};
 
 #[derive(Debug)]
 struct ExecutionRound {
@@ -14,6 +25,9 @@ struct ExecutionRound {
     memory: Memory,
     symbols: Option<SymbolTable>,
     failed: bool,
+    terminated: bool,
+    started: bool, // started by a marker, the teardown block runs at its end
+    skipped: bool, // not selected, its commands do not run
 }
 
 impl Default for ExecutionRound {
@@ -27,6 +41,9 @@ impl Default for ExecutionRound {
             memory,
             symbols: None,
             failed,
+            terminated: false,
+            started: false,
+            skipped: false,
         }
     }
 }
@@ -45,16 +62,135 @@ impl ExecutionRound {
     }
 }
 
+/*
+ * PlanHooks
+ * Setup and teardown blocks run around each plan, with the snapshot of the
+ * setup when it is cached.
+ */
+#[derive(Debug, Default)]
+struct PlanHooks {
+    setup: Option<SetupBlock>,
+    teardown: Vec<CliCommand>,
+    snapshot: Option<RoundSnapshot>,
+}
+
+/*
+ * RoundSnapshot
+ * Registers and RAM left by a setup block, they are copied in the rounds of
+ * the next plans instead of running the block again. Devices and host calls
+ * cannot be copied, a setup block adding some cannot be cached.
+ */
+#[derive(Debug)]
+struct RoundSnapshot {
+    registers: Registers,
+    ram: Vec<u8>,
+    symbols: Option<SymbolTable>,
+    unmapped_policy: UnmappedPolicy,
+    read_only_policy: ReadOnlyPolicy,
+}
+
+impl RoundSnapshot {
+    fn take(round: &ExecutionRound) -> AppResult<Self> {
+        if round.memory.get_subsystems().len() > 1 || !round.memory.get_host_call_addresses().is_empty() {
+            return Err(anyhow!("setup block adding devices or host calls cannot be cached in a snapshot"));
+        }
+
+        Ok(Self {
+            registers: round.registers.clone(),
+            ram: round.memory.peek(0x0000, 0x10000)?,
+            symbols: round.symbols.clone(),
+            unmapped_policy: round.memory.get_unmapped_policy(),
+            read_only_policy: round.memory.get_read_only_policy(),
+        })
+    }
+
+    fn restore(&self, round: &mut ExecutionRound) -> AppResult<()> {
+        round.registers = self.registers.clone();
+        round.memory.write(0x0000, &self.ram)?;
+        round.memory.set_unmapped_policy(self.unmapped_policy);
+        round.memory.set_read_only_policy(self.read_only_policy);
+        round.symbols = self.symbols.clone();
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct CommandIterator<B>
 where
     B: BufRead,
 {
     iterator: Lines<B>,
+    path: Option<PathBuf>,
+    includes: Vec<IncludedFile>,
+    table: Option<PendingPlans>,
+    block: Option<PendingBlock>,
     symbols: Option<SymbolTable>,
+    variables: Variables,
+    random: Option<RefCell<RandomSource>>,
+    listing: bool, // only the plans are read, the commands they run may not resolve
     state: StreamingState,
 }
 
+/*
+ * IncludedFile
+ * Script read in place of an include command, its canonical path tells the
+ * include cycles. The plans expanded from a table are read the same way, in
+ * place of the commands following it, they keep the path of the file holding
+ * the table (none for the main script).
+ */
+#[derive(Debug)]
+struct IncludedFile {
+    lines: IncludedLines,
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+enum IncludedLines {
+    File(Lines<BufReader<File>>),
+    Plans(std::vec::IntoIter<String>),
+}
+
+impl Iterator for IncludedLines {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::File(lines) => lines.next(),
+            Self::Plans(lines) => lines.next().map(Ok),
+        }
+    }
+}
+
+/*
+ * PendingPlans
+ * Table or fuzz plan waiting for the commands it runs, they are read up to
+ * the next marker or the end of the file holding it.
+ */
+#[derive(Debug)]
+struct PendingPlans {
+    plans: Plans,
+    commands: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+enum Plans {
+    Table(PlanTable),
+    Fuzz(FuzzPlan),
+}
+
+/*
+ * PendingBlock
+ * Setup or teardown block waiting for its closing brace, its commands are
+ * parsed then.
+ */
+#[derive(Debug)]
+struct PendingBlock {
+    block: CliCommand,
+    commands: Vec<String>,
+}
+
 #[derive(Debug, Default)]
 struct StreamingState {
     accumulated_command: String,
@@ -69,11 +205,72 @@ where
     pub fn new(iterator: Lines<B>) -> Self {
         Self { 
             iterator,
+            path: None,
+            includes: Vec::new(),
+            table: None,
+            block: None,
             symbols: Some(SymbolTable::new()),
+            variables: Variables::new(),
+            random: None,
+            listing: false,
             state: StreamingState::default(),
         }
     }
 
+    /// Location of the script read, the files it includes are looked for
+    /// relative to it instead of the current directory.
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Value a `let` computed when it ran, the commands parsed next use it.
+    pub fn set_variable(&mut self, name: &str, value: usize) {
+        self.variables.insert(name.to_string(), Some(value));
+    }
+
+    /// Description and tags of the selected plans, the commands they run are
+    /// not checked beyond their syntax since they never run.
+    pub fn list_plans(mut self, selection: &PlanSelection) -> AppResult<Vec<(String, Vec<String>)>> {
+        self.listing = true;
+        let mut plans = Vec::new();
+        for command in self {
+            match command? {
+                CliCommand::Marker { description, tags } if selection.selects(&description, &tags) => plans.push((description, tags)),
+                CliCommand::Fuzz(plan) if selection.selects(&plan.description, &plan.tags) => {
+                    plans.push((format!("{} (fuzz {} runs)", plan.description, plan.runs), plan.tags));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(plans)
+    }
+
+    /// Parse a deferred command when it runs, its expressions read the given
+    /// registers and memory.
+    pub fn resolve(&mut self, command: &DeferredCommand, registers: &Registers, memory: &Memory) -> AppResult<CliCommand> {
+        self.parse_with_machine(&command.text, Some((registers, memory)))
+    }
+
+    /// Commands of a fuzz plan, parsed with the symbols and variables known so
+    /// far. Their `random` values are drawn from the given source.
+    pub fn fuzz_commands<'s>(&self, plan: &FuzzPlan, script: &'s str, random: RandomSource) -> CommandIterator<&'s [u8]> {
+        CommandIterator {
+            path: plan.path.clone().or(self.path.clone()),
+            symbols: self.symbols.clone(),
+            variables: self.variables.clone(),
+            random: Some(RefCell::new(random)),
+            listing: self.listing,
+            ..CommandIterator::new(script.as_bytes().lines())
+        }
+    }
+
+    /// Source of the random values drawn by the commands parsed so far.
+    pub fn take_random(&mut self) -> Option<RandomSource> {
+        self.random.take().map(RefCell::into_inner)
+    }
+
 }
 
 impl<B> Iterator for CommandIterator<B>
@@ -85,17 +282,33 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             // Try to get the next line
-            let line = match self.iterator.next() {
+            let line = match self.next_line() {
                 Some(Ok(line)) => line,
                 Some(Err(e)) => return Some(Err(anyhow!(e))),
                 None => {
-                    // End of input - if we have a partial command, try to parse it
-                    if !self.state.accumulated_command.trim().is_empty() {
-                        let command = self.state.accumulated_command.trim().to_string();
-                        self.state.accumulated_command.clear();
-                        return Some(self.parse_command(&command));
+                    // End of a file - if we have a partial command, try to parse it
+                    // and go on with the file including it
+                    let depth = self.includes.len();
+                    let command = self.state.accumulated_command.trim().to_string();
+                    self.state = StreamingState::default();
+                    let mut result = if command.is_empty() { None } else { self.handle_command(command) };
+                    if let Some(block) = self.block.take() {
+                        let name = if matches!(block.block, CliCommand::Setup(_)) { "setup" } else { "teardown" };
+                        result = Some(Err(anyhow!("{name} block is not closed")));
+                    }
+                    let ended = if depth > 0 { Some(self.includes.remove(depth - 1)) } else { None };
+                    // the commands of a table end with the file holding it, a
+                    // table ending the previous one is read at the end of its plans
+                    if !matches!(ended, Some(IncludedFile { lines: IncludedLines::Plans(_), .. })) {
+                        if let Some(plans) = self.table.take() {
+                            result = self.finish(plans, None);
+                        }
+                    }
+                    match result {
+                        Some(result) => return Some(result),
+                        None if depth == 0 && self.includes.is_empty() => return None,
+                        None => continue,
                     }
-                    return None;
                 }
             };
 
@@ -120,7 +333,9 @@ where
                 let command = self.state.accumulated_command.trim().to_string();
                 self.state.accumulated_command.clear();
                 if !command.is_empty() {
-                    return Some(self.parse_command(&command));
+                    if let Some(result) = self.handle_command(command) {
+                        return Some(result);
+                    }
                 }
             }
             // If we're still in a string, continue accumulating lines
@@ -133,11 +348,187 @@ impl<B> CommandIterator<B>
 where
     B: BufRead,
 {
-    fn parse_command(&mut self, command_text: &str) -> AppResult<CliCommand> {
-        match CliCommandParser::from_with_context(command_text, crate::pest_parser::ParserContext::new(self.symbols.as_ref())) {
+    /*
+     * Parse a complete command unless it belongs to a pending table or fuzz
+     * plan, a marker ends their commands.
+     */
+    fn handle_command(&mut self, command_text: String) -> Option<AppResult<CliCommand>> {
+        if let Some(mut block) = self.block.take() {
+            if CliCommandParser::is_block_end(&command_text) {
+                return Some(self.close(block));
+            }
+            block.commands.push(command_text);
+            self.block = Some(block);
+
+            return None;
+        }
+        match self.table.take() {
+            Some(plans) if CliCommandParser::is_marker(&command_text) => self.finish(plans, Some(command_text)),
+            Some(mut plans) => {
+                plans.commands.push(command_text);
+                self.table = Some(plans);
+                None
+            }
+            None => self.parse_command(&command_text),
+        }
+    }
+
+    /*
+     * Parse the commands of a setup or teardown block, they run in every plan
+     * so they can neither start plans nor include files.
+     */
+    fn close(&mut self, block: PendingBlock) -> AppResult<CliCommand> {
+        let name = if matches!(block.block, CliCommand::Setup(_)) { "setup" } else { "teardown" };
+        let mut commands = Vec::new();
+        for command_text in &block.commands {
+            let command = match self.parse_cli_command(command_text) {
+                Ok(command) => command,
+                Err(e) => {
+                    return match self.includes.last().and_then(|included_file| included_file.path.as_ref()) {
+                        Some(path) => Err(anyhow!("{}: {e}", path.display())),
+                        None => Err(e),
+                    }
+                }
+            };
+            match command {
+                CliCommand::None => {}
+                CliCommand::Marker { .. }
+                | CliCommand::Plans(_)
+                | CliCommand::Fuzz(_)
+                | CliCommand::Include(_)
+                | CliCommand::Setup(_)
+                | CliCommand::Teardown(_) => {
+                    return Err(anyhow!("'{}' cannot be used in a {name} block", command_text.trim()));
+                }
+                command => commands.push(command),
+            }
+        }
+
+        Ok(match block.block {
+            CliCommand::Setup(setup) => CliCommand::Setup(SetupBlock { commands, ..setup }),
+            _ => CliCommand::Teardown(commands),
+        })
+    }
+
+    /*
+     * The plans of a table are read next, a fuzz plan is returned to the
+     * executor once its commands parse. The marker ending them is read after.
+     */
+    fn finish(&mut self, pending: PendingPlans, marker: Option<String>) -> Option<AppResult<CliCommand>> {
+        let (lines, result) = match pending.plans {
+            Plans::Table(table) => (table.expand(&pending.commands), None),
+            Plans::Fuzz(plan) => {
+                let plan = FuzzPlan { commands: pending.commands, path: pending.path.clone(), ..plan };
+                let script = plan.commands.join("\n");
+                let error = self
+                    .fuzz_commands(&plan, &script, RandomSource::replay(Vec::new()))
+                    .find_map(Result::err);
+                let result = match (error, &pending.path) {
+                    (Some(e), Some(path)) => Err(anyhow!("{}: {e}", path.display())),
+                    (Some(e), None) => Err(e),
+                    (None, _) => Ok(CliCommand::Fuzz(plan)),
+                };
+                (Vec::new(), Some(result))
+            }
+        };
+        let lines: Vec<String> = lines.into_iter().chain(marker).collect();
+        if !lines.is_empty() {
+            self.includes.push(IncludedFile {
+                lines: IncludedLines::Plans(lines.into_iter()),
+                path: pending.path,
+            });
+        }
+
+        result
+    }
+
+    fn next_line(&mut self) -> Option<std::io::Result<String>> {
+        match self.includes.last_mut() {
+            Some(included_file) => included_file.lines.next(),
+            None => self.iterator.next(),
+        }
+    }
+
+    /*
+     * Parse a command, an include command is not returned: the lines of the
+     * included file are read next. Neither is a table of plans, it waits for
+     * the commands following it.
+     */
+    fn parse_command(&mut self, command_text: &str) -> Option<AppResult<CliCommand>> {
+        let path = self.includes.last().and_then(|included_file| included_file.path.clone());
+        match (self.parse_cli_command(command_text), path) {
+            (Ok(CliCommand::Include(path)), _) => self.include(&path).err().map(Err),
+            (Ok(CliCommand::Plans(table)), path) => {
+                self.table = Some(PendingPlans { plans: Plans::Table(table), commands: Vec::new(), path });
+                None
+            }
+            (Ok(CliCommand::Fuzz(plan)), path) => {
+                self.table = Some(PendingPlans { plans: Plans::Fuzz(plan), commands: Vec::new(), path });
+                None
+            }
+            (Ok(block @ (CliCommand::Setup(_) | CliCommand::Teardown(_))), _) => {
+                self.block = Some(PendingBlock { block, commands: Vec::new() });
+                None
+            }
+            (Err(e), Some(path)) => Some(Err(anyhow!("{}: {e}", path.display()))),
+            (result, _) => Some(result),
+        }
+    }
+
+    /*
+     * Open the file to read in place of an include command. A relative path
+     * is relative to the directory of the file holding the command. A file
+     * cannot include itself, even through other files.
+     */
+    fn include(&mut self, path: &Path) -> AppResult<()> {
+        let including_path = self.includes.last().map_or(self.path.as_deref(), |included_file| included_file.path.as_deref().or(self.path.as_deref()));
+        let path = match including_path.and_then(|including_path| including_path.parent()) {
+            Some(directory) if path.is_relative() => directory.join(path),
+            _ => path.to_path_buf(),
+        };
+        let path = path
+            .canonicalize()
+            .map_err(|e| anyhow!("cannot include '{}': {e}", path.display()))?;
+        let mut chain: Vec<PathBuf> = self.path.iter().filter_map(|path| path.canonicalize().ok()).collect();
+        chain.extend(self.includes.iter().filter_map(|included_file| included_file.path.clone()));
+        if chain.contains(&path) {
+            chain.push(path);
+            let chain: Vec<String> = chain.iter().map(|path| path.display().to_string()).collect();
+            return Err(anyhow!("include cycle: {}", chain.join(" -> ")));
+        }
+        let file = File::open(&path).map_err(|e| anyhow!("cannot include '{}': {e}", path.display()))?;
+        self.includes.push(IncludedFile {
+            lines: IncludedLines::File(BufReader::new(file).lines()),
+            path: Some(path),
+        });
+
+        Ok(())
+    }
+
+    fn parse_cli_command(&mut self, command_text: &str) -> AppResult<CliCommand> {
+        self.parse_with_machine(command_text, None)
+    }
+
+    fn parse_with_machine(&mut self, command_text: &str, machine: Option<(&Registers, &Memory)>) -> AppResult<CliCommand> {
+        let mut context = crate::pest_parser::ParserContext::new(self.symbols.as_ref()).with_variables(&self.variables);
+        if let Some(random) = &self.random {
+            context = context.with_random(random);
+        }
+        if let Some((registers, memory)) = machine {
+            context = context.with_machine(registers, memory);
+        }
+        match CliCommandParser::from_with_context(command_text, context) {
             Ok(cmd) => {
                 // Successfully parsed a command, update symbols if needed
                 match &cmd {
+                    // a variable reading registers or memory is pending until the let runs
+                    CliCommand::Let(command) => {
+                        let value = command.expression.evaluate_constant().ok();
+                        self.variables.insert(command.name.clone(), value);
+                    }
+                    CliCommand::Deferred(DeferredCommand { variable: Some(name), .. }) => {
+                        self.variables.insert(name.clone(), None);
+                    }
                     CliCommand::Memory(crate::commands::MemoryCommand::LoadSymbols { symbols }) => {
                         self.symbols = Some(symbols.clone());
                     }
@@ -150,6 +541,7 @@ where
                 }
                 Ok(cmd)
             }
+            Err(_) if self.listing && CliCommandParser::is_plan_command(command_text) => Ok(CliCommand::None),
             Err(e) => Err(anyhow!(e))
         }
     }
@@ -178,6 +570,17 @@ pub struct ExecutorConfiguration {
 
     /// If true, the executor stops when an assertion fails.
     pub stop_on_failed_assertion: bool,
+
+    /// Location of the script, the files it includes are looked for relative
+    /// to it. They are looked for from the current directory when unset.
+    pub script_path: Option<PathBuf>,
+
+    /// Seed of the random values of the fuzz plans, a random one is picked
+    /// when unset. A failing fuzz plan tells the seed replaying it.
+    pub seed: Option<u64>,
+
+    /// Plans to run, the others are reported as skipped.
+    pub selection: PlanSelection,
 }
 
 impl Default for ExecutorConfiguration {
@@ -185,6 +588,55 @@ impl Default for ExecutorConfiguration {
         Self {
             ignore_parse_error: false,
             stop_on_failed_assertion: true,
+            script_path: None,
+            seed: None,
+            selection: PlanSelection::default(),
+        }
+    }
+}
+
+/// Selection of the plans to run by their description and their tags. All
+/// the plans are selected by default.
+#[derive(Debug, Default, Clone)]
+pub struct PlanSelection {
+    /// Pattern the description of the plans shall contain, `*` matches any
+    /// characters and `?` any single character.
+    pub filter: Option<String>,
+
+    /// Tags of the plans to run, a plan is selected if it has one of them.
+    pub tags: Vec<String>,
+}
+
+impl PlanSelection {
+    pub fn selects(&self, description: &str, tags: &[String]) -> bool {
+        let matches_filter = match &self.filter {
+            Some(filter) => Self::contains(description, filter),
+            None => true,
+        };
+        let matches_tags = self.tags.is_empty() || self.tags.iter().any(|tag| tags.contains(tag));
+
+        matches_filter && matches_tags
+    }
+
+    /*
+     * Tell if the text contains the pattern, both are read as chars. A star
+     * matches any sequence of characters, it is tried from the shortest one.
+     */
+    fn contains(text: &str, pattern: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+
+        (0..=text.len()).any(|start| Self::matches_prefix(&text[start..], &pattern))
+    }
+
+    fn matches_prefix(text: &[char], pattern: &[char]) -> bool {
+        match pattern.split_first() {
+            None => true,
+            Some(('*', rest)) => (0..=text.len()).any(|skipped| Self::matches_prefix(&text[skipped..], rest)),
+            Some((expected, rest)) => match text.split_first() {
+                Some((found, text)) if *expected == '?' || found == expected => Self::matches_prefix(text, rest),
+                _ => false,
+            },
         }
     }
 }
@@ -211,42 +663,55 @@ impl Executor {
     /// marker.
     pub fn run<T: BufRead>(self, buffer: T, sender: Sender<OutputToken>) -> AppResult<()> {
         let mut round = ExecutionRound::default();
+        let mut hooks = PlanHooks::default();
         let mut failed: usize = 0;
-        let mut had_terminated_run = false;
+        let stop = self.configuration.stop_on_failed_assertion;
+
+        let seed = self.configuration.seed.unwrap_or_else(rand::random);
 
-        for result in CommandIterator::new(buffer.lines()) {
+        let mut commands = CommandIterator::new(buffer.lines());
+        if let Some(path) = &self.configuration.script_path {
+            commands = commands.with_path(path);
+        }
+
+        while let Some(result) = commands.next() {
             let command = match result {
                 Err(e) if !self.configuration.ignore_parse_error => return Err(anyhow!(e)),
                 Err(_) => continue,
                 Ok(c) => c,
             };
 
-            if matches!(command, CliCommand::None) {
-                continue;
-            } else if matches!(command, CliCommand::Marker(_)) {
-                round = ExecutionRound::default();
-                had_terminated_run = false;
-            } else if had_terminated_run || (!round.is_ok() && self.configuration.stop_on_failed_assertion) {
-                continue;
-            }
-            let (registers, memory, symbols) = round.get_mut();
-            let token = command.execute(registers, memory, symbols)?;
-
-            // Count both assertion failures and terminated runs as failures
-            if matches!(token, OutputToken::Assertion { ref failure, description: _ } if failure.is_some())
-                || matches!(token, OutputToken::TerminatedRun { .. })
-            {
-                failed += 1;
-                round.set_failed();
-            }
-
-            // Track TerminatedRun separately from other failures
-            if matches!(token, OutputToken::TerminatedRun { .. }) {
-                had_terminated_run = true;
+            match command {
+                CliCommand::None => {}
+                CliCommand::Setup(setup) => {
+                    hooks.setup = Some(setup);
+                    hooks.snapshot = None;
+                }
+                CliCommand::Teardown(teardown) => hooks.teardown = teardown,
+                CliCommand::Fuzz(plan) => {
+                    failed += Self::end_plan(&mut round, &hooks, &mut commands, &sender, stop)?;
+                    if self.configuration.selection.selects(&plan.description, &plan.tags) {
+                        failed += Self::run_fuzz(&plan, &mut hooks, &commands, seed, &sender)?;
+                    } else {
+                        sender.send(OutputToken::Skipped { description: plan.description })?;
+                    }
+                }
+                CliCommand::Marker { ref description, ref tags } => {
+                    failed += Self::end_plan(&mut round, &hooks, &mut commands, &sender, stop)?;
+                    round = ExecutionRound::default();
+                    if !self.configuration.selection.selects(description, tags) {
+                        round.skipped = true;
+                        sender.send(OutputToken::Skipped { description: description.clone() })?;
+                        continue;
+                    }
+                    failed += Self::execute(&command, &mut round, &mut commands, &sender)?;
+                    failed += Self::start_plan(&mut round, &mut hooks, &mut commands, &sender, stop)?;
+                }
+                _ if round.skipped || round.terminated || (!round.is_ok() && stop) => {}
+                command => failed += Self::execute(&command, &mut round, &mut commands, &sender)?,
             }
-
-            sender.send(token)?;
         }
+        failed += Self::end_plan(&mut round, &hooks, &mut commands, &sender, stop)?;
 
         // buffer is exhausted
         if failed > 0 {
@@ -255,6 +720,213 @@ impl Executor {
             Ok(())
         }
     }
+
+    /// Execute a command in the round and send its outputs. Return 1 when an
+    /// assertion fails or a run is terminated, 0 otherwise.
+    fn execute<B: BufRead>(
+        command: &CliCommand,
+        round: &mut ExecutionRound,
+        commands: &mut CommandIterator<B>,
+        sender: &Sender<OutputToken>,
+    ) -> AppResult<usize> {
+        let mut failed = 0;
+        let (registers, memory, symbols) = round.get_mut();
+        let token = match command {
+            CliCommand::Deferred(deferred) => commands.resolve(deferred, registers, memory)?.execute(registers, memory, symbols)?,
+            command => command.execute(registers, memory, symbols)?,
+        };
+        if let OutputToken::Variable { name, value } = &token {
+            commands.set_variable(name, *value);
+        }
+
+        // Count both assertion failures and terminated runs as failures
+        if matches!(token, OutputToken::Assertion { ref failure, description: _ } if failure.is_some())
+            || matches!(token, OutputToken::TerminatedRun { .. })
+        {
+            failed = 1;
+            round.set_failed();
+        }
+
+        // Track TerminatedRun separately from other failures
+        if matches!(token, OutputToken::TerminatedRun { .. }) {
+            round.terminated = true;
+        }
+
+        sender.send(token)?;
+
+        let warnings = round.memory.take_warnings();
+        if !warnings.is_empty() {
+            sender.send(OutputToken::Warning(warnings))?;
+        }
+
+        Ok(failed)
+    }
+
+    /// Execute the commands of a setup or teardown block, they are skipped
+    /// as the commands of the plan once it failed.
+    fn execute_block<B: BufRead>(
+        block: &[CliCommand],
+        round: &mut ExecutionRound,
+        commands: &mut CommandIterator<B>,
+        sender: &Sender<OutputToken>,
+        stop: bool,
+    ) -> AppResult<usize> {
+        let mut failed = 0;
+        for command in block {
+            if round.terminated || (!round.is_ok() && stop) {
+                break;
+            }
+            failed += Self::execute(command, round, commands, sender)?;
+        }
+
+        Ok(failed)
+    }
+
+    /*
+     * Run the setup block at the start of a plan. When it is cached, the
+     * snapshot taken the first time it passed is restored instead.
+     */
+    fn start_plan<B: BufRead>(
+        round: &mut ExecutionRound,
+        hooks: &mut PlanHooks,
+        commands: &mut CommandIterator<B>,
+        sender: &Sender<OutputToken>,
+        stop: bool,
+    ) -> AppResult<usize> {
+        round.started = true;
+        let Some(setup) = &hooks.setup else {
+            return Ok(0);
+        };
+        if let Some(snapshot) = &hooks.snapshot {
+            snapshot.restore(round)?;
+            sender.send(OutputToken::Setup(vec!["setup restored from snapshot".to_string()]))?;
+
+            return Ok(0);
+        }
+        let failed = Self::execute_block(&setup.commands, round, commands, sender, stop)?;
+        if setup.snapshot && failed == 0 {
+            hooks.snapshot = Some(RoundSnapshot::take(round)?);
+        }
+
+        Ok(failed)
+    }
+
+    /// Run the teardown block at the end of a plan started by a marker.
+    fn end_plan<B: BufRead>(
+        round: &mut ExecutionRound,
+        hooks: &PlanHooks,
+        commands: &mut CommandIterator<B>,
+        sender: &Sender<OutputToken>,
+        stop: bool,
+    ) -> AppResult<usize> {
+        if !std::mem::take(&mut round.started) {
+            return Ok(0);
+        }
+
+        Self::execute_block(&hooks.teardown, round, commands, sender, stop)
+    }
+
+    /*
+     * Run a fuzz plan, each run draws its random values from a generator
+     * seeded by the plan's one. The random values of a failing run are shrunk
+     * to the smallest ones still failing, only this run's outputs are sent.
+     * The outputs of the last run are sent when they all pass.
+     */
+    fn run_fuzz<B: BufRead>(
+        plan: &FuzzPlan,
+        hooks: &mut PlanHooks,
+        commands: &CommandIterator<B>,
+        seed: u64,
+        sender: &Sender<OutputToken>,
+    ) -> AppResult<usize> {
+        let script = plan.commands.join("\n");
+        let mut generator = StdRng::seed_from_u64(seed);
+        let mut outputs = Vec::new();
+
+        for run in 1..=plan.runs {
+            let random = RandomSource::new(generator.random());
+            let (failed, run_outputs, drawn) = Self::run_fuzz_once(hooks, commands.fuzz_commands(plan, &script, random))?;
+            outputs = run_outputs;
+            if !failed {
+                continue;
+            }
+            let (outputs, drawn) = Self::shrink(plan, hooks, commands, &script, outputs, drawn)?;
+            let bytes: Vec<String> = drawn.iter().map(|byte| format!("{byte:02x}")).collect();
+            sender.send(OutputToken::Marker {
+                description: format!(
+                    "{} (fuzz run {run} of {} failed with seed {seed}, random 0x({}))",
+                    plan.description,
+                    plan.runs,
+                    bytes.join(",")
+                ),
+            })?;
+            for output in outputs {
+                sender.send(output)?;
+            }
+
+            return Ok(1);
+        }
+
+        sender.send(OutputToken::Marker {
+            description: format!("{} ({} fuzz runs with seed {seed})", plan.description, plan.runs),
+        })?;
+        for output in outputs {
+            sender.send(output)?;
+        }
+
+        Ok(0)
+    }
+
+    /// Replay a failing fuzz run with simpler random bytes as long as it still
+    /// fails, return the outputs and the bytes of the simplest failing run.
+    fn shrink<B: BufRead>(
+        plan: &FuzzPlan,
+        hooks: &mut PlanHooks,
+        commands: &CommandIterator<B>,
+        script: &str,
+        mut outputs: Vec<OutputToken>,
+        mut drawn: Vec<u8>,
+    ) -> AppResult<(Vec<OutputToken>, Vec<u8>)> {
+        let mut shrink_runs = 0;
+        loop {
+            let mut simpler = None;
+            for candidate in shrink_candidates(&drawn).take(MAX_SHRINK_RUNS - shrink_runs) {
+                shrink_runs += 1;
+                let (failed, run_outputs, candidate) =
+                    Self::run_fuzz_once(hooks, commands.fuzz_commands(plan, script, RandomSource::replay(candidate)))?;
+                if failed {
+                    simpler = Some((run_outputs, candidate));
+                    break;
+                }
+            }
+            match simpler {
+                Some(simpler) => (outputs, drawn) = simpler,
+                None => return Ok((outputs, drawn)),
+            }
+        }
+    }
+
+    /// Run the commands of a fuzz plan between the setup and teardown blocks
+    /// on fresh registers and memory up to the first failure. Tell if it
+    /// failed, the outputs and the random bytes drawn.
+    fn run_fuzz_once(hooks: &mut PlanHooks, mut commands: CommandIterator<&[u8]>) -> AppResult<(bool, Vec<OutputToken>, Vec<u8>)> {
+        let (sender, receiver) = channel::<OutputToken>();
+        let mut round = ExecutionRound::default();
+
+        let mut failed = Self::start_plan(&mut round, hooks, &mut commands, &sender, true)?;
+        while failed == 0 {
+            let Some(result) = commands.next() else {
+                break;
+            };
+            failed += Self::execute(&result?, &mut round, &mut commands, &sender)?;
+        }
+        failed += Self::end_plan(&mut round, hooks, &mut commands, &sender, true)?;
+        drop(sender);
+        let outputs = receiver.try_iter().filter(|token| !matches!(token, OutputToken::None)).collect();
+        let drawn = commands.take_random().map(|random| random.drawn().to_vec()).unwrap_or_default();
+
+        Ok((failed > 0, outputs, drawn))
+    }
 }
 
 #[cfg(test)]
@@ -262,6 +934,8 @@ mod tests {
     use std::sync::mpsc::channel;
 
     use super::*;
+    use crate::commands::LetCommand;
+    use crate::test_utils::fixture_directory;
 
     #[test]
     fn test_halt_on_error() {
@@ -557,4 +1231,446 @@ mod tests {
         assert!(matches!(outputs[0], OutputToken::Setup(_)));
         assert!(matches!(outputs[1], OutputToken::TerminatedRun { .. }));
     }
+
+    #[test]
+    fn test_let_variables() {
+        let lines = &[
+            "memory write #0x0300 0x(34,12)",
+            "let ptr = word #0x0300",
+            "memory write ($ptr + 1) 0x(ff)",
+            "assert #0x1235 = 0xff $$written after the pointer$$",
+        ]
+        .join("\n");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration::default());
+
+        executor.run(lines.as_bytes(), sender).unwrap();
+
+        let outputs: Vec<OutputToken> = receiver.iter().collect();
+        assert!(matches!(&outputs[1], OutputToken::Variable { name, value } if name == "ptr" && *value == 0x1234));
+        assert!(matches!(&outputs[3], OutputToken::Assertion { failure: None, .. }));
+    }
+
+    #[test]
+    fn test_pending_variables() {
+        let lines = "let x = (X + 1)\nmemory write ($x) 0x(01)\nlet y = $x + 1\nassert ($y - 1) = 0x01 $$written at x$$\n";
+        let commands = CommandIterator::new(lines.as_bytes().lines())
+            .collect::<AppResult<Vec<CliCommand>>>()
+            .unwrap();
+        assert!(matches!(&commands[0], CliCommand::Let(LetCommand { name, .. }) if name == "x"));
+        assert!(matches!(&commands[1], CliCommand::Deferred(DeferredCommand { variable: None, .. })));
+        assert!(matches!(&commands[2], CliCommand::Deferred(DeferredCommand { variable: Some(name), .. }) if name == "y"));
+        assert!(matches!(&commands[3], CliCommand::Deferred(DeferredCommand { variable: None, .. })));
+
+        let lines = format!("registers set X=0x10\n{lines}");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration::default());
+
+        executor.run(lines.as_bytes(), sender).unwrap();
+
+        let outputs: Vec<OutputToken> = receiver.iter().collect();
+        assert!(matches!(&outputs[3], OutputToken::Variable { name, value } if name == "y" && *value == 0x12));
+        assert!(matches!(outputs.last(), Some(OutputToken::Assertion { failure: None, .. })));
+
+        let lines = &[
+            "marker $$pointer$$ fuzz 3",
+            "memory write #0x0300 0x(00,12)",
+            "let ptr = word #0x0300",
+            "memory write ($ptr + 1) 0x(ff)",
+            "assert #0x1201 = 0xff $$written after the pointer$$",
+        ]
+        .join("\n");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration::default());
+
+        executor.run(lines.as_bytes(), sender).unwrap();
+
+        let outputs: Vec<OutputToken> = receiver.iter().collect();
+        assert!(matches!(outputs.last(), Some(OutputToken::Assertion { failure: None, .. })));
+    }
+
+    #[test]
+    fn test_plan_tables() {
+        let lines = &[
+            "marker $$increment$$ foreach (a_in, expected) in [(0x01, 0x02), (0x7f, 0x81), (0xff, 0x00)]",
+            "registers set A=$a_in",
+            "assert A=$a_in $$A set$$",
+            "memory write #0x1000 0x(1a)",
+            "run #0x1000 until CP=0x1001",
+            "assert A=$expected $$A incremented$$",
+            "marker $$store$$ foreach (value) in [(0x12), (0x34)]",
+            "registers set X=$value",
+            "memory write #0x0200 0x(ff)",
+            "assert X=$value $$value stored$$",
+            "marker $$plain marker$$",
+            "assert #0x0200 = 0x00 $$memory is fresh$$",
+        ]
+        .join("\n");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration::default());
+
+        let error = executor.run(lines.as_bytes(), sender).unwrap_err();
+
+        assert_eq!("1 assertions failed!", error.to_string());
+        let outputs: Vec<OutputToken> = receiver.iter().collect();
+        let markers: Vec<&str> = outputs
+            .iter()
+            .filter_map(|token| match token {
+                OutputToken::Marker { description } => Some(description.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "increment (a_in=0x01, expected=0x02)",
+                "increment (a_in=0x7F, expected=0x81)",
+                "increment (a_in=0xFF, expected=0x00)",
+                "store (value=0x12)",
+                "store (value=0x34)",
+                "plain marker",
+            ],
+            markers
+        );
+        let failures: Vec<bool> = outputs
+            .iter()
+            .filter_map(|token| match token {
+                OutputToken::Assertion { failure, .. } => Some(failure.is_some()),
+                _ => None,
+            })
+            .collect();
+        // the second row fails as 0x7F + 1 = 0x80, the following plans run anyway
+        assert_eq!(vec![false, false, false, true, false, false, false, false, false], failures);
+    }
+
+    #[test]
+    fn test_fuzz_plans() {
+        let lines = &[
+            "marker $$small byte$$ fuzz 50",
+            "registers set A=random",
+            "assert A < 0x10 $$A is small$$",
+            "marker $$any byte$$ fuzz 20",
+            "memory write #0x0200 random 2",
+            "registers set X=random",
+            "assert X <= 0xff $$X is a byte$$",
+        ]
+        .join("\n");
+        let run = || {
+            let (sender, receiver) = channel::<OutputToken>();
+            let executor = Executor::new(ExecutorConfiguration {
+                seed: Some(7),
+                ..ExecutorConfiguration::default()
+            });
+            let error = executor.run(lines.as_bytes(), sender).unwrap_err();
+            assert_eq!("1 assertions failed!", error.to_string());
+
+            receiver.iter().collect::<Vec<OutputToken>>()
+        };
+
+        let outputs = run();
+        let markers: Vec<&str> = outputs
+            .iter()
+            .filter_map(|token| match token {
+                OutputToken::Marker { description } => Some(description.as_str()),
+                _ => None,
+            })
+            .collect();
+        // the failing value is shrunk to the smallest one failing
+        assert!(markers[0].starts_with("small byte (fuzz run "), "{}", markers[0]);
+        assert!(markers[0].ends_with(" of 50 failed with seed 7, random 0x(10))"), "{}", markers[0]);
+        assert_eq!("any byte (20 fuzz runs with seed 7)", markers[1]);
+        assert!(matches!(&outputs[1], OutputToken::Setup(_)));
+        assert!(matches!(&outputs[2], OutputToken::Assertion { failure: Some(_), .. }));
+        assert!(matches!(&outputs[outputs.len() - 1], OutputToken::Assertion { failure: None, .. }));
+        // the same seed replays the same runs
+        let replayed: Vec<String> = run()
+            .into_iter()
+            .filter_map(|token| match token {
+                OutputToken::Marker { description } => Some(description),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(markers, replayed);
+    }
+
+    #[test]
+    fn test_plan_selection() {
+        let selection = PlanSelection {
+            filter: Some("div*zero".to_string()),
+            tags: vec!["math".to_string(), "fast".to_string()],
+        };
+        let math = vec!["slow".to_string(), "math".to_string()];
+
+        assert!(selection.selects("divide by zero", &math));
+        assert!(selection.selects("unsigned divide by zero twice", &math));
+        assert!(!selection.selects("divide by zero", &[]));
+        assert!(!selection.selects("divide by one", &math));
+        let selection = PlanSelection {
+            filter: Some("a?c".to_string()),
+            tags: Vec::new(),
+        };
+        assert!(selection.selects("xxabcxx", &[]));
+        assert!(!selection.selects("ac", &[]));
+        assert!(PlanSelection::default().selects("anything", &[]));
+    }
+
+    #[test]
+    fn test_list_plans() {
+        let lines = &[
+            "setup {",
+            "    registers set A=random",
+            "}",
+            "marker $$division$$ tags(math)",
+            "let x = (X + 1)",
+            "memory write ($x) 0x(01)",
+            "memory write ($missing) 0x(00)",
+            "marker $$copy$$",
+            "marker $$fuzzed$$ tags(math) fuzz 10",
+            "assert A=$missing $$not fuzzed$$",
+        ]
+        .join("\n");
+        let selection = PlanSelection {
+            filter: None,
+            tags: vec!["math".to_string()],
+        };
+
+        let plans = CommandIterator::new(lines.as_bytes().lines()).list_plans(&selection).unwrap();
+
+        assert_eq!(
+            vec![
+                ("division".to_string(), vec!["math".to_string()]),
+                ("fuzzed (fuzz 10 runs)".to_string(), vec!["math".to_string()]),
+            ],
+            plans
+        );
+        let error = CommandIterator::new("marker $$copy$$\nmemory write $missing\n".as_bytes().lines())
+            .list_plans(&PlanSelection::default())
+            .unwrap_err();
+        assert!(error.to_string().contains("expected"), "{error}");
+    }
+
+    #[test]
+    fn test_skipped_plans() {
+        let lines = &[
+            "setup {",
+            "    registers set A=0x01",
+            "}",
+            "teardown {",
+            "    assert A=0x01 $$teardown$$",
+            "}",
+            "marker $$division$$ tags(math)",
+            "assert false $$not run$$",
+            "marker $$copy$$",
+            "assert true $$copied$$",
+            "marker $$fuzzed$$ tags(math) fuzz 10",
+            "assert false $$not fuzzed$$",
+        ]
+        .join("\n");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration {
+            selection: PlanSelection {
+                filter: Some("copy".to_string()),
+                tags: Vec::new(),
+            },
+            ..ExecutorConfiguration::default()
+        });
+
+        executor.run(lines.as_bytes(), sender).unwrap();
+
+        let outputs: Vec<String> = receiver
+            .iter()
+            .filter_map(|token| match token {
+                OutputToken::Skipped { description } => Some(format!("skipped {description}")),
+                OutputToken::Marker { description } | OutputToken::Assertion { description, .. } => Some(description),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["skipped division", "copy", "copied", "teardown", "skipped fuzzed"], outputs);
+    }
+
+    #[test]
+    fn test_setup_and_teardown() {
+        let lines = &[
+            "setup {",
+            "    memory write #0x1000 0x(e8)",
+            "    registers set X=0x10",
+            "}",
+            "teardown {",
+            "    assert X=0x11 $$X incremented once$$",
+            "}",
+            "marker $$first plan$$",
+            "run #0x1000 until CP=0x1001",
+            "marker $$second plan$$",
+            "assert #0x1000 = 0xe8 $$setup done again$$",
+            "run #0x1000 until CP=0x1001",
+        ]
+        .join("\n");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration::default());
+
+        executor.run(lines.as_bytes(), sender).unwrap();
+
+        let descriptions: Vec<String> = receiver
+            .iter()
+            .filter_map(|token| match token {
+                OutputToken::Marker { description } | OutputToken::Assertion { description, .. } => Some(description),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            vec!["first plan", "X incremented once", "second plan", "setup done again", "X incremented once"],
+            descriptions
+        );
+    }
+
+    #[test]
+    fn test_setup_snapshot() {
+        let lines = &[
+            "setup snapshot {",
+            "    memory write #0x0200 0x(01)",
+            "    registers set A=0x42",
+            "}",
+            "marker $$first plan$$",
+            "memory write #0x0200 0x(ff)",
+            "registers set A=0x00",
+            "marker $$second plan$$",
+            "assert #0x0200 = 0x01 $$memory restored$$",
+            "assert A=0x42 $$registers restored$$",
+        ]
+        .join("\n");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration::default());
+
+        executor.run(lines.as_bytes(), sender).unwrap();
+
+        let outputs: Vec<OutputToken> = receiver.iter().collect();
+        let restored = outputs
+            .iter()
+            .filter(|token| matches!(token, OutputToken::Setup(lines) if lines[0] == "setup restored from snapshot"))
+            .count();
+        assert_eq!(1, restored);
+        assert_eq!(
+            2,
+            outputs.iter().filter(|token| matches!(token, OutputToken::Assertion { failure: None, .. })).count()
+        );
+
+        let lines = "setup snapshot {\nmock #0x1000 returns A=0x00\n}\nmarker $$mocked$$";
+        let (sender, _receiver) = channel::<OutputToken>();
+        let error = Executor::new(ExecutorConfiguration::default()).run(lines.as_bytes(), sender).unwrap_err();
+        assert_eq!("setup block adding devices or host calls cannot be cached in a snapshot", error.to_string());
+    }
+
+    #[test]
+    fn test_setup_block_errors() {
+        let parse = |lines: &str| CommandIterator::new(lines.as_bytes().lines()).find_map(Result::err).unwrap().to_string();
+
+        assert_eq!("setup block is not closed", parse("setup {\nregisters set A=0x00"));
+        assert_eq!("teardown block is not closed", parse("teardown {"));
+        assert_eq!("'marker $$plan$$' cannot be used in a setup block", parse("setup {\nmarker $$plan$$\n}"));
+        assert_eq!("'include \"common.txt\"' cannot be used in a teardown block", parse("teardown {\ninclude \"common.txt\"\n}"));
+        assert_eq!("'}' closes no setup or teardown block", parse("}"));
+        assert!(parse("setup {\nazerty\n}").contains("azerty"));
+    }
+
+    #[test]
+    fn test_fuzz_plan_errors() {
+        let lines = "marker $$broken$$ fuzz 10\nregisters set A=random\nazerty\nmarker $$next$$";
+        let (sender, _receiver) = channel::<OutputToken>();
+        let error = Executor::new(ExecutorConfiguration::default()).run(lines.as_bytes(), sender).unwrap_err();
+        assert!(error.to_string().contains("azerty"));
+
+        let lines = "marker $$not fuzzed$$\nregisters set A=random";
+        let (sender, _receiver) = channel::<OutputToken>();
+        let error = Executor::new(ExecutorConfiguration::default()).run(lines.as_bytes(), sender).unwrap_err();
+        assert!(error.to_string().contains("random values can only be used in fuzz plans"));
+    }
+
+    #[test]
+    fn test_plan_table_in_included_file() {
+        let directory = fixture_directory("plan_table");
+        std::fs::create_dir_all(directory.join("common")).unwrap();
+        std::fs::write(
+            directory.join("common/plans.txt"),
+            "marker $$included$$ foreach (value) in [(1), (2)]\nassert $value < 3 $$value in range$$\n",
+        )
+        .unwrap();
+        let lines = &["include \"common/plans.txt\"", "assert true $$after the plans$$"].join("\n");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration {
+            script_path: Some(directory.join("test.txt")),
+            ..ExecutorConfiguration::default()
+        });
+
+        executor.run(lines.as_bytes(), sender).unwrap();
+
+        let descriptions: Vec<String> = receiver
+            .iter()
+            .filter_map(|token| match token {
+                OutputToken::Marker { description } | OutputToken::Assertion { description, .. } => Some(description),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            vec!["included (value=0x01)", "value in range", "included (value=0x02)", "value in range", "after the plans"],
+            descriptions
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_include() {
+        let directory = fixture_directory("include");
+        std::fs::create_dir_all(directory.join("common")).unwrap();
+        std::fs::write(
+            directory.join("common/setup.txt"),
+            "memory write #0x0200 0x(42)\ninclude \"values.txt\"",
+        )
+        .unwrap();
+        std::fs::write(directory.join("common/values.txt"), "symbols add answer=0x42\n").unwrap();
+        let lines = &[
+            "marker $$included fixtures$$",
+            "include \"common/setup.txt\"",
+            "assert #0x0200 = $answer $$fixture loaded$$",
+        ]
+        .join("\n");
+        let (sender, receiver) = channel::<OutputToken>();
+        let executor = Executor::new(ExecutorConfiguration {
+            script_path: Some(directory.join("test.txt")),
+            ..ExecutorConfiguration::default()
+        });
+
+        executor.run(lines.as_bytes(), sender).unwrap();
+
+        let outputs: Vec<OutputToken> = receiver.iter().collect();
+        assert_eq!(4, outputs.len());
+        assert!(matches!(&outputs[1], OutputToken::Setup(lines) if lines[0] == *"1 byte written"));
+        assert!(matches!(&outputs[3], OutputToken::Assertion { failure: None, .. }));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let directory = fixture_directory("include_errors");
+        std::fs::create_dir_all(directory.join("common")).unwrap();
+        std::fs::write(directory.join("a.txt"), "include \"common/b.txt\"").unwrap();
+        std::fs::write(directory.join("common/b.txt"), "include \"../a.txt\"").unwrap();
+        std::fs::write(directory.join("common/c.txt"), "registers set A=0x00\nazerty").unwrap();
+
+        std::fs::write(directory.join("common/d.txt"), "registers set A=0x00").unwrap();
+        let parse = |script: &str| -> AppResult<Vec<CliCommand>> {
+            CommandIterator::new(script.as_bytes().lines())
+                .with_path(directory.join("test.txt"))
+                .collect()
+        };
+
+        let message = parse("include \"a.txt\"").unwrap_err().to_string();
+        assert!(message.starts_with("include cycle: "));
+        assert!(message.ends_with("a.txt"));
+        assert_eq!(2, message.matches(" -> ").count());
+        assert!(parse("include \"missing.txt\"").unwrap_err().to_string().starts_with("cannot include "));
+        let message = parse("include \"common/c.txt\"").unwrap_err().to_string();
+        assert!(message.contains("c.txt: "));
+        // a file included twice is not a cycle
+        assert_eq!(2, parse("include \"common/d.txt\"\ninclude \"common/d.txt\"").unwrap().len());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
 }
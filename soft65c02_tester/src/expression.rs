@@ -0,0 +1,264 @@
+use anyhow::anyhow;
+use soft65c02_lib::{AddressableIO, Memory, Registers};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::until_condition::RegisterSource;
+use crate::AppResult;
+
+/// Values of the variables set by `let`, by name. A variable reading the
+/// registers or the memory has no value until its `let` runs.
+pub type Variables = BTreeMap<String, Option<usize>>;
+
+/// Error of a value only known when its command runs, the parser defers the
+/// command instead of failing.
+#[derive(Debug)]
+pub struct PendingValue(pub String);
+
+impl fmt::Display for PendingValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PendingValue {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Multiply,
+    Divide,
+    Remainder,
+    Add,
+    Subtract,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Xor,
+    Or,
+}
+
+impl Operator {
+    pub fn from_symbol(operator: &str) -> Option<Self> {
+        match operator {
+            "*" => Some(Self::Multiply),
+            "/" => Some(Self::Divide),
+            "%" => Some(Self::Remainder),
+            "+" => Some(Self::Add),
+            "-" => Some(Self::Subtract),
+            "<<" => Some(Self::ShiftLeft),
+            ">>" => Some(Self::ShiftRight),
+            "&" => Some(Self::And),
+            "^" => Some(Self::Xor),
+            "|" => Some(Self::Or),
+            _ => None,
+        }
+    }
+
+    /// Operators binding tighter have a higher precedence, as in C.
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Multiply | Self::Divide | Self::Remainder => 5,
+            Self::Add | Self::Subtract => 4,
+            Self::ShiftLeft | Self::ShiftRight => 3,
+            Self::And => 2,
+            Self::Xor => 1,
+            Self::Or => 0,
+        }
+    }
+
+    fn apply(&self, left: usize, right: usize) -> AppResult<usize> {
+        let result = match self {
+            Self::Multiply => left.checked_mul(right),
+            Self::Divide if right == 0 => return Err(anyhow!("division of 0x{left:X} by zero")),
+            Self::Divide => Some(left / right),
+            Self::Remainder if right == 0 => return Err(anyhow!("remainder of 0x{left:X} by zero")),
+            Self::Remainder => Some(left % right),
+            Self::Add => left.checked_add(right),
+            Self::Subtract => left.checked_sub(right),
+            Self::ShiftLeft => u32::try_from(right).ok().and_then(|right| left.checked_shl(right)),
+            Self::ShiftRight => Some(u32::try_from(right).ok().and_then(|right| left.checked_shr(right)).unwrap_or(0)),
+            Self::And => Some(left & right),
+            Self::Xor => Some(left ^ right),
+            Self::Or => Some(left | right),
+        };
+
+        result.ok_or_else(|| anyhow!("0x{left:X} {self} 0x{right:X} is out of range"))
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Remainder => "%",
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::ShiftLeft => "<<",
+            Self::ShiftRight => ">>",
+            Self::And => "&",
+            Self::Xor => "^",
+            Self::Or => "|",
+        };
+        write!(f, "{operator}")
+    }
+}
+
+/*
+ * Expression
+ * Computed value of the scripts. Symbols and variables are resolved when the
+ * script is parsed, registers and memory words or bytes are read when the
+ * command runs. An expression reading neither is constant and can be computed
+ * by the parser.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Value(usize),
+    Register(RegisterSource),
+    Word(Box<Expression>),     // Little endian word read in memory at the given address
+    Byte(Box<Expression>),     // Byte read in memory at the given address
+    LowByte(Box<Expression>),
+    HighByte(Box<Expression>),
+    Operation(Operator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /*
+     * Build the expression of operands separated by operators, the operations
+     * are done by decreasing precedence then from left to right.
+     */
+    pub fn from_infix(operands: Vec<Expression>, operators: Vec<Operator>) -> Self {
+        assert_eq!(operands.len(), operators.len() + 1, "there shall be an operator between operands");
+        let mut operands = operands.into_iter();
+        let first = operands.next().unwrap();
+        let mut rest = operators.into_iter().zip(operands).peekable();
+
+        Self::climb(first, &mut rest, 0)
+    }
+
+    fn climb<I>(mut left: Expression, rest: &mut std::iter::Peekable<I>, min_precedence: u8) -> Expression
+    where
+        I: Iterator<Item = (Operator, Expression)>,
+    {
+        while let Some((operator, _)) = rest.peek() {
+            let precedence = operator.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            let (operator, mut right) = rest.next().unwrap();
+            while let Some((next, _)) = rest.peek() {
+                if next.precedence() <= precedence {
+                    break;
+                }
+                let next_precedence = next.precedence();
+                right = Self::climb(right, rest, next_precedence);
+            }
+            left = Expression::Operation(operator, Box::new(left), Box::new(right));
+        }
+
+        left
+    }
+
+    /// Tell if the expression reads neither the registers nor the memory.
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Self::Value(_) => true,
+            Self::Register(_) | Self::Word(_) | Self::Byte(_) => false,
+            Self::LowByte(expression) | Self::HighByte(expression) => expression.is_constant(),
+            Self::Operation(_, left, right) => left.is_constant() && right.is_constant(),
+        }
+    }
+
+    pub fn evaluate(&self, registers: &Registers, memory: &Memory) -> AppResult<usize> {
+        self.compute(Some((registers, memory)))
+    }
+
+    pub fn evaluate_constant(&self) -> AppResult<usize> {
+        self.compute(None)
+    }
+
+    fn compute(&self, state: Option<(&Registers, &Memory)>) -> AppResult<usize> {
+        match self {
+            Self::Value(value) => Ok(*value),
+            Self::Register(register) => state
+                .map(|(registers, _)| register.get_value(registers))
+                .ok_or_else(|| PendingValue(format!("register {register} is only known when the command runs")).into()),
+            Self::Word(address) | Self::Byte(address) => {
+                let address = address.compute(state)?;
+                let (_, memory) = state.ok_or_else(|| PendingValue("memory is only known when the command runs".to_string()))?;
+                let length = if matches!(self, Self::Word(_)) { 2 } else { 1 };
+                if address + length > 0x10000 {
+                    return Err(anyhow!("cannot read {length} byte(s) at 0x{address:X}"));
+                }
+                let bytes = memory.peek(address, length)?;
+
+                Ok(bytes.iter().rev().fold(0, |value, byte| (value << 8) | *byte as usize))
+            }
+            Self::LowByte(expression) => Ok(expression.compute(state)? & 0xFF),
+            Self::HighByte(expression) => Ok((expression.compute(state)? >> 8) & 0xFF),
+            Self::Operation(operator, left, right) => operator.apply(left.compute(state)?, right.compute(state)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(value: usize) -> Expression {
+        Expression::Value(value)
+    }
+
+    #[test]
+    fn test_precedence() {
+        use Operator::*;
+
+        // 2 + 3 * 4 - 1
+        let expression = Expression::from_infix(vec![value(2), value(3), value(4), value(1)], vec![Add, Multiply, Subtract]);
+        assert_eq!(13, expression.evaluate_constant().unwrap());
+        // 1 | 2 << 4 & 0xF0
+        let expression = Expression::from_infix(vec![value(1), value(2), value(4), value(0xF0)], vec![Or, ShiftLeft, And]);
+        assert_eq!(0x21, expression.evaluate_constant().unwrap());
+        // 100 - 10 - 1
+        let expression = Expression::from_infix(vec![value(100), value(10), value(1)], vec![Subtract, Subtract]);
+        assert_eq!(89, expression.evaluate_constant().unwrap());
+        // 0x1234 + 0x100 then its high byte
+        let expression = Expression::HighByte(Box::new(Expression::from_infix(vec![value(0x1234), value(0x100)], vec![Add])));
+        assert_eq!(0x13, expression.evaluate_constant().unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        use Operator::*;
+
+        let error = Expression::from_infix(vec![value(1), value(2)], vec![Subtract]).evaluate_constant().unwrap_err();
+        assert_eq!("0x1 - 0x2 is out of range", error.to_string());
+        assert!(Expression::from_infix(vec![value(1), value(0)], vec![Divide]).evaluate_constant().is_err());
+        let expression = Expression::Register(RegisterSource::RegisterX);
+        assert!(!expression.is_constant());
+        let error = expression.evaluate_constant().unwrap_err();
+        assert!(error.is::<PendingValue>());
+        assert_eq!("register X is only known when the command runs", error.to_string());
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut memory = Memory::new_with_ram();
+        memory.write(0x0300, &[0x34, 0x12]).unwrap();
+        let mut registers = Registers::new(0x1000);
+        registers.register_x = 0x02;
+        // word $0300 + X * 2
+        let expression = Expression::from_infix(
+            vec![
+                Expression::Word(Box::new(value(0x0300))),
+                Expression::Register(RegisterSource::RegisterX),
+                value(2),
+            ],
+            vec![Operator::Add, Operator::Multiply],
+        );
+
+        assert_eq!(0x1238, expression.evaluate(&registers, &memory).unwrap());
+        assert_eq!(0x12, Expression::Byte(Box::new(value(0x0301))).evaluate(&registers, &memory).unwrap());
+        assert!(Expression::Word(Box::new(value(0xFFFF))).evaluate(&registers, &memory).is_err());
+    }
+}
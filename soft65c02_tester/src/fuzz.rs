@@ -0,0 +1,95 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Runs tried at most to shrink the random values of a failing fuzz run.
+pub const MAX_SHRINK_RUNS: usize = 1000;
+
+/*
+ * RandomSource
+ * Random bytes drawn by the `random` values of a fuzz plan. The bytes drawn
+ * are recorded so a failing run can be replayed: a replayed source gives the
+ * recorded bytes then zeros.
+ */
+#[derive(Debug)]
+pub struct RandomSource {
+    replayed: std::vec::IntoIter<u8>,
+    rng: Option<StdRng>,
+    drawn: Vec<u8>,
+}
+
+impl RandomSource {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            replayed: Vec::new().into_iter(),
+            rng: Some(StdRng::seed_from_u64(seed)),
+            drawn: Vec::new(),
+        }
+    }
+
+    pub fn replay(bytes: Vec<u8>) -> Self {
+        Self {
+            replayed: bytes.into_iter(),
+            rng: None,
+            drawn: Vec::new(),
+        }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        let byte = match (self.replayed.next(), &mut self.rng) {
+            (Some(byte), _) => byte,
+            (None, Some(rng)) => rng.random(),
+            (None, None) => 0,
+        };
+        self.drawn.push(byte);
+
+        byte
+    }
+
+    pub fn drawn(&self) -> &[u8] {
+        &self.drawn
+    }
+}
+
+/*
+ * Simpler variants of the bytes drawn by a failing run, from the first byte
+ * to the last one: the byte set to zero, halved then decremented. Shrinking
+ * keeps the first variant still failing and starts over from it.
+ */
+pub fn shrink_candidates(bytes: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+    bytes.iter().enumerate().flat_map(move |(index, &byte)| {
+        let mut values = vec![0, byte / 2, byte.saturating_sub(1)];
+        values.dedup();
+        values.into_iter().filter(move |value| *value < byte).map(move |value| {
+            let mut candidate = bytes.to_vec();
+            candidate[index] = value;
+            candidate
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_source() {
+        let mut first = RandomSource::new(1234);
+        let mut second = RandomSource::new(1234);
+        let bytes: Vec<u8> = (0..8).map(|_| first.next_byte()).collect();
+        assert_eq!(bytes, (0..8).map(|_| second.next_byte()).collect::<Vec<u8>>());
+        assert_eq!(&bytes, first.drawn());
+
+        let mut replayed = RandomSource::replay(vec![0x12, 0x34]);
+        assert_eq!(vec![0x12, 0x34, 0x00], (0..3).map(|_| replayed.next_byte()).collect::<Vec<u8>>());
+        assert_eq!(&[0x12, 0x34, 0x00], replayed.drawn());
+    }
+
+    #[test]
+    fn test_shrink_candidates() {
+        let candidates: Vec<Vec<u8>> = shrink_candidates(&[0x10, 0x01, 0x00]).collect();
+
+        assert_eq!(
+            vec![vec![0x00, 0x01, 0x00], vec![0x08, 0x01, 0x00], vec![0x0F, 0x01, 0x00], vec![0x10, 0x00, 0x00]],
+            candidates
+        );
+    }
+}
@@ -1,10 +1,16 @@
 mod commands;
 mod displayer;
+mod expression;
 mod executor;
+mod fuzz;
 mod pest_parser;
+mod report;
 mod until_condition;
+#[cfg(test)]
+mod test_utils;
 pub mod atari_binary;
 pub mod apple_single;
+pub mod c64_prg;
 pub mod symbols;
 pub mod disassembler;
 pub mod utils;
@@ -12,6 +18,7 @@ pub mod utils;
 pub use commands::*;
 pub use displayer::*;
 pub use executor::*;
+pub use report::*;
 pub use pest_parser::CliCommandParser;
 pub use symbols::SymbolTable;
 pub use disassembler::Disassembler;
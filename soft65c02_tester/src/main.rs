@@ -6,12 +6,25 @@ use std::{
 };
 
 use anyhow::{anyhow, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use soft65c02_tester::{
     AppResult, CliCommand, CliDisplayer, CommandIterator, Displayer, Executor,
-    ExecutorConfiguration, OutputToken,
+    ExecutorConfiguration, JsonDisplayer, JunitDisplayer, OutputToken, PlanSelection, TapDisplayer,
 };
 
+/// Format of the test results output.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable results.
+    Text,
+    /// JUnit XML report, one test case per plan.
+    Junit,
+    /// Test Anything Protocol, one test point per plan.
+    Tap,
+    /// JSON object per output line, as the commands execute.
+    Json,
+}
+
 /// 65C02 code tester
 ///
 /// This program allows step by step execution of 65C02 processor and performs
@@ -38,6 +51,29 @@ pub struct CommandLineArguments {
     /// Just parse the file without executing the tests.
     #[arg(short, long, default_value = "false")]
     parse: bool,
+
+    /// Seed of the random values of the fuzz plans, a failing fuzz plan
+    /// tells the seed replaying it.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Only run the plans whose description contains this pattern, "*"
+    /// matches any characters. The other plans are reported as skipped.
+    #[arg(long)]
+    filter: Option<String>,
+
+    /// Only run the plans having this tag, the option can be repeated to run
+    /// the plans having any of the tags.
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+
+    /// List the selected plans without running them.
+    #[arg(long)]
+    list: bool,
+
+    /// Format of the test results.
+    #[arg(long, value_enum, default_value = "text")]
+    format: OutputFormat,
 }
 
 impl CommandLineArguments {
@@ -55,11 +91,18 @@ impl CommandLineArguments {
     }
 
     pub fn read_from_standard_input(&self) -> bool {
-        self.input_filepath == PathBuf::from("-")
+        self.input_filepath.as_os_str() == "-"
     }
 
     pub fn write_to_standard_output(&self) -> bool {
-        self.output_filepath == PathBuf::from("-")
+        self.output_filepath.as_os_str() == "-"
+    }
+
+    pub fn get_selection(&self) -> PlanSelection {
+        PlanSelection {
+            filter: self.filter.clone(),
+            tags: self.tags.clone(),
+        }
     }
 }
 fn main() -> Result<()> {
@@ -72,23 +115,53 @@ fn main() -> Result<()> {
             parameters.get_input_file_path()?,
         )?))
     };
+    let script_path = parameters.get_input_file_path().ok();
+    let script_name = script_path
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "stdin".to_string());
     if parameters.parse {
-        let result: AppResult<Vec<CliCommand>> = CommandIterator::new(input_buffer.lines())
-            .map(|result| result.map_err(anyhow::Error::from))
-            .collect();
+        let mut commands = CommandIterator::new(input_buffer.lines());
+        if let Some(path) = script_path {
+            commands = commands.with_path(path);
+        }
+        let result: AppResult<Vec<CliCommand>> = commands.collect();
 
         return result.map(|_| ());
     }
-    let output_buffer: Box<dyn Write + Sync + Send> = if parameters.write_to_standard_output() {
+    let mut output_buffer: Box<dyn Write + Sync + Send> = if parameters.write_to_standard_output() {
         Box::new(std::io::stdout())
     } else {
-        Box::new(std::fs::File::create(parameters.output_filepath)?)
+        Box::new(std::fs::File::create(&parameters.output_filepath)?)
     };
+    if parameters.list {
+        let mut commands = CommandIterator::new(input_buffer.lines());
+        if let Some(path) = script_path {
+            commands = commands.with_path(path);
+        }
+        for (description, tags) in commands.list_plans(&parameters.get_selection())? {
+            match tags.is_empty() {
+                true => writeln!(output_buffer, "📄 {description}")?,
+                false => writeln!(output_buffer, "📄 {description} [{}]", tags.join(", "))?,
+            }
+        }
+
+        return Ok(());
+    }
     let (sender, receiver) = channel::<OutputToken>();
-    let mut displayer = CliDisplayer::new(output_buffer, parameters.verbose);
+    let mut displayer: Box<dyn Displayer + Send> = match parameters.format {
+        OutputFormat::Text => Box::new(CliDisplayer::new(output_buffer, parameters.verbose)),
+        OutputFormat::Junit => Box::new(JunitDisplayer::new(output_buffer, &script_name)),
+        OutputFormat::Tap => Box::new(TapDisplayer::new(output_buffer)),
+        OutputFormat::Json => Box::new(JsonDisplayer::new(output_buffer)),
+    };
     let handler = std::thread::spawn(move || displayer.display(receiver));
     let executor = Executor::new(ExecutorConfiguration {
         stop_on_failed_assertion: !parameters.continue_on_failure,
+        script_path,
+        seed: parameters.seed,
+        selection: parameters.get_selection(),
         ..Default::default()
     });
     let result = executor.run(input_buffer, sender);
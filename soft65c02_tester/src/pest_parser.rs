@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::path::PathBuf;
 use std::env;
 
@@ -5,12 +6,18 @@ use anyhow::anyhow;
 use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use pest_derive::Parser;
+use soft65c02_lib::devices::SpiPins;
+use soft65c02_lib::memory::{ReadOnlyPolicy, UnmappedPolicy};
+use soft65c02_lib::{Memory, Registers, HOST_CALL_RECORD_SIZE};
 
 use crate::{
     commands::*,
+    expression::{Expression, Operator, PendingValue, Variables},
+    fuzz::RandomSource,
     until_condition::{Assignment, BooleanExpression, RegisterSource, Source},
     atari_binary::AtariBinary,
     apple_single::AppleSingle,
+    c64_prg::C64Program,
     SymbolTable,
     AppResult,
 };
@@ -21,11 +28,127 @@ pub struct PestParser;
 
 pub struct ParserContext<'a> {
     symbols: Option<&'a SymbolTable>,
+    variables: Option<&'a Variables>,
+    random: Option<&'a RefCell<RandomSource>>,
+    machine: Option<(&'a Registers, &'a Memory)>,
 }
 
 impl<'a> ParserContext<'a> {
     pub fn new(symbols: Option<&'a SymbolTable>) -> Self {
-        Self { symbols }
+        Self { symbols, variables: None, random: None, machine: None }
+    }
+
+    /// Variables set by `let`, they are looked for before the symbols.
+    pub fn with_variables(mut self, variables: &'a Variables) -> Self {
+        self.variables = Some(variables);
+        self
+    }
+
+    /// Source of the `random` values, they can only be used in fuzz plans.
+    pub fn with_random(mut self, random: &'a RefCell<RandomSource>) -> Self {
+        self.random = Some(random);
+        self
+    }
+
+    /// Registers and memory of a command parsed when it runs, its expressions
+    /// can read them.
+    pub fn with_machine(mut self, registers: &'a Registers, memory: &'a Memory) -> Self {
+        self.machine = Some((registers, memory));
+        self
+    }
+
+    fn draw_random(&self, count: usize) -> AppResult<Vec<u8>> {
+        let random = self
+            .random
+            .ok_or_else(|| anyhow!("random values can only be used in fuzz plans"))?;
+        let mut random = random.borrow_mut();
+
+        Ok((0..count).map(|_| random.next_byte()).collect())
+    }
+
+    fn resolve_symbol(&self, symbol_name: &str) -> AppResult<usize> {
+        match self.variables.and_then(|variables| variables.get(symbol_name)) {
+            Some(Some(value)) => return Ok(*value),
+            Some(None) => {
+                return Err(PendingValue(format!("variable '{}' is only known when its let runs", symbol_name)).into())
+            }
+            None => (),
+        }
+        match &self.symbols {
+            Some(symbols) => symbols
+                .get_address(symbol_name)
+                .map(|addr| addr as usize)
+                .ok_or_else(|| anyhow!("Symbol '{}' not found", symbol_name)),
+            None => Err(anyhow!("Symbol table not available for resolving '{}'", symbol_name)),
+        }
+    }
+
+    /*
+     * Build the expression of an `expression` node, symbols and variables are
+     * replaced by their values.
+     */
+    fn parse_expression(&self, node: &Pair<Rule>) -> AppResult<Expression> {
+        let mut operands = Vec::new();
+        let mut operators = Vec::new();
+        for pair in node.clone().into_inner() {
+            match pair.as_rule() {
+                Rule::expression_operator => operators.push(
+                    Operator::from_symbol(pair.as_str()).expect("the grammar only accepts known operators"),
+                ),
+                _ => operands.push(self.parse_expression_operand(&pair)?),
+            }
+        }
+
+        Ok(Expression::from_infix(operands, operators))
+    }
+
+    fn parse_expression_operand(&self, node: &Pair<Rule>) -> AppResult<Expression> {
+        let inner = || self.parse_expression_operand(&node.clone().into_inner().next().unwrap());
+        Ok(match node.as_rule() {
+            Rule::expression => self.parse_expression(node)?,
+            Rule::expression_number => match self.parse_number(node.as_str())? {
+                value if value > 0xFFFF => return Err(anyhow!("Value {} is too large for 16-bit value", node.as_str())),
+                value => Expression::Value(value),
+            },
+            Rule::random_value => Expression::Value(self.draw_random(1)?[0] as usize),
+            Rule::hex_address | Rule::symbol_reference => Expression::Value(self.parse_memory(node)?),
+            Rule::register8 | Rule::register16 => match self.parse_source_register(node) {
+                Source::Register(register) => Expression::Register(register),
+                _ => unreachable!("a register source is always a register"),
+            },
+            Rule::expression_low_byte => Expression::LowByte(Box::new(inner()?)),
+            Rule::expression_high_byte => Expression::HighByte(Box::new(inner()?)),
+            Rule::expression_word => Expression::Word(Box::new(inner()?)),
+            Rule::expression_byte => Expression::Byte(Box::new(inner()?)),
+            v => panic!("unexpected node '{:?}' in expression", v),
+        })
+    }
+
+    /// Value of a parenthesized expression, the registers and the memory can
+    /// only be read when the command runs.
+    fn parse_computed_value(&self, node: &Pair<Rule>) -> AppResult<usize> {
+        let expression = self.parse_expression(&node.clone().into_inner().next().unwrap())?;
+        let value = match self.machine {
+            Some((registers, memory)) => expression.evaluate(registers, memory),
+            None => expression.evaluate_constant(),
+        };
+
+        value.map_err(|e| match e.is::<PendingValue>() {
+            true => e,
+            false => anyhow!("Cannot compute {}: {}", node.as_str(), e),
+        })
+    }
+
+    fn parse_number(&self, value_str: &str) -> AppResult<usize> {
+        if let Some(hex) = value_str.strip_prefix("0x") {
+            self.parse_hex(hex)
+        } else if let Some(binary) = value_str.strip_prefix("0b") {
+            usize::from_str_radix(binary, 2)
+                .map_err(|e| anyhow!("Failed to parse binary value '{}': {}", value_str, e))
+        } else {
+            value_str.parse::<usize>()
+                .map_err(|e| anyhow!("Failed to parse decimal value '{}': {}", value_str, e))
+        }
     }
 
     fn prepare_hex_str(hex_str: &str) -> String {
@@ -89,14 +212,12 @@ impl<'a> ParserContext<'a> {
             }
             Rule::symbol_reference => {
                 let symbol_name = &pair.as_str()[1..]; // Skip the "$" prefix
-                if let Some(symbols) = &self.symbols {
-                    if let Some(addr) = symbols.get_address(symbol_name) {
-                        return Ok(addr as usize);
-                    }
-                    return Err(anyhow!("Symbol '{}' not found", symbol_name));
-                }
-                Err(anyhow!("Symbol table not available for resolving '{}'", symbol_name))
+                self.resolve_symbol(symbol_name)
             }
+            Rule::computed_value => match self.parse_computed_value(pair)? {
+                addr if addr > 0xFFFF => Err(anyhow!("Address 0x{:X} of {} is out of memory", addr, pair.as_str())),
+                addr => Ok(addr),
+            },
             _ => panic!("Unexpected rule in parse_memory: {:?}", pair.as_rule()),
         }
     }
@@ -136,34 +257,29 @@ impl<'a> ParserContext<'a> {
 
     fn parse_source_value(&self, node: &Pair<Rule>) -> AppResult<Source> {
         let value_str = node.as_str();
-        let value = if value_str.starts_with("0x") {
-            // Parse hex value
-            self.parse_hex(&value_str[2..])?
-        } else if value_str.starts_with("0b") {
-            // Parse binary value
-            usize::from_str_radix(&value_str[2..], 2)
-                .map_err(|e| anyhow!("Failed to parse binary value '{}': {}", value_str, e))?
-        } else {
-            // Parse decimal value
-            value_str.parse::<usize>()
-                .map_err(|e| anyhow!("Failed to parse decimal value '{}': {}", value_str, e))?
+        let computed = node.clone().into_inner().next();
+        let (limit, size) = match node.as_rule() {
+            Rule::value8 => (0xFF, 8),
+            Rule::value16 => (0xFFFF, 16),
+            _ => panic!("Unexpected rule in parse_source_value: {:?}", node.as_rule()),
         };
-        
-        // Validate the value size matches the rule type
-        match node.as_rule() {
-            Rule::value8 => {
-                if value > 0xFF {
-                    return Err(anyhow!("Value {} is too large for 8-bit value", value));
-                }
-            }
-            Rule::value16 => {
-                if value > 0xFFFF {
-                    return Err(anyhow!("Value {} is too large for 16-bit value", value));
-                }
+        let value = match &computed {
+            Some(random) if random.as_rule() == Rule::random_value => {
+                let bytes = self.draw_random(size / 8)?;
+                return Ok(Source::Value(bytes.iter().rev().fold(0, |value, byte| (value << 8) | *byte as usize)));
             }
-            _ => panic!("Unexpected rule in parse_source_value: {:?}", node.as_rule()),
+            Some(computed_value) => self.parse_computed_value(computed_value)?,
+            None => self.parse_number(value_str)?,
+        };
+
+        // Validate the value size matches the rule type
+        if value > limit {
+            return Err(match computed {
+                Some(_) => anyhow!("Value 0x{:X} of {} is too large for {}-bit value", value, value_str, size),
+                None => anyhow!("Value {} is too large for {}-bit value", value, size),
+            });
         }
-        
+
         Ok(Source::Value(value))
     }
 
@@ -212,6 +328,18 @@ impl<'a> ParserContext<'a> {
         bytes
     }
 
+    /// Path of a `filename` node, without its quotes and with the
+    /// environment variables expanded.
+    fn parse_filename(&self, pair: &Pair<Rule>) -> AppResult<PathBuf> {
+        let filename = pair.as_str();
+        let stripped = filename
+            .strip_prefix('"')
+            .and_then(|filename| filename.strip_suffix('"'))
+            .ok_or_else(|| anyhow!("filename {} shall be quoted", filename))?;
+
+        Ok(PathBuf::from(MemoryCommandParser::expand_env_vars(stripped)))
+    }
+
     fn parse_memory_sequence(&self, node: Pair<Rule>) -> AppResult<BooleanExpression> {
         let mut seq_nodes = node.into_inner();
         let addr_node = seq_nodes.next().expect("memory_sequence should have a memory_location node");
@@ -286,6 +414,20 @@ impl<'a> ParserContext<'a> {
             Rule::comparison => self.parse_comparison(first.into_inner()),
             Rule::boolean_condition => self.parse_boolean_condition(first.into_inner()),
             Rule::memory_sequence => self.parse_memory_sequence(first),
+            Rule::lcd_line => self.parse_lcd_line(first),
+            Rule::screen_text | Rule::console_output => {
+                let rule = first.as_rule();
+                let literal = first.into_inner().next().unwrap().as_str();
+                let text = self.parse_string_literal(&literal[1..literal.len() - 1]);
+                Ok(match rule {
+                    Rule::screen_text => BooleanExpression::ScreenText(text),
+                    _ => BooleanExpression::ConsoleOutput(text),
+                })
+            }
+            Rule::sound_log => {
+                let filepath = self.parse_filename(&first.into_inner().next().unwrap())?;
+                Ok(BooleanExpression::SoundLog(filepath))
+            }
             Rule::pointer_assertion => self.parse_pointer_assertion(first),
             Rule::boolean => Ok(BooleanExpression::Value(first.as_str() == "true")),
             Rule::NOT_OP => {
@@ -296,6 +438,22 @@ impl<'a> ParserContext<'a> {
         }
     }
 
+    fn parse_lcd_line(&self, node: Pair<Rule>) -> AppResult<BooleanExpression> {
+        let mut nodes = node.into_inner();
+        let line_node = nodes.next().expect("lcd_line should have a line number");
+        let line = line_node
+            .as_str()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("Invalid LCD line number '{}': {}", line_node.as_str(), e))?;
+        if line == 0 {
+            return Err(anyhow!("LCD lines are numbered from 1"));
+        }
+        let text_node = nodes.next().expect("lcd_line should have a string literal");
+        let text = &text_node.as_str()[1..text_node.as_str().len() - 1];
+
+        Ok(BooleanExpression::LcdLine(line, self.parse_string_literal(text)))
+    }
+
     fn parse_pointer_assertion(&self, node: Pair<Rule>) -> AppResult<BooleanExpression> {
         let mut nodes = node.into_inner();
         
@@ -354,12 +512,32 @@ impl<'a> ParserContext<'a> {
         ))
     }
 
+    /*
+     * Low (<$name) or high (>$name) byte of a symbol address.
+     */
+    fn parse_symbol_byte(&self, node: &Pair<Rule>) -> AppResult<usize> {
+        let inner = node.clone().into_inner().next().unwrap();
+        let value_node = inner.clone().into_inner().next().unwrap();
+        let addr = match value_node.as_rule() {
+            Rule::computed_value => self.parse_computed_value(&value_node)?,
+            _ => self.resolve_symbol(value_node.as_str())?,
+        };
+
+        match inner.as_rule() {
+            Rule::symbol_low_byte => Ok(addr & 0xFF),
+            Rule::symbol_high_byte => Ok((addr >> 8) & 0xFF),
+            v => panic!("unexpected symbol byte reference type '{:?}'", v),
+        }
+    }
+
     fn parse_comparison(&self, mut nodes: Pairs<Rule>) -> AppResult<BooleanExpression> {
         let lh_node = nodes.next().unwrap();
         let lh = match lh_node.as_rule() {
             Rule::register8 | Rule::register16 | Rule::register_cycle => self.parse_source_register(&lh_node),
             Rule::memory_location => self.parse_source_memory(&lh_node)?,
             Rule::value8 | Rule::value16 => self.parse_source_value(&lh_node)?,
+            Rule::call_count => Source::CallCount(self.parse_memory(&lh_node.into_inner().next().unwrap())?),
+            Rule::call_record => self.parse_call_record(lh_node.into_inner())?,
             v => panic!("unexpected node '{:?}' in comparison", v),
         };
 
@@ -371,46 +549,9 @@ impl<'a> ParserContext<'a> {
             Rule::value8 | Rule::value16 => self.parse_source_value(&rh_node)?,
             Rule::symbol_reference => {
                 let symbol_name = &rh_node.as_str()[1..]; // Skip the "$" prefix
-                if let Some(symbols) = &self.symbols {
-                    if let Some(addr) = symbols.get_address(symbol_name) {
-                        Source::Value(addr as usize)
-                    } else {
-                        return Err(anyhow!("Symbol '{}' not found", symbol_name));
-                    }
-                } else {
-                    return Err(anyhow!("Symbol table not available for resolving '{}'", symbol_name));
-                }
-            },
-            Rule::symbol_byte_reference => {
-                let inner = rh_node.clone().into_inner().next().unwrap();
-                match inner.as_rule() {
-                    Rule::symbol_low_byte => {
-                        let symbol_name = &inner.as_str()[2..]; // Skip the "<$" prefix
-                        if let Some(symbols) = &self.symbols {
-                            if let Some(addr) = symbols.get_address(symbol_name) {
-                                Source::Value((addr as usize) & 0xFF) // Low byte
-                            } else {
-                                return Err(anyhow!("Symbol '{}' not found", symbol_name));
-                            }
-                        } else {
-                            return Err(anyhow!("Symbol table not available for resolving '{}'", symbol_name));
-                        }
-                    },
-                    Rule::symbol_high_byte => {
-                        let symbol_name = &inner.as_str()[2..]; // Skip the ">$" prefix
-                        if let Some(symbols) = &self.symbols {
-                            if let Some(addr) = symbols.get_address(symbol_name) {
-                                Source::Value((addr as usize) >> 8) // High byte
-                            } else {
-                                return Err(anyhow!("Symbol '{}' not found", symbol_name));
-                            }
-                        } else {
-                            return Err(anyhow!("Symbol table not available for resolving '{}'", symbol_name));
-                        }
-                    },
-                    v => panic!("unexpected symbol byte reference type '{:?}'", v),
-                }
+                Source::Value(self.resolve_symbol(symbol_name)?)
             },
+            Rule::symbol_byte_reference => Source::Value(self.parse_symbol_byte(&rh_node)?),
             v => panic!("unexpected node '{:?}' in comparison", v),
         };
 
@@ -425,6 +566,39 @@ impl<'a> ParserContext<'a> {
         })
     }
 
+    /*
+     * A register or a zero or stack page byte as it was when the Nth call
+     * to a mocked subroutine was made, calls are numbered from 1.
+     */
+    fn parse_call_record(&self, mut nodes: Pairs<Rule>) -> AppResult<Source> {
+        let number_node = nodes.next().unwrap();
+        let call = number_node
+            .as_str()
+            .parse::<usize>()
+            .map_err(|e| anyhow!("Invalid call number '{}': {}", number_node.as_str(), e))?;
+        if call == 0 {
+            return Err(anyhow!("Calls are numbered from 1"));
+        }
+        let address = self.parse_memory(&nodes.next().unwrap())?;
+        let node = nodes.next().unwrap();
+
+        match self.parse_source_location(&node)? {
+            Source::Register(register) => Ok(Source::CallRegister(call, address, register)),
+            Source::Memory(addr) if addr < HOST_CALL_RECORD_SIZE => Ok(Source::CallMemory(call, address, addr)),
+            Source::Memory(addr) => Err(anyhow!(
+                "Only the zero page and the stack page are recorded, #0x{:04X} is not", addr
+            )),
+            v => panic!("unexpected source '{:?}' in call record", v),
+        }
+    }
+
+    fn parse_source_location(&self, node: &Pair<Rule>) -> AppResult<Source> {
+        match node.as_rule() {
+            Rule::register8 => Ok(self.parse_source_register(node)),
+            _ => self.parse_source_memory(node),
+        }
+    }
+
     fn parse_source_register(&self, node: &Pair<Rule>) -> Source {
         match node.as_str() {
             "A" => Source::Register(RegisterSource::Accumulator),
@@ -458,6 +632,7 @@ impl<'a> MemoryCommandParser<'a> {
 
         let command = match pair.as_rule() {
             Rule::memory_flush => MemoryCommand::Flush,
+            Rule::memory_map => MemoryCommand::Map,
             Rule::memory_write => self.handle_memory_write(pair.into_inner())?,
             Rule::memory_load => self.handle_memory_load(pair.into_inner())?,
             Rule::memory_fill => self.handle_memory_fill(pair.into_inner())?,
@@ -465,6 +640,7 @@ impl<'a> MemoryCommandParser<'a> {
             Rule::symbol_add => self.handle_symbol_add(pair.into_inner())?,
             Rule::symbol_remove => self.handle_symbol_remove(pair.into_inner())?,
             Rule::memory_show => self.handle_memory_show(pair.into_inner())?,
+            Rule::memory_policy => self.handle_memory_policy(pair.into_inner())?,
             _ => return Err(anyhow!("Unknown memory command")),
         };
 
@@ -498,6 +674,17 @@ impl<'a> MemoryCommandParser<'a> {
                 let address = self.context.parse_memory(&bytes_node)?;
                 vec![(address & 0xFF) as u8, (address >> 8) as u8]
             }
+            Rule::random_bytes => {
+                let length_node = bytes_node.into_inner().next().unwrap();
+                let length = match self.context.parse_source_value(&length_node)? {
+                    Source::Value(length) => length,
+                    _ => unreachable!("value16 is always a value"),
+                };
+                if address + length > 0x10000 {
+                    return Err(anyhow!("Cannot write {} random bytes at 0x{:04X}", length, address));
+                }
+                self.context.draw_random(length)?
+            }
             _ => panic!("Expected bytes, string_literal, or memory_location in memory write")
         };
 
@@ -538,7 +725,7 @@ impl<'a> MemoryCommandParser<'a> {
             
             // Find the end of the variable name
             let mut end = start + 1;
-            while end < result.len() && (result[end..end + 1].chars().next().unwrap().is_alphanumeric() || result[end..end + 1].chars().next().unwrap() == '_') {
+            while end < result.len() && (result[end..end + 1].chars().next().unwrap().is_alphanumeric() || result[end..end + 1].starts_with('_')) {
                 end += 1;
             }
             
@@ -557,23 +744,18 @@ impl<'a> MemoryCommandParser<'a> {
     fn handle_address_load(&self, address_pair: Pair<'_, Rule>, filename_pair: Option<Pair<'_, Rule>>) -> AppResult<MemoryCommand> {
         println!("DEBUG handle_address_load:");
         let address = self.context.parse_memory(&address_pair)?;
-        let filename = filename_pair
-            .expect("there shall be a filename argument to memory load")
-            .as_str();
-        let stripped = &filename[1..filename.len() - 1];
-        let expanded = Self::expand_env_vars(stripped);
-        let filepath = PathBuf::from(expanded);
+        let filepath = self
+            .context
+            .parse_filename(&filename_pair.expect("there shall be a filename argument to memory load"))?;
 
         Ok(MemoryCommand::Load { address, filepath })
     }
 
     fn handle_target_load(&self, target_pair: Pair<'_, Rule>, filename_pair: Option<Pair<'_, Rule>>) -> AppResult<MemoryCommand> {
         let target = target_pair.as_str();
-        let filename_pair = filename_pair.expect("there shall be a filename argument to memory load");
-        let filename = filename_pair.as_str();
-        let stripped = &filename[1..filename.len() - 1];
-        let expanded = Self::expand_env_vars(stripped);
-        let filepath = PathBuf::from(expanded);
+        let filepath = self
+            .context
+            .parse_filename(&filename_pair.expect("there shall be a filename argument to memory load"))?;
 
         let command = match target {
             "atari" => {
@@ -586,21 +768,22 @@ impl<'a> MemoryCommandParser<'a> {
                 let segments = binary.into_memory_segments();
                 MemoryCommand::LoadSegments { segments }
             }
-            // This case is unreachable because the grammar only allows "atari" or "apple"
-            _ => unreachable!("Grammar ensures only 'atari' or 'apple' can be targets"),
+            "c64" => {
+                let program = C64Program::from_file(&filepath)?;
+                let segments = program.into_memory_segments();
+                MemoryCommand::LoadSegments { segments }
+            }
+            // This case is unreachable because the grammar only allows "atari", "apple" or "c64"
+            _ => unreachable!("Grammar ensures only 'atari', 'apple' or 'c64' can be targets"),
         };
 
         Ok(command)
     }
 
     fn handle_symbol_load(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<MemoryCommand> {
-        let filename = pairs
-            .next()
-            .expect("there shall be a filename argument to symbols load")
-            .as_str();
-        let stripped = &filename[1..filename.len() - 1];
-        let expanded = Self::expand_env_vars(stripped);
-        let filepath = PathBuf::from(expanded);
+        let filepath = self
+            .context
+            .parse_filename(&pairs.next().expect("there shall be a filename argument to symbols load"))?;
         
         let mut symbols = SymbolTable::new();
         symbols.load_vice_labels(&filepath)?;
@@ -615,10 +798,10 @@ impl<'a> MemoryCommandParser<'a> {
         let value_inner = value_node.into_inner().next().unwrap();
 
         let value = match value_inner.as_rule() {
-            Rule::value8 | Rule::value16 => {
-                let value_str = &value_inner.as_str()[2..]; // Skip "0x"
-                self.context.parse_hex(value_str)?
-            }
+            Rule::value8 | Rule::value16 => match self.context.parse_source_value(&value_inner)? {
+                Source::Value(value) => value,
+                _ => unreachable!("a value source is always a value"),
+            },
             Rule::symbol_reference => {
                 self.context.parse_memory(&value_inner)?
             }
@@ -730,18 +913,153 @@ impl<'a> MemoryCommandParser<'a> {
 
         Ok(MemoryCommand::Show { address, length, width, description })
     }
+
+    fn handle_memory_policy(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<MemoryCommand> {
+        let policy_pair = pairs.next().unwrap();
+        let is_unmapped = policy_pair.as_rule() == Rule::policy_unmapped;
+        let value_pair = policy_pair.into_inner().next().unwrap();
+
+        let command = if is_unmapped {
+            let policy = match value_pair.as_rule() {
+                Rule::policy_error => UnmappedPolicy::Error,
+                Rule::policy_open_bus => UnmappedPolicy::OpenBus,
+                Rule::policy_warn => UnmappedPolicy::Warn,
+                Rule::value8 => match self.context.parse_source_value(&value_pair)? {
+                    Source::Value(v) => UnmappedPolicy::Fixed(v as u8),
+                    _ => return Err(anyhow!("Expected value for unmapped memory policy")),
+                },
+                v => return Err(anyhow!("Unexpected unmapped memory policy {:?}", v)),
+            };
+            MemoryCommand::SetUnmappedPolicy { policy }
+        } else {
+            let policy = match value_pair.as_rule() {
+                Rule::policy_error => ReadOnlyPolicy::Error,
+                Rule::policy_ignore => ReadOnlyPolicy::Ignore,
+                Rule::policy_warn => ReadOnlyPolicy::Warn,
+                v => return Err(anyhow!("Unexpected read-only memory policy {:?}", v)),
+            };
+            MemoryCommand::SetReadOnlyPolicy { policy }
+        };
+
+        Ok(command)
+    }
 }
 
 #[cfg(test)]
-mod test_utils {
+mod expression_parser_tests {
+    use soft65c02_lib::AddressableIO;
+
     use super::*;
+    use crate::test_utils::setup_test_symbols;
 
-    pub fn setup_test_symbols() -> SymbolTable {
-        let mut symbols = SymbolTable::new();
-        symbols.add_symbol(0x34, "byte_var".to_string());    // 8-bit value
-        symbols.add_symbol(0x1234, "word_var".to_string());  // 16-bit value
-        symbols.add_symbol(0x2000, "counter".to_string());
-        symbols
+    fn parse_value(input: &str, context: &ParserContext) -> AppResult<usize> {
+        let pair = PestParser::parse(Rule::value16, input).unwrap().next().unwrap();
+        match context.parse_source_value(&pair)? {
+            Source::Value(value) => Ok(value),
+            v => panic!("unexpected source {v:?}"),
+        }
+    }
+
+    #[test]
+    fn test_computed_values() {
+        let symbols = setup_test_symbols();
+        let mut variables = Variables::new();
+        variables.insert("ptr".to_string(), Some(0x0400));
+        variables.insert("pending".to_string(), None);
+        let context = ParserContext::new(Some(&symbols)).with_variables(&variables);
+
+        assert_eq!(0x1244, parse_value("($word_var + 0x10)", &context).unwrap());
+        assert_eq!(0x2000 + 0x34 * 2, parse_value("($counter + $byte_var * 2)", &context).unwrap());
+        assert_eq!(0x0410, parse_value("(($ptr | 0x10) & 0xFFF0)", &context).unwrap());
+        assert_eq!(0x13, parse_value("(>($word_var + 0x100))", &context).unwrap());
+        assert_eq!(0x34, parse_value("(<$word_var)", &context).unwrap());
+        assert_eq!(0x80, parse_value("(1 << 7)", &context).unwrap());
+
+        let pair = PestParser::parse(Rule::memory_address, "($ptr + 2)").unwrap().next().unwrap();
+        assert_eq!(0x0402, context.parse_memory(&pair).unwrap());
+        assert_eq!(
+            "variable 'pending' is only known when its let runs",
+            context.resolve_symbol("pending").unwrap_err().to_string()
+        );
+        let pair = PestParser::parse(Rule::symbol_byte_reference, ">($ptr + 0x100)").unwrap().next().unwrap();
+        assert_eq!(0x05, context.parse_symbol_byte(&pair).unwrap());
+    }
+
+    #[test]
+    fn test_computed_value_errors() {
+        let symbols = setup_test_symbols();
+        let context = ParserContext::new(Some(&symbols));
+
+        let pair = PestParser::parse(Rule::value8, "($byte_var * 8)").unwrap().next().unwrap();
+        assert_eq!(
+            "Value 0x1A0 of ($byte_var * 8) is too large for 8-bit value",
+            context.parse_source_value(&pair).unwrap_err().to_string()
+        );
+        assert!(parse_value("($byte_var - 0x35)", &context).is_err());
+        assert!(parse_value("($missing + 1)", &context).is_err());
+        assert!(parse_value("(X + 1)", &context).unwrap_err().is::<PendingValue>());
+        assert!(PestParser::parse(Rule::value16, "($counter +)").is_err());
+    }
+
+    #[test]
+    fn test_runtime_values() {
+        let symbols = setup_test_symbols();
+        let mut memory = Memory::new_with_ram();
+        memory.write(0x2000, &[0x34, 0x12]).unwrap();
+        let mut registers = Registers::new(0x1000);
+        registers.register_x = 0x02;
+        let context = ParserContext::new(Some(&symbols)).with_machine(&registers, &memory);
+
+        assert_eq!(0x2004, parse_value("($counter + X * 2)", &context).unwrap());
+        assert_eq!(0x1235, parse_value("(word $counter + 1)", &context).unwrap());
+        let pair = PestParser::parse(Rule::memory_address, "(byte $counter + 0x0300)").unwrap().next().unwrap();
+        assert_eq!(0x0334, context.parse_memory(&pair).unwrap());
+    }
+
+    #[test]
+    fn test_deferred_commands() {
+        let symbols = setup_test_symbols();
+        let mut variables = Variables::new();
+        variables.insert("pending".to_string(), None);
+
+        let context = ParserContext::new(Some(&symbols)).with_variables(&variables);
+        let cli_command = CliCommandParser::from_with_context("memory write ($counter + X * 2) 0x(00,00)", context).unwrap();
+        assert!(matches!(
+            cli_command,
+            CliCommand::Deferred(DeferredCommand { text, variable: None }) if text == "memory write ($counter + X * 2) 0x(00,00)"
+        ));
+        let context = ParserContext::new(Some(&symbols)).with_variables(&variables);
+        let cli_command = CliCommandParser::from_with_context("let next = $pending + 1", context).unwrap();
+        assert!(matches!(cli_command, CliCommand::Deferred(DeferredCommand { variable: Some(name), .. }) if name == "next"));
+
+        let context = ParserContext::new(Some(&symbols)).with_variables(&variables);
+        let error = CliCommandParser::from_with_context("marker $$rows$$ foreach (a) in [($pending)]", context).unwrap_err();
+        assert_eq!("variable 'pending' is only known when its let runs", error.to_string());
+        let memory = Memory::new_with_ram();
+        let registers = Registers::new(0x1000);
+        let context = ParserContext::new(Some(&symbols))
+            .with_variables(&variables)
+            .with_machine(&registers, &memory);
+        assert!(CliCommandParser::from_with_context("memory write ($pending) 0x(00)", context).is_err());
+    }
+
+    #[test]
+    fn test_let_parser() {
+        let symbols = setup_test_symbols();
+        let context = ParserContext::new(Some(&symbols));
+
+        let cli_command = CliCommandParser::from_with_context("let table = $counter + 0x10", context).unwrap();
+        assert!(matches!(
+            cli_command,
+            CliCommand::Let(LetCommand { name, expression }) if name == *"table" && expression.evaluate_constant().unwrap() == 0x2010
+        ));
+        let context = ParserContext::new(Some(&symbols));
+        let cli_command = CliCommandParser::from_with_context("let ptr = word $counter + X * 2", context).unwrap();
+        assert!(matches!(cli_command, CliCommand::Let(LetCommand { expression, .. }) if !expression.is_constant()));
+
+        let context = ParserContext::new(Some(&symbols));
+        assert!(CliCommandParser::from_with_context("let big = $word_var * 0x100", context).is_err());
+        assert!(CliCommandParser::from("let 1x = 2").is_err());
     }
 }
 
@@ -767,6 +1085,77 @@ mod memory_command_parser_tests {
         assert!(matches!(command, MemoryCommand::Flush));
     }
 
+    #[test]
+    fn test_memory_map() {
+        let input = "memory map";
+        let context = create_test_context();
+        let pairs = PestParser::parse(Rule::memory_instruction, input)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+        let command = MemoryCommandParser::from_pairs(pairs, &context).unwrap();
+
+        assert!(matches!(command, MemoryCommand::Map));
+    }
+
+    #[test]
+    fn test_memory_policy_unmapped() {
+        let context = create_test_context();
+        for (input, expected) in [
+            ("memory policy unmapped error", UnmappedPolicy::Error),
+            ("memory policy unmapped open_bus", UnmappedPolicy::OpenBus),
+            ("memory policy unmapped warn", UnmappedPolicy::Warn),
+            ("memory policy unmapped 0xff", UnmappedPolicy::Fixed(0xff)),
+        ] {
+            let pairs = PestParser::parse(Rule::memory_instruction, input)
+                .unwrap()
+                .next()
+                .unwrap()
+                .into_inner();
+            let command = MemoryCommandParser::from_pairs(pairs, &context).unwrap();
+
+            assert!(
+                matches!(command, MemoryCommand::SetUnmappedPolicy { policy } if policy == expected),
+                "parsing '{input}'"
+            );
+        }
+    }
+
+    #[test]
+    fn test_memory_policy_rom() {
+        let context = create_test_context();
+        for (input, expected) in [
+            ("memory policy rom error", ReadOnlyPolicy::Error),
+            ("memory policy rom ignore", ReadOnlyPolicy::Ignore),
+            ("memory policy rom warn", ReadOnlyPolicy::Warn),
+        ] {
+            let pairs = PestParser::parse(Rule::memory_instruction, input)
+                .unwrap()
+                .next()
+                .unwrap()
+                .into_inner();
+            let command = MemoryCommandParser::from_pairs(pairs, &context).unwrap();
+
+            assert!(
+                matches!(command, MemoryCommand::SetReadOnlyPolicy { policy } if policy == expected),
+                "parsing '{input}'"
+            );
+        }
+    }
+
+    #[test]
+    fn test_memory_policy_invalid() {
+        assert!(PestParser::parse(Rule::memory_instruction, "memory policy rom open_bus").is_err());
+        let context = create_test_context();
+        let pairs = PestParser::parse(Rule::memory_instruction, "memory policy unmapped 256")
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+        assert!(MemoryCommandParser::from_pairs(pairs, &context).is_err());
+    }
+
     #[test]
     fn test_memory_write() {
         let input = "memory write #0x1234 0x(01,02,03)";
@@ -785,7 +1174,7 @@ mod memory_command_parser_tests {
 
     #[test]
     fn test_memory_write_with_symbols() {
-        let symbols = test_utils::setup_test_symbols();
+        let symbols = crate::test_utils::setup_test_symbols();
         let context = ParserContext::new(Some(&symbols));
         let input = "memory write $word_var 0x(a9,c0)";
         let pairs = PestParser::parse(Rule::memory_instruction, input)
@@ -803,7 +1192,7 @@ mod memory_command_parser_tests {
 
     #[test]
     fn test_memory_write_with_memory_location() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "somewhere".to_string());
         symbols.add_symbol(0x1234, "address".to_string());
         let context = ParserContext::new(Some(&symbols));
@@ -1014,13 +1403,13 @@ mod memory_command_parser_tests {
         let command = MemoryCommandParser::from_pairs(pairs, &context).unwrap();
 
         assert!(
-            matches!(command, MemoryCommand::Load { address, filepath } if address == 0x1000 && filepath == PathBuf::from("script.txt"))
+            matches!(command, MemoryCommand::Load { address, filepath } if address == 0x1000 && filepath.as_os_str() == "script.txt")
         );
     }
 
     #[test]
     fn test_memory_load_with_symbols() {
-        let symbols = test_utils::setup_test_symbols();
+        let symbols = crate::test_utils::setup_test_symbols();
         let context = ParserContext::new(Some(&symbols));
         let input = "memory load $word_var \"script.txt\"";
         let pairs = PestParser::parse(Rule::memory_instruction, input)
@@ -1031,7 +1420,7 @@ mod memory_command_parser_tests {
         let command = MemoryCommandParser::from_pairs(pairs, &context).unwrap();
 
         assert!(
-            matches!(command, MemoryCommand::Load { address, filepath } if address == 0x1234 && filepath == PathBuf::from("script.txt"))
+            matches!(command, MemoryCommand::Load { address, filepath } if address == 0x1234 && filepath.as_os_str() == "script.txt")
         );
     }
 
@@ -1085,6 +1474,7 @@ mod memory_command_parser_tests {
         // Test that only valid targets are accepted by the grammar
         assert!(PestParser::parse(Rule::target_name, "atari").is_ok());
         assert!(PestParser::parse(Rule::target_name, "apple").is_ok());
+        assert!(PestParser::parse(Rule::target_name, "c64").is_ok());
         assert!(PestParser::parse(Rule::target_name, "invalid_target").is_err());
     }
 
@@ -1176,7 +1566,7 @@ mod memory_command_parser_tests {
         );
 
         // Test fill with symbols and offsets
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "array".to_string());
         let context = ParserContext::new(Some(&symbols));
         
@@ -1229,7 +1619,7 @@ mod memory_command_parser_tests {
         );
 
         // Test with symbol and 16-bit length
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "data".to_string());
         let context = ParserContext::new(Some(&symbols));
         
@@ -1389,42 +1779,348 @@ mod symbol_command_parser_tests {
         let command = MemoryCommandParser::from_pairs(pairs, &context).unwrap();
 
         assert!(matches!(
-            command,
-            MemoryCommand::AddSymbol { name, value }
-            if name == "baz" && value == 0x12
+            command,
+            MemoryCommand::AddSymbol { name, value }
+            if name == "baz" && value == 0x12
+        ));
+    }
+
+    #[test]
+    fn test_symbol_remove_parser() {
+        let cli_command = CliCommandParser::from("symbols remove foo").unwrap();
+        assert!(matches!(
+            cli_command,
+            CliCommand::Memory(MemoryCommand::RemoveSymbol { name })
+            if name == "foo"
+        ));
+    }
+
+    #[test]
+    fn test_symbol_remove_with_existing_symbol() {
+        // First create a context with a symbol
+        let mut symbols = SymbolTable::new();
+        symbols.add_symbol(0x1234, "foo".to_string());
+        let context = ParserContext::new(Some(&symbols));
+
+        let input = "symbols remove foo";
+        let pairs = PestParser::parse(Rule::symbols_instruction, input)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+        let command = MemoryCommandParser::from_pairs(pairs, &context).unwrap();
+
+        assert!(matches!(
+            command,
+            MemoryCommand::RemoveSymbol { name }
+            if name == "foo"
+        ));
+    }
+}
+
+pub struct DeviceCommandParser<'a> {
+    context: &'a ParserContext<'a>,
+}
+
+impl<'a> DeviceCommandParser<'a> {
+    pub fn new(context: &'a ParserContext<'a>) -> Self {
+        Self { context }
+    }
+
+    pub fn from_pairs(pairs: Pairs<'_, Rule>, context: &'a ParserContext<'a>) -> AppResult<DeviceCommand> {
+        let parser = Self::new(context);
+        parser.parse_pairs(pairs)
+    }
+
+    fn parse_pairs(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<DeviceCommand> {
+        let pair = pairs.next().unwrap();
+
+        let command = match pair.as_rule() {
+            Rule::device_lcd => self.handle_device_lcd(pair.into_inner())?,
+            Rule::device_sdcard => self.handle_device_sdcard(pair.into_inner())?,
+            Rule::device_disk => self.handle_device_disk(pair.into_inner())?,
+            Rule::device_timer => DeviceCommand::AddTimer {
+                address: self.context.parse_memory(&pair.into_inner().next().unwrap())?,
+            },
+            Rule::device_rtc => DeviceCommand::AddRtc {
+                address: self.context.parse_memory(&pair.into_inner().next().unwrap())?,
+            },
+            Rule::device_keyboard => {
+                let mut pairs = pair.into_inner();
+                let address = self.context.parse_memory(&pairs.next().unwrap())?;
+                let kind = match pairs.next().unwrap().as_str().to_lowercase().as_str() {
+                    "ascii" => KeyboardKind::Ascii,
+                    "matrix" => KeyboardKind::Matrix,
+                    _ => KeyboardKind::Ps2,
+                };
+                DeviceCommand::AddKeyboard { address, kind }
+            }
+            Rule::keyboard_type => self.handle_keyboard_type(pair.into_inner())?,
+            Rule::machine_name => match pair.as_str().to_lowercase().as_str() {
+                "apple" => DeviceCommand::AddMachine { machine: Machine::Apple },
+                "atari" => DeviceCommand::AddMachine { machine: Machine::Atari },
+                _ => DeviceCommand::AddMachine { machine: Machine::C64 },
+            },
+            Rule::atari_load => {
+                let filepath = self.context.parse_filename(&pair.into_inner().next().unwrap())?;
+                DeviceCommand::LoadXex { sections: AtariBinary::from_file(&filepath)?.into_sections() }
+            }
+            Rule::device_sound => self.handle_device_sound(pair.into_inner())?,
+            Rule::sound_save => {
+                let mut pairs = pair.into_inner();
+                let format = match pairs.next().unwrap().as_str().to_lowercase().as_str() {
+                    "wav" => SoundFormat::Wav,
+                    _ => SoundFormat::Log,
+                };
+                let filepath = self.context.parse_filename(&pairs.next().unwrap())?;
+                DeviceCommand::SaveSound { format, filepath }
+            }
+            Rule::rtc_set => {
+                let datetime = pair.into_inner().next().unwrap().as_str();
+                DeviceCommand::SetRtc { datetime: datetime.parse().map_err(|e: String| anyhow!(e))? }
+            }
+            _ => return Err(anyhow!("Unknown device command")),
+        };
+
+        Ok(command)
+    }
+
+    fn handle_device_lcd(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<DeviceCommand> {
+        let address = self.context.parse_memory(&pairs.next().unwrap())?;
+        let (columns, rows) = match pairs.next() {
+            Some(size_node) => {
+                let (columns, rows) = size_node.as_str().split_once('x').unwrap();
+                (columns.parse::<usize>()?, rows.parse::<usize>()?)
+            }
+            None => (16, 2),
+        };
+        if !(1..=4).contains(&rows) || columns == 0 || columns * rows > 80 {
+            return Err(anyhow!("Invalid LCD size {}x{}, the controller drives up to 80 characters on 1 to 4 lines", columns, rows));
+        }
+
+        Ok(DeviceCommand::AddLcd { address, columns, rows })
+    }
+
+    fn handle_device_sdcard(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<DeviceCommand> {
+        let address = self.context.parse_memory(&pairs.next().unwrap())?;
+        let filepath = self.context.parse_filename(&pairs.next().unwrap())?;
+        let pins = match pairs.next() {
+            Some(pins_node) => {
+                let pins: Vec<u8> = pins_node
+                    .into_inner()
+                    .map(|pin| pin.as_str().parse::<u8>())
+                    .collect::<Result<_, _>>()?;
+                if (1..pins.len()).any(|i| pins[i..].contains(&pins[i - 1])) {
+                    return Err(anyhow!("SPI pins shall be wired to different bits of the port"));
+                }
+                SpiPins { sck: pins[0], mosi: pins[1], miso: pins[2], cs: pins[3] }
+            }
+            None => SpiPins::default(),
+        };
+
+        Ok(DeviceCommand::AddSdCard { address, filepath, pins })
+    }
+
+    fn handle_device_sound(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<DeviceCommand> {
+        let address = self.context.parse_memory(&pairs.next().unwrap())?;
+        let mut clock = DEFAULT_SOUND_CLOCK;
+        let mut sample_rate = DEFAULT_SOUND_SAMPLE_RATE;
+        for node in pairs {
+            match node.as_rule() {
+                Rule::sound_clock => clock = node.as_str().parse::<u64>()?,
+                Rule::sound_rate => sample_rate = node.as_str().parse::<u32>()?,
+                rule => panic!("unknown node type {rule:?}. Is the Pest grammar up to date?"),
+            }
+        }
+        if clock == 0 || sample_rate == 0 {
+            return Err(anyhow!("the clock and the sample rate of the sound chip cannot be 0"));
+        }
+
+        Ok(DeviceCommand::AddSound { address, clock, sample_rate })
+    }
+
+    fn handle_keyboard_type(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<DeviceCommand> {
+        let literal = pairs.next().unwrap().as_str();
+        let keys = self.context.parse_string_literal(&literal[1..literal.len() - 1]);
+        let delay = match pairs.next() {
+            Some(delay_node) => delay_node.as_str().parse::<u64>()?,
+            None => DEFAULT_KEYBOARD_DELAY,
+        };
+
+        Ok(DeviceCommand::TypeKeys { keys, delay })
+    }
+
+    fn handle_device_disk(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<DeviceCommand> {
+        let address = self.context.parse_memory(&pairs.next().unwrap())?;
+        let filepath = self.context.parse_filename(&pairs.next().unwrap())?;
+
+        Ok(DeviceCommand::AddDisk { address, filepath })
+    }
+}
+
+#[cfg(test)]
+mod device_parser_tests {
+    use super::*;
+
+    fn parse(input: &str) -> AppResult<DeviceCommand> {
+        let context = ParserContext::new(None);
+        let pairs = PestParser::parse(Rule::device_instruction, input)?
+            .next()
+            .unwrap()
+            .into_inner();
+
+        DeviceCommandParser::from_pairs(pairs, &context)
+    }
+
+    #[test]
+    fn test_device_lcd() {
+        assert!(matches!(
+            parse("device lcd #0x8000").unwrap(),
+            DeviceCommand::AddLcd { address: 0x8000, columns: 16, rows: 2 }
+        ));
+        assert!(matches!(
+            parse("device lcd #0xB000 20x4").unwrap(),
+            DeviceCommand::AddLcd { address: 0xB000, columns: 20, rows: 4 }
+        ));
+        assert!(parse("device lcd #0xB000 40x4").is_err());
+        assert!(parse("device lcd #0xB000 16x0").is_err());
+    }
+
+    #[test]
+    fn test_device_sdcard() {
+        assert!(matches!(
+            parse("device sdcard #0x8000 \"disk.img\"").unwrap(),
+            DeviceCommand::AddSdCard { address: 0x8000, filepath, pins }
+            if filepath.to_str() == Some("disk.img") && pins == SpiPins::default()
+        ));
+        assert!(matches!(
+            parse("device sdcard #0x8000 \"disk.img\" pins 4 5 6 3").unwrap(),
+            DeviceCommand::AddSdCard { pins: SpiPins { sck: 4, mosi: 5, miso: 6, cs: 3 }, .. }
+        ));
+        assert!(parse("device sdcard #0x8000 \"disk.img\" pins 4 5 4 3").is_err());
+    }
+
+    #[test]
+    fn test_device_disk() {
+        assert!(matches!(
+            parse("device disk #0xB100 \"boot.img\"").unwrap(),
+            DeviceCommand::AddDisk { address: 0xB100, filepath }
+            if filepath.to_str() == Some("boot.img")
+        ));
+        assert!(parse("device disk #0xB100").is_err());
+    }
+
+    #[test]
+    fn test_device_timer_rtc() {
+        assert!(matches!(parse("device timer #0xB200").unwrap(), DeviceCommand::AddTimer { address: 0xB200 }));
+        assert!(matches!(parse("device rtc #0xB210").unwrap(), DeviceCommand::AddRtc { address: 0xB210 }));
+    }
+
+    #[test]
+    fn test_device_keyboard() {
+        assert!(matches!(
+            parse("device keyboard #0xC000 ascii").unwrap(),
+            DeviceCommand::AddKeyboard { address: 0xC000, kind: KeyboardKind::Ascii }
+        ));
+        assert!(matches!(
+            parse("device keyboard #0xDC00 MATRIX").unwrap(),
+            DeviceCommand::AddKeyboard { kind: KeyboardKind::Matrix, .. }
+        ));
+        assert!(matches!(
+            parse("device keyboard #0xB300 ps2").unwrap(),
+            DeviceCommand::AddKeyboard { kind: KeyboardKind::Ps2, .. }
+        ));
+    }
+
+    #[test]
+    fn test_keyboard_type() {
+        let context = ParserContext::new(None);
+        let parse = |input| {
+            let pairs = PestParser::parse(Rule::keyboard_instruction, input)?.next().unwrap().into_inner();
+            DeviceCommandParser::from_pairs(pairs, &context)
+        };
+        assert!(matches!(
+            parse("keyboard type \"RUN\\n\"").unwrap(),
+            DeviceCommand::TypeKeys { keys, delay: DEFAULT_KEYBOARD_DELAY } if keys == b"RUN\n"
+        ));
+        assert!(matches!(
+            parse("keyboard type \"A\" delay 500").unwrap(),
+            DeviceCommand::TypeKeys { keys, delay: 500 } if keys == b"A"
+        ));
+    }
+
+    #[test]
+    fn test_device_sound() {
+        assert!(matches!(
+            parse("device sound #0xB300").unwrap(),
+            DeviceCommand::AddSound { address: 0xB300, clock: DEFAULT_SOUND_CLOCK, sample_rate: DEFAULT_SOUND_SAMPLE_RATE }
+        ));
+        assert!(matches!(
+            parse("device sound #0xB300 clock 1773400").unwrap(),
+            DeviceCommand::AddSound { clock: 1773400, sample_rate: DEFAULT_SOUND_SAMPLE_RATE, .. }
+        ));
+        assert!(matches!(
+            parse("device sound #0xB300 clock 2000000 rate 22050").unwrap(),
+            DeviceCommand::AddSound { clock: 2000000, sample_rate: 22050, .. }
+        ));
+        assert!(matches!(
+            parse("device sound #0xB300 rate 8000").unwrap(),
+            DeviceCommand::AddSound { clock: DEFAULT_SOUND_CLOCK, sample_rate: 8000, .. }
+        ));
+        assert!(parse("device sound #0xB300 rate 0").is_err());
+        let context = ParserContext::new(None);
+        let parse = |input| {
+            let pairs = PestParser::parse(Rule::sound_instruction, input)?.next().unwrap().into_inner();
+            DeviceCommandParser::from_pairs(pairs, &context)
+        };
+        assert!(matches!(
+            parse("sound save wav \"music.wav\"").unwrap(),
+            DeviceCommand::SaveSound { format: SoundFormat::Wav, filepath } if filepath.to_str() == Some("music.wav")
+        ));
+        assert!(matches!(
+            parse("sound save log \"music.log\"").unwrap(),
+            DeviceCommand::SaveSound { format: SoundFormat::Log, .. }
         ));
+        assert!(parse("sound save \"music.wav\"").is_err());
     }
 
     #[test]
-    fn test_symbol_remove_parser() {
-        let cli_command = CliCommandParser::from("symbols remove foo").unwrap();
+    fn test_machine() {
+        let context = ParserContext::new(None);
+        let pairs = PestParser::parse(Rule::machine_instruction, "machine apple").unwrap().next().unwrap().into_inner();
         assert!(matches!(
-            cli_command,
-            CliCommand::Memory(MemoryCommand::RemoveSymbol { name })
-            if name == "foo"
+            DeviceCommandParser::from_pairs(pairs, &context).unwrap(),
+            DeviceCommand::AddMachine { machine: Machine::Apple }
+        ));
+        let pairs = PestParser::parse(Rule::machine_instruction, "machine atari").unwrap().next().unwrap().into_inner();
+        assert!(matches!(
+            DeviceCommandParser::from_pairs(pairs, &context).unwrap(),
+            DeviceCommand::AddMachine { machine: Machine::Atari }
+        ));
+        let pairs = PestParser::parse(Rule::machine_instruction, "machine C64").unwrap().next().unwrap().into_inner();
+        assert!(matches!(
+            DeviceCommandParser::from_pairs(pairs, &context).unwrap(),
+            DeviceCommand::AddMachine { machine: Machine::C64 }
         ));
+        assert!(PestParser::parse(Rule::machine_instruction, "machine amiga").is_err());
+
+        let pairs = PestParser::parse(Rule::atari_instruction, "atari load \"missing.xex\"").unwrap().next().unwrap().into_inner();
+        assert!(DeviceCommandParser::from_pairs(pairs, &context).is_err());
     }
 
     #[test]
-    fn test_symbol_remove_with_existing_symbol() {
-        // First create a context with a symbol
-        let mut symbols = SymbolTable::new();
-        symbols.add_symbol(0x1234, "foo".to_string());
-        let context = ParserContext::new(Some(&symbols));
-
-        let input = "symbols remove foo";
-        let pairs = PestParser::parse(Rule::symbols_instruction, input)
-            .unwrap()
-            .next()
-            .unwrap()
-            .into_inner();
-        let command = MemoryCommandParser::from_pairs(pairs, &context).unwrap();
-
+    fn test_rtc_set() {
+        let context = ParserContext::new(None);
+        let parse = |input| {
+            let pairs = PestParser::parse(Rule::rtc_instruction, input)?.next().unwrap().into_inner();
+            DeviceCommandParser::from_pairs(pairs, &context)
+        };
         assert!(matches!(
-            command,
-            MemoryCommand::RemoveSymbol { name }
-            if name == "foo"
+            parse("rtc set 2024-01-01T00:00:00").unwrap(),
+            DeviceCommand::SetRtc { datetime } if datetime.to_string() == "2024-01-01T00:00:00"
         ));
+        assert!(parse("rtc set 2024-02-30T00:00:00").is_err());
+        assert!(parse("rtc set 2024-01-01").is_err());
     }
 }
 
@@ -1851,6 +2547,59 @@ mod register_parser_tests {
         );
     }
 
+    #[test]
+    fn test_assert_lcd_line() {
+        let context = create_test_context();
+        let input = "assert lcd line 1 ~ \"HELLO\" AND A = 0x00 $$check the LCD$$";
+        let pairs = PestParser::parse(Rule::assert_instruction, input)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+        let command = AssertCommandParser::from_pairs(pairs, &context).unwrap();
+
+        match command.condition {
+            BooleanExpression::And(left, _) => assert!(
+                matches!(*left, BooleanExpression::LcdLine(1, ref text) if text == b"HELLO")
+            ),
+            v => panic!("Expected And, got {:?}", v),
+        }
+
+        let input = "assert sound log ~ \"music.log\" $$check the music$$";
+        let pairs = PestParser::parse(Rule::assert_instruction, input)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+        let command = AssertCommandParser::from_pairs(pairs, &context).unwrap();
+        assert!(matches!(
+            command.condition,
+            BooleanExpression::SoundLog(ref filepath) if filepath.to_str() == Some("music.log")
+        ));
+
+        let input = "assert screen ~ \"READY\" AND output ~ \"OK\\n\" $$check the screen$$";
+        let pairs = PestParser::parse(Rule::assert_instruction, input)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+        match AssertCommandParser::from_pairs(pairs, &context).unwrap().condition {
+            BooleanExpression::And(left, right) => {
+                assert!(matches!(*left, BooleanExpression::ScreenText(ref text) if text == b"READY"));
+                assert!(matches!(*right, BooleanExpression::ConsoleOutput(ref text) if text == b"OK\n"));
+            }
+            v => panic!("Expected And, got {:?}", v),
+        }
+
+        let input = "assert lcd line 0 ~ \"HELLO\" $$check the LCD$$";
+        let pairs = PestParser::parse(Rule::assert_instruction, input)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+        assert!(AssertCommandParser::from_pairs(pairs, &context).is_err());
+    }
+
     #[test]
     fn test_assert_memory_sequence_single_digit_hex() {
         let context = create_test_context();
@@ -1956,7 +2705,7 @@ impl<'a> RunCommandParser<'a> {
 #[cfg(test)]
 mod run_command_parser_tests {
     use super::*;
-    use super::test_utils::setup_test_symbols;
+    use crate::test_utils::setup_test_symbols;
     use crate::until_condition::{RegisterSource, Source};
 
     fn create_test_context<'a>() -> ParserContext<'a> {
@@ -2263,7 +3012,7 @@ mod run_command_parser_tests {
 
     #[test]
     fn test_run_with_while_and_symbol() {
-        let symbols = test_utils::setup_test_symbols();
+        let symbols = crate::test_utils::setup_test_symbols();
         let context = ParserContext::new(Some(&symbols));
         let input = "run while $byte_var = 0x42";
         
@@ -2287,6 +3036,238 @@ mod run_command_parser_tests {
     }
 }
 
+pub struct CallCommandParser<'a> {
+    context: &'a ParserContext<'a>,
+}
+
+impl<'a> CallCommandParser<'a> {
+    pub fn new(context: &'a ParserContext<'a>) -> Self {
+        Self { context }
+    }
+
+    pub fn from_pairs(pairs: Pairs<'_, Rule>, context: &'a ParserContext<'a>) -> AppResult<CallCommand> {
+        let parser = Self::new(context);
+        parser.parse_pairs(pairs)
+    }
+
+    fn parse_pairs(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<CallCommand> {
+        let address = self.context.parse_memory(&pairs.next().unwrap())?;
+        let mut arguments = Vec::new();
+        let mut limit = DEFAULT_CALL_LIMIT;
+
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::call_argument => arguments.push(self.parse_argument(pair.into_inner())?),
+                Rule::call_limit => limit = self.parse_limit(pair.into_inner())?,
+                stmt => panic!("unknown node type {stmt:?}. Is the Pest grammar up to date?"),
+            }
+        }
+
+        Ok(CallCommand { address, arguments, limit })
+    }
+
+    fn parse_argument(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<Assignment> {
+        let destination = match self.context.parse_source_register(&pairs.next().unwrap()) {
+            Source::Register(register) => register,
+            _ => unreachable!("a register source is always a register"),
+        };
+        let value_node = pairs.next().unwrap();
+        let source = match value_node.as_rule() {
+            Rule::value8 => self.context.parse_source_value(&value_node)?,
+            Rule::symbol_byte_reference => Source::Value(self.context.parse_symbol_byte(&value_node)?),
+            Rule::memory_address => {
+                let value = self.context.parse_memory(&value_node)?;
+                if value > 0xFF {
+                    return Err(anyhow!("Value 0x{:X} is too large for 8-bit register", value));
+                }
+                Source::Value(value)
+            }
+            v => panic!("unexpected node '{:?}' here.", v),
+        };
+
+        Ok(Assignment::new(source, destination))
+    }
+
+    fn parse_limit(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<CallLimit> {
+        let value_node = pairs.next().unwrap();
+        let value = value_node
+            .as_str()
+            .parse::<u64>()
+            .map_err(|e| anyhow!("Invalid limit '{}': {}", value_node.as_str(), e))?;
+        if value == 0 {
+            return Err(anyhow!("The call limit must be greater than 0"));
+        }
+
+        Ok(match pairs.next().unwrap().as_rule() {
+            Rule::limit_cycles => CallLimit::Cycles(value),
+            _ => CallLimit::Instructions(value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod call_command_parser_tests {
+    use super::*;
+    use crate::test_utils::setup_test_symbols;
+    use crate::until_condition::RegisterSource;
+
+    fn parse(input: &str, context: &ParserContext) -> AppResult<CallCommand> {
+        let pairs = PestParser::parse(Rule::call_instruction, input)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+
+        CallCommandParser::from_pairs(pairs, context)
+    }
+
+    #[test]
+    fn simple_call() {
+        let context = ParserContext::new(None);
+        let command = parse("call #0x1234", &context).unwrap();
+
+        assert_eq!(0x1234, command.address);
+        assert!(command.arguments.is_empty());
+        assert_eq!(DEFAULT_CALL_LIMIT, command.limit);
+    }
+
+    #[test]
+    fn call_with_arguments_and_limit() {
+        let symbols = setup_test_symbols();
+        let context = ParserContext::new(Some(&symbols));
+        let command = parse("call $counter A=0x12 X=<$word_var Y=$byte_var limit 500 instructions", &context).unwrap();
+
+        assert_eq!(0x2000, command.address);
+        assert_eq!(3, command.arguments.len());
+        assert!(matches!(
+            command.arguments[0],
+            Assignment { source: Source::Value(0x12), destination: RegisterSource::Accumulator }
+        ));
+        assert!(matches!(
+            command.arguments[1],
+            Assignment { source: Source::Value(0x34), destination: RegisterSource::RegisterX }
+        ));
+        assert!(matches!(
+            command.arguments[2],
+            Assignment { source: Source::Value(0x34), destination: RegisterSource::RegisterY }
+        ));
+        assert_eq!(CallLimit::Instructions(500), command.limit);
+
+        let command = parse("call #0x1000 limit 2000 cycles", &context).unwrap();
+        assert_eq!(CallLimit::Cycles(2000), command.limit);
+    }
+
+    #[test]
+    fn call_errors() {
+        let symbols = setup_test_symbols();
+        let context = ParserContext::new(Some(&symbols));
+        assert!(parse("call #0x1000 A=$word_var", &context).is_err());
+        assert!(parse("call $missing", &context).is_err());
+        assert!(parse("call #0x1000 limit 0 cycles", &context).is_err());
+    }
+}
+
+pub struct MockCommandParser<'a> {
+    context: &'a ParserContext<'a>,
+}
+
+impl<'a> MockCommandParser<'a> {
+    pub fn new(context: &'a ParserContext<'a>) -> Self {
+        Self { context }
+    }
+
+    pub fn from_pairs(pairs: Pairs<'_, Rule>, context: &'a ParserContext<'a>) -> AppResult<MockCommand> {
+        let parser = Self::new(context);
+        parser.parse_pairs(pairs)
+    }
+
+    fn parse_pairs(&self, mut pairs: Pairs<'_, Rule>) -> AppResult<MockCommand> {
+        let address = self.context.parse_memory(&pairs.next().unwrap())?;
+        let action = pairs.next().unwrap();
+        let returns = match action.as_rule() {
+            Rule::mock_returns => {
+                let call_parser = CallCommandParser::new(self.context);
+                action
+                    .into_inner()
+                    .map(|pair| call_parser.parse_argument(pair.into_inner()))
+                    .collect::<AppResult<Vec<Assignment>>>()?
+            }
+            Rule::mock_record => Vec::new(),
+            stmt => panic!("unknown node type {stmt:?}. Is the Pest grammar up to date?"),
+        };
+
+        Ok(MockCommand { address, returns })
+    }
+}
+
+#[cfg(test)]
+mod mock_command_parser_tests {
+    use super::*;
+    use crate::test_utils::setup_test_symbols;
+
+    fn parse(input: &str, context: &ParserContext) -> AppResult<MockCommand> {
+        let pairs = PestParser::parse(Rule::mock_instruction, input)
+            .unwrap()
+            .next()
+            .unwrap()
+            .into_inner();
+
+        MockCommandParser::from_pairs(pairs, context)
+    }
+
+    #[test]
+    fn mock_returns_and_record() {
+        let symbols = setup_test_symbols();
+        let context = ParserContext::new(Some(&symbols));
+        let command = parse("mock $counter returns A=0x00 X=<$word_var", &context).unwrap();
+
+        assert_eq!(0x2000, command.address);
+        assert_eq!(2, command.returns.len());
+        assert!(matches!(
+            command.returns[0],
+            Assignment { source: Source::Value(0x00), destination: RegisterSource::Accumulator }
+        ));
+        assert!(matches!(
+            command.returns[1],
+            Assignment { source: Source::Value(0x34), destination: RegisterSource::RegisterX }
+        ));
+
+        let command = parse("mock #0xFFD2 record", &context).unwrap();
+        assert_eq!(0xFFD2, command.address);
+        assert!(command.returns.is_empty());
+    }
+
+    #[test]
+    fn mock_errors() {
+        let symbols = setup_test_symbols();
+        let context = ParserContext::new(Some(&symbols));
+        assert!(parse("mock $missing record", &context).is_err());
+        assert!(parse("mock #0x1000 returns A=$word_var", &context).is_err());
+        assert!(PestParser::parse(Rule::mock_instruction, "mock #0x1000 returns").is_err());
+        assert!(PestParser::parse(Rule::mock_instruction, "mock #0x1000").is_err());
+    }
+
+    #[test]
+    fn assert_calls() {
+        let symbols = setup_test_symbols();
+        let context = ParserContext::new(Some(&symbols));
+        let parse_assert = |input: &str| {
+            let pairs = PestParser::parse(Rule::assert_instruction, input).unwrap().next().unwrap().into_inner();
+            AssertCommandParser::from_pairs(pairs, &context)
+        };
+
+        let command = parse_assert("assert calls $counter = 2 $$two calls$$").unwrap();
+        assert_eq!("calls #0x2000 = 0x02", command.condition.to_string());
+        let command = parse_assert("assert call 1 $counter A = <$word_var $$first argument$$").unwrap();
+        assert_eq!("call 1 #0x2000 A = 0x34", command.condition.to_string());
+        let command = parse_assert("assert call 2 #0xFFD2 #0x0080 != 0x00 $$pointer set$$").unwrap();
+        assert_eq!("call 2 #0xFFD2 #0x0080 != 0x00", command.condition.to_string());
+
+        assert!(parse_assert("assert call 0 $counter A = 0x00 $$numbered from 1$$").is_err());
+        assert!(parse_assert("assert call 1 $counter #0x0200 = 0x00 $$not recorded$$").is_err());
+    }
+}
+
 pub struct AssertCommandParser<'a> {
     context: &'a ParserContext<'a>,
 }
@@ -2405,7 +3386,7 @@ impl<'a> AssertCommandParser<'a> {
 #[cfg(test)]
 mod assert_parser_tests {
     use super::*;
-    use super::test_utils::setup_test_symbols;
+    use crate::test_utils::setup_test_symbols;
 
     fn create_test_context<'a>() -> ParserContext<'a> {
         ParserContext::new(None)
@@ -2661,7 +3642,7 @@ mod assert_parser_tests {
     #[test]
     fn test_pointer_assertion() {
         // Create context with necessary symbols
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "entry_loc".to_string());
         symbols.add_symbol(0x2000, "cache".to_string());
         let context = ParserContext::new(Some(&symbols));
@@ -2750,7 +3731,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_pointer_assertion_with_symbols() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "entry_loc".to_string());
         symbols.add_symbol(0x2000, "cache".to_string());
         let context = ParserContext::new(Some(&symbols));
@@ -2786,7 +3767,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_pointer_assertion_wrapping() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "ptr".to_string());
         symbols.add_symbol(0xFFE0, "near_end".to_string());
         let context = ParserContext::new(Some(&symbols));
@@ -2822,7 +3803,7 @@ mod assert_parser_tests {
     #[test]
     fn test_pointer_assertion_with_negative_offset() {
         // Create context with necessary symbols
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "ptr".to_string());
         symbols.add_symbol(0x2000, "base".to_string());
         let context = ParserContext::new(Some(&symbols));
@@ -2857,7 +3838,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_pointer_assertion_wrapping_with_negative_offset() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "ptr".to_string());
         symbols.add_symbol(0x0020, "near_start".to_string());
         let context = ParserContext::new(Some(&symbols));
@@ -2892,7 +3873,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_pointer_assertion_with_decimal_negative_offset() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "ptr".to_string());
         symbols.add_symbol(0x2000, "base".to_string());
         let context = ParserContext::new(Some(&symbols));
@@ -2926,7 +3907,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_assert_with_memory_offset() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x2000, "cache".to_string());
         let context = ParserContext::new(Some(&symbols));
         
@@ -2970,7 +3951,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_assert_memory_sequence_with_offset() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x2000, "cache".to_string());
         let context = ParserContext::new(Some(&symbols));
         
@@ -3014,7 +3995,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_memory_offset_wrapping() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0xFFFF, "end".to_string());
         symbols.add_symbol(0x0000, "start".to_string());
         let context = ParserContext::new(Some(&symbols));
@@ -3056,7 +4037,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_assert_with_symbol_byte_references() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1234, "test_str1".to_string());
         let context = ParserContext::new(Some(&symbols));
 
@@ -3095,7 +4076,7 @@ mod assert_parser_tests {
 
     #[test]
     fn test_assert_with_symbol_byte_references_memory_comparison() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1234, "test_str1".to_string());
         symbols.add_symbol(0x2000, "cps_strings_a".to_string());
         symbols.add_symbol(0x2001, "cps_strings_x".to_string());
@@ -3150,7 +4131,7 @@ mod assert_parser_tests {
         assert!(result.unwrap_err().to_string().contains("Symbol table not available"));
 
         // Test that missing symbol produces error
-        let symbols = test_utils::setup_test_symbols();
+        let symbols = crate::test_utils::setup_test_symbols();
         let context = ParserContext::new(Some(&symbols));
 
         let pairs = PestParser::parse(Rule::assert_instruction, "assert A = >$nonexistent $$error test$$")
@@ -3178,7 +4159,98 @@ impl<'a> CliCommandParser<'a> {
     // Main method used in production code, takes a context with symbols
     pub fn from_with_context(line: &str, context: ParserContext<'a>) -> AppResult<CliCommand> {
         let parser = Self { context };
-        parser.parse_line(line)
+        match parser.parse_line(line) {
+            Err(e) if e.is::<PendingValue>() && parser.context.machine.is_none() => Self::defer(line, e),
+            result => result,
+        }
+    }
+
+    /*
+     * A command reading a value only known when it runs is parsed again then,
+     * a marker cannot wait for it.
+     */
+    fn defer(line: &str, error: anyhow::Error) -> AppResult<CliCommand> {
+        let text = line.trim().to_string();
+        let pair = PestParser::parse(Rule::sentence, &text)?
+            .next()
+            .and_then(|pair| pair.into_inner().next())
+            .expect("a command reading a value is an instruction");
+        let variable = match pair.as_rule() {
+            Rule::marker => return Err(error),
+            Rule::let_instruction => Some(pair.into_inner().next().unwrap().as_str().to_string()),
+            _ => None,
+        };
+
+        Ok(CliCommand::Deferred(DeferredCommand { text, variable }))
+    }
+
+    /// Tell if the line is a marker without resolving what it holds, the
+    /// commands of a table of plans are read up to the next one.
+    pub fn is_marker(line: &str) -> bool {
+        Self::instruction_rule(line) == Some(Rule::marker)
+    }
+
+    /// Tell if the line closes a setup or teardown block.
+    pub fn is_block_end(line: &str) -> bool {
+        Self::instruction_rule(line) == Some(Rule::block_end)
+    }
+
+    /// Tell if the line is a command run in the plans, neither a marker nor
+    /// an include reading them. Only its syntax is checked.
+    pub fn is_plan_command(line: &str) -> bool {
+        !matches!(Self::instruction_rule(line), None | Some(Rule::marker | Rule::include_instruction))
+    }
+
+    fn instruction_rule(line: &str) -> Option<Rule> {
+        PestParser::parse(Rule::sentence, line.trim())
+            .ok()
+            .and_then(|mut pairs| pairs.next())
+            .and_then(|pair| pair.into_inner().next())
+            .map(|pair| pair.as_rule())
+    }
+
+    fn parse_plan_table(&self, description: &str, node: Pair<Rule>) -> AppResult<PlanTable> {
+        let mut parameters: Vec<String> = Vec::new();
+        let mut rows = Vec::new();
+        for pair in node.into_inner() {
+            match pair.as_rule() {
+                Rule::symbol_name if parameters.iter().any(|name| name == pair.as_str()) => {
+                    return Err(anyhow!("Parameter '{}' is set twice", pair.as_str()));
+                }
+                Rule::symbol_name => parameters.push(pair.as_str().to_string()),
+                Rule::plan_row => {
+                    let mut row = Vec::new();
+                    for expression in pair.clone().into_inner() {
+                        let value = self
+                            .context
+                            .parse_expression(&expression)?
+                            .evaluate_constant()
+                            .map_err(|e| anyhow!("Cannot compute {}: {}", expression.as_str(), e))?;
+                        if value > 0xFFFF {
+                            return Err(anyhow!("Value 0x{:X} of {} is too large for 16-bit value", value, expression.as_str()));
+                        }
+                        row.push(value);
+                    }
+                    if row.len() != parameters.len() {
+                        return Err(anyhow!(
+                            "Row {} has {} values for {} parameters",
+                            pair.as_str(),
+                            row.len(),
+                            parameters.len()
+                        ));
+                    }
+                    rows.push(row);
+                }
+                v => panic!("unexpected node '{:?}' in plan table", v),
+            }
+        }
+
+        Ok(PlanTable {
+            description: description.to_string(),
+            tags: Vec::new(),
+            parameters,
+            rows,
+        })
     }
 
     fn parse_line(&self, line: &str) -> AppResult<CliCommand> {
@@ -3206,12 +4278,74 @@ impl<'a> CliCommandParser<'a> {
             Rule::run_instruction => {
                 CliCommand::Run(RunCommandParser::from_pairs(pair.into_inner(), &self.context)?)
             }
+            Rule::call_instruction => {
+                CliCommand::Call(CallCommandParser::from_pairs(pair.into_inner(), &self.context)?)
+            }
+            Rule::mock_instruction => {
+                CliCommand::Mock(MockCommandParser::from_pairs(pair.into_inner(), &self.context)?)
+            }
             Rule::assert_instruction => {
                 CliCommand::Assert(AssertCommandParser::from_pairs(pair.into_inner(), &self.context)?)
             }
             Rule::marker => {
-                let marker = pair.into_inner().next().unwrap().as_str();
-                CliCommand::Marker(marker.to_owned())
+                let mut pairs = pair.into_inner().peekable();
+                let marker = pairs.next().unwrap().as_str();
+                let mut tags: Vec<String> = Vec::new();
+                if let Some(tags_node) = pairs.next_if(|pair| pair.as_rule() == Rule::marker_tags) {
+                    for tag in tags_node.into_inner() {
+                        if !tags.iter().any(|known| known == tag.as_str()) {
+                            tags.push(tag.as_str().to_string());
+                        }
+                    }
+                }
+                match pairs.next() {
+                    Some(fuzz) if fuzz.as_rule() == Rule::plan_fuzz => {
+                        let runs_node = fuzz.into_inner().next().unwrap();
+                        let runs = runs_node
+                            .as_str()
+                            .parse::<usize>()
+                            .map_err(|e| anyhow!("Invalid count of fuzz runs '{}': {}", runs_node.as_str(), e))?;
+                        if runs == 0 {
+                            return Err(anyhow!("A fuzz plan needs at least one run"));
+                        }
+                        CliCommand::Fuzz(FuzzPlan {
+                            description: marker.to_owned(),
+                            tags,
+                            runs,
+                            commands: Vec::new(),
+                            path: None,
+                        })
+                    }
+                    Some(table) => CliCommand::Plans(PlanTable {
+                        tags,
+                        ..self.parse_plan_table(marker, table)?
+                    }),
+                    None => CliCommand::Marker { description: marker.to_owned(), tags },
+                }
+            }
+            Rule::let_instruction => {
+                let mut pairs = pair.into_inner();
+                let name = pairs.next().unwrap().as_str().to_string();
+                let expression = self.context.parse_expression(&pairs.next().unwrap())?;
+                if expression.is_constant() {
+                    match expression.evaluate_constant() {
+                        Ok(value) if value > 0xFFFF => {
+                            return Err(anyhow!("Value 0x{:X} of variable '{}' is too large for 16-bit value", value, name));
+                        }
+                        Ok(_) => (),
+                        Err(e) => return Err(anyhow!("Cannot compute variable '{}': {}", name, e)),
+                    }
+                }
+                CliCommand::Let(LetCommand { name, expression })
+            }
+            Rule::setup_block => CliCommand::Setup(SetupBlock {
+                commands: Vec::new(),
+                snapshot: pair.into_inner().next().is_some(),
+            }),
+            Rule::teardown_block => CliCommand::Teardown(Vec::new()),
+            Rule::block_end => return Err(anyhow!("'}}' closes no setup or teardown block")),
+            Rule::include_instruction => {
+                CliCommand::Include(self.context.parse_filename(&pair.into_inner().next().unwrap())?)
             }
             Rule::registers_instruction => {
                 CliCommand::Registers(RegisterCommandParser::from_pairs(pair.into_inner(), &self.context)?)
@@ -3246,6 +4380,10 @@ impl<'a> CliCommandParser<'a> {
                 };
                 CliCommand::Enable(function)
             }
+            Rule::device_instruction | Rule::rtc_instruction | Rule::keyboard_instruction | Rule::sound_instruction | Rule::machine_instruction
+            | Rule::atari_instruction => {
+                CliCommand::Device(DeviceCommandParser::from_pairs(pair.into_inner(), &self.context)?)
+            }
             Rule::disable_instruction => {
                 let mut pairs = pair.into_inner();
                 let function_name = pairs.next().unwrap().as_str();
@@ -3257,7 +4395,7 @@ impl<'a> CliCommandParser<'a> {
             }
             _ => {
                 panic!(
-                    "'{}' was not expected here: 'register|memory|run|assert|reset|symbols|disassemble|enable|disable instruction'.",
+                    "'{}' was not expected here: 'register|memory|run|assert|reset|symbols|disassemble|enable|disable|device instruction'.",
                     pair.as_str()
                 );
             }
@@ -3296,8 +4434,79 @@ mod cli_command_parser_test {
     fn test_marker_cli_parser() {
         let cli_command = CliCommandParser::from("marker $$This is a marker.$$").unwrap();
         assert!(
-            matches!(cli_command, CliCommand::Marker(comment) if comment == *"This is a marker.")
+            matches!(cli_command, CliCommand::Marker { description, tags } if description == *"This is a marker." && tags.is_empty())
         );
+        let cli_command = CliCommandParser::from("marker $$divide by zero$$ tags(math, slow, math)").unwrap();
+        assert!(matches!(cli_command, CliCommand::Marker { tags, .. } if tags == vec!["math", "slow"]));
+        let cli_command = CliCommandParser::from("marker $$table$$ tags(math) foreach (a) in [(1)]").unwrap();
+        assert!(matches!(cli_command, CliCommand::Plans(table) if table.tags == vec!["math"]));
+        let cli_command = CliCommandParser::from("marker $$fuzzed$$ tags(slow) fuzz 10").unwrap();
+        assert!(matches!(cli_command, CliCommand::Fuzz(plan) if plan.tags == vec!["slow"]));
+        assert!(CliCommandParser::from("marker $$broken$$ tags(math").is_err());
+        assert!(CliCommandParser::from("marker $$broken$$ tags()").is_err());
+    }
+
+    #[test]
+    fn test_include_cli_parser() {
+        let cli_command = CliCommandParser::from("include \"${CARGO_MANIFEST_DIR}/common/setup.txt\"").unwrap();
+        let expected = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("common/setup.txt");
+        assert!(matches!(cli_command, CliCommand::Include(path) if path == expected));
+        assert!(CliCommandParser::from("include setup.txt").is_err());
+    }
+
+    #[test]
+    fn test_fuzz_cli_parser() {
+        let cli_command = CliCommandParser::from("marker $$addition$$ fuzz 1000").unwrap();
+        assert!(matches!(cli_command, CliCommand::Fuzz(plan) if plan.description == "addition" && plan.runs == 1000));
+        assert!(CliCommandParser::from("marker $$addition$$ fuzz 0").is_err());
+        assert!(CliCommandParser::from("marker $$addition$$ fuzz").is_err());
+
+        let random = RefCell::new(RandomSource::replay(vec![0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde]));
+        let parse = |line: &str| CliCommandParser::from_with_context(line, ParserContext::new(None).with_random(&random));
+        let cli_command = parse("registers set A=random").unwrap();
+        assert!(matches!(cli_command, CliCommand::Registers(RegisterCommand::Set { assignment })
+            if assignment.source == Source::Value(0x12)));
+        let cli_command = parse("registers set CP=random").unwrap();
+        assert!(matches!(cli_command, CliCommand::Registers(RegisterCommand::Set { assignment })
+            if assignment.source == Source::Value(0x5634)));
+        let cli_command = parse("memory write #0x0200 random 3").unwrap();
+        assert!(matches!(cli_command, CliCommand::Memory(MemoryCommand::Write { address, bytes })
+            if address == 0x0200 && bytes == vec![0x78, 0x9a, 0xbc]));
+        let cli_command = parse("let value = (random & 0x0F)").unwrap();
+        assert!(matches!(cli_command, CliCommand::Let(command) if command.expression.evaluate_constant().unwrap() == 0x0E));
+        assert!(parse("memory write #0xFFFF random 2").is_err());
+
+        let message = CliCommandParser::from("registers set A=random").unwrap_err().to_string();
+        assert_eq!("random values can only be used in fuzz plans", message);
+    }
+
+    #[test]
+    fn test_plan_table_cli_parser() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_symbol(0x0200, "buffer".to_string());
+        let context = ParserContext::new(Some(&symbols));
+        let cli_command =
+            CliCommandParser::from_with_context("marker $$increment$$ foreach (a_in, expected) in [(0x01, 2), (0x7f, ($buffer + 1))]", context).unwrap();
+        let expected = PlanTable {
+            description: "increment".to_string(),
+            tags: Vec::new(),
+            parameters: vec!["a_in".to_string(), "expected".to_string()],
+            rows: vec![vec![0x01, 0x02], vec![0x7f, 0x0201]],
+        };
+        assert!(matches!(cli_command, CliCommand::Plans(table) if table == expected));
+
+        let message = CliCommandParser::from("marker $$x$$ foreach (a, b) in [(1, 2), (3)]").unwrap_err().to_string();
+        assert_eq!("Row (3) has 1 values for 2 parameters", message);
+        let message = CliCommandParser::from("marker $$x$$ foreach (a, a) in [(1, 2)]").unwrap_err().to_string();
+        assert_eq!("Parameter 'a' is set twice", message);
+        assert!(CliCommandParser::from("marker $$x$$ foreach (a) in [(0xFFFF + 1)]").is_err());
+        assert!(CliCommandParser::from("marker $$x$$ foreach (a) in [(1]").is_err());
+        assert!(CliCommandParser::from("marker $$x$$ foreach (a) in [(A)]").is_err());
+
+        assert!(CliCommandParser::is_marker("marker $$x$$ foreach (a) in [($undefined)]"));
+        assert!(CliCommandParser::is_marker("marker $$plain$$"));
+        assert!(!CliCommandParser::is_marker("assert true $$marker$$"));
+        assert!(!CliCommandParser::is_marker("// marker $$commented$$"));
     }
 
     #[test]
@@ -3338,7 +4547,7 @@ mod cli_command_parser_test {
             CliCommand::Memory(MemoryCommand::Load {
                 address,
                 filepath
-            }) if address == 0x1234 && filepath == PathBuf::from("file.test")
+            }) if address == 0x1234 && filepath.as_os_str() == "file.test"
         ));
     }
 
@@ -3481,7 +4690,7 @@ mod cli_command_parser_test {
 #[cfg(test)]
 mod parser_context_tests {
     use super::*;
-    use super::test_utils::setup_test_symbols;
+    use crate::test_utils::setup_test_symbols;
     use crate::until_condition::{BooleanExpression, RegisterSource, Source};
 
     fn create_test_context<'a>() -> ParserContext<'a> {
@@ -3989,6 +5198,18 @@ mod parser_context_tests {
                 BooleanExpression::Value(val) => {
                     format!("{}Value({})", spaces, val)
                 },
+                BooleanExpression::LcdLine(line, text) => {
+                    format!("{}LcdLine({}, {:?})", spaces, line, text)
+                },
+                BooleanExpression::SoundLog(filepath) => {
+                    format!("{}SoundLog({:?})", spaces, filepath)
+                },
+                BooleanExpression::ScreenText(text) => {
+                    format!("{}ScreenText({:?})", spaces, text)
+                },
+                BooleanExpression::ConsoleOutput(text) => {
+                    format!("{}ConsoleOutput({:?})", spaces, text)
+                },
                 BooleanExpression::MemorySequence(addr, bytes) => {
                     format!("{}MemorySequence({:?}, {:?})", spaces, addr, bytes)
                 },
@@ -4002,7 +5223,7 @@ mod parser_context_tests {
 
     #[test]
     fn test_parse_memory_with_memory_location() {
-        let mut symbols = test_utils::setup_test_symbols();
+        let mut symbols = crate::test_utils::setup_test_symbols();
         symbols.add_symbol(0x1000, "somewhere".to_string());
         let context = ParserContext::new(Some(&symbols));
         let addr = context.parse_memory(&PestParser::parse(Rule::memory_location, "$somewhere")
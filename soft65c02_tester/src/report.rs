@@ -0,0 +1,453 @@
+use std::{
+    io::Write,
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
+};
+
+use crate::{AppResult, Displayer, OutputToken};
+
+/// Description of the plan holding the assertions found before any marker.
+const UNMARKED_PLAN: &str = "script";
+
+/*
+ * PlanReport
+ * Outcome of a test plan, from its marker to the next one: the failed
+ * assertions and terminated runs, the cycles spent by the runs and calls and
+ * the time it took.
+ */
+#[derive(Debug, Default)]
+pub struct PlanReport {
+    pub description: String,
+    pub assertions: usize,
+    pub failures: Vec<String>,
+    pub cycles: u64,
+    pub duration: Duration,
+    pub skipped: bool,
+}
+
+impl PlanReport {
+    pub fn is_failed(&self) -> bool {
+        !self.failures.is_empty()
+    }
+}
+
+/*
+ * PlanRecorder
+ * Gathers the output tokens in plan reports. A plan ends when the next marker
+ * or skipped plan comes or when the output is over.
+ */
+#[derive(Debug, Default)]
+struct PlanRecorder {
+    current: Option<(PlanReport, Instant)>,
+}
+
+impl PlanRecorder {
+    /// Record a token, return the plans it ends.
+    fn record(&mut self, token: &OutputToken) -> Vec<PlanReport> {
+        let mut reports = Vec::new();
+
+        match token {
+            OutputToken::Marker { description } => {
+                reports.extend(self.finish());
+                self.start(description);
+            }
+            OutputToken::Skipped { description } => {
+                reports.extend(self.finish());
+                reports.push(PlanReport {
+                    description: description.to_owned(),
+                    skipped: true,
+                    ..Default::default()
+                });
+            }
+            OutputToken::Assertion { failure, description } => {
+                let report = self.current();
+                report.assertions += 1;
+                if let Some(message) = failure {
+                    report.failures.push(format!("{description}: {message}"));
+                }
+            }
+            OutputToken::TerminatedRun { loglines, reason, .. } => {
+                let report = self.current();
+                report.cycles += loglines.iter().map(|l| l.cycles as u64).sum::<u64>();
+                report.failures.push(format!("run terminated: {reason}"));
+            }
+            OutputToken::Run { loglines, .. } => {
+                if let Some((report, _)) = &mut self.current {
+                    report.cycles += loglines.iter().map(|l| l.cycles as u64).sum::<u64>();
+                }
+            }
+            OutputToken::Call { cycles, .. } => {
+                if let Some((report, _)) = &mut self.current {
+                    report.cycles += cycles;
+                }
+            }
+            _ => (),
+        }
+
+        reports
+    }
+
+    /// End the current plan, if any.
+    fn finish(&mut self) -> Option<PlanReport> {
+        self.current.take().map(|(mut report, started)| {
+            report.duration = started.elapsed();
+            report
+        })
+    }
+
+    fn start(&mut self, description: &str) {
+        let report = PlanReport {
+            description: description.to_owned(),
+            ..Default::default()
+        };
+        self.current = Some((report, Instant::now()));
+    }
+
+    fn current(&mut self) -> &mut PlanReport {
+        if self.current.is_none() {
+            self.start(UNMARKED_PLAN);
+        }
+
+        &mut self.current.as_mut().unwrap().0
+    }
+}
+
+/*
+ * JunitDisplayer
+ * Writes the test plans as a JUnit XML test suite once the output is over,
+ * each plan being a test case with its failures, duration and cycle count.
+ */
+#[derive(Debug)]
+pub struct JunitDisplayer<T>
+where
+    T: Write,
+{
+    output: T,
+    name: String,
+}
+
+impl<T> JunitDisplayer<T>
+where
+    T: Write,
+{
+    pub fn new(output: T, name: &str) -> Self {
+        Self {
+            output,
+            name: name.to_owned(),
+        }
+    }
+
+    fn write_report(&mut self, reports: &[PlanReport]) -> AppResult<()> {
+        let failures = reports.iter().filter(|r| r.is_failed()).count();
+        let skipped = reports.iter().filter(|r| r.skipped).count();
+        let duration: Duration = reports.iter().map(|r| r.duration).sum();
+
+        writeln!(self.output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            self.output,
+            r#"<testsuite name="{}" tests="{}" failures="{failures}" errors="0" skipped="{skipped}" time="{:.6}">"#,
+            escape_xml(&self.name),
+            reports.len(),
+            duration.as_secs_f64()
+        )?;
+        for report in reports {
+            writeln!(
+                self.output,
+                r#"  <testcase name="{}" classname="{}" assertions="{}" time="{:.6}">"#,
+                escape_xml(&report.description),
+                escape_xml(&self.name),
+                report.assertions,
+                report.duration.as_secs_f64()
+            )?;
+            writeln!(self.output, "    <properties>")?;
+            writeln!(self.output, r#"      <property name="cycles" value="{}"/>"#, report.cycles)?;
+            writeln!(self.output, "    </properties>")?;
+            if report.skipped {
+                writeln!(self.output, "    <skipped/>")?;
+            }
+            if let Some(message) = report.failures.first() {
+                writeln!(
+                    self.output,
+                    r#"    <failure message="{}" type="assertion">{}</failure>"#,
+                    escape_xml(message),
+                    escape_xml(&report.failures.join("\n"))
+                )?;
+            }
+            writeln!(self.output, "  </testcase>")?;
+        }
+        writeln!(self.output, "</testsuite>")?;
+
+        Ok(())
+    }
+}
+
+impl<T> Displayer for JunitDisplayer<T>
+where
+    T: Write + Sync + Send,
+{
+    fn display(&mut self, receiver: Receiver<OutputToken>) -> AppResult<()> {
+        let mut recorder = PlanRecorder::default();
+        let mut reports = Vec::new();
+
+        while let Ok(token) = receiver.recv() {
+            reports.extend(recorder.record(&token));
+        }
+        reports.extend(recorder.finish());
+
+        self.write_report(&reports)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/*
+ * TapDisplayer
+ * Writes the test plans in the Test Anything Protocol as they end, the plan
+ * count comes last. Failures come with a YAML block holding the failed
+ * assertions, the duration and the cycle count.
+ */
+#[derive(Debug)]
+pub struct TapDisplayer<T>
+where
+    T: Write,
+{
+    output: T,
+    count: usize,
+}
+
+impl<T> TapDisplayer<T>
+where
+    T: Write,
+{
+    pub fn new(output: T) -> Self {
+        Self { output, count: 0 }
+    }
+
+    fn write_plan(&mut self, report: &PlanReport) -> AppResult<()> {
+        self.count += 1;
+        // a '#' would start a directive in the description
+        let description = report.description.replace('#', "\\#");
+
+        if report.skipped {
+            writeln!(self.output, "ok {} - {description} # SKIP", self.count)?;
+        } else if report.is_failed() {
+            writeln!(self.output, "not ok {} - {description}", self.count)?;
+            writeln!(self.output, "  ---")?;
+            writeln!(self.output, "  message: {}", quote_yaml(&report.failures[0]))?;
+            writeln!(self.output, "  failures:")?;
+            for failure in &report.failures {
+                writeln!(self.output, "    - {}", quote_yaml(failure))?;
+            }
+            writeln!(self.output, "  duration_ms: {:.3}", report.duration.as_secs_f64() * 1000.0)?;
+            writeln!(self.output, "  cycles: {}", report.cycles)?;
+            writeln!(self.output, "  ...")?;
+        } else {
+            writeln!(self.output, "ok {} - {description}", self.count)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Displayer for TapDisplayer<T>
+where
+    T: Write + Sync + Send,
+{
+    fn display(&mut self, receiver: Receiver<OutputToken>) -> AppResult<()> {
+        let mut recorder = PlanRecorder::default();
+        writeln!(self.output, "TAP version 13")?;
+
+        while let Ok(token) = receiver.recv() {
+            for report in recorder.record(&token) {
+                self.write_plan(&report)?;
+            }
+            if let OutputToken::Warning(lines) = &token {
+                for line in lines {
+                    writeln!(self.output, "# warning: {line}")?;
+                }
+            }
+        }
+        if let Some(report) = recorder.finish() {
+            self.write_plan(&report)?;
+        }
+        writeln!(self.output, "1..{}", self.count)?;
+
+        Ok(())
+    }
+}
+
+fn quote_yaml(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/*
+ * JsonDisplayer
+ * Writes each output token as a JSON object on its own line as soon as it
+ * comes, so the runs can be followed live by other programs.
+ */
+#[derive(Debug)]
+pub struct JsonDisplayer<T>
+where
+    T: Write,
+{
+    output: T,
+}
+
+impl<T> JsonDisplayer<T>
+where
+    T: Write,
+{
+    pub fn new(output: T) -> Self {
+        Self { output }
+    }
+}
+
+impl<T> Displayer for JsonDisplayer<T>
+where
+    T: Write + Sync + Send,
+{
+    fn display(&mut self, receiver: Receiver<OutputToken>) -> AppResult<()> {
+        while let Ok(token) = receiver.recv() {
+            if matches!(token, OutputToken::None) {
+                continue;
+            }
+            serde_json::to_writer(&mut self.output, &token)?;
+            self.output.write_all(b"\n")?;
+            self.output.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soft65c02_lib::{AddressingMode, AddressingModeResolution, LogLine, RegisterState};
+    use std::sync::mpsc::channel;
+
+    fn send_plans() -> Receiver<OutputToken> {
+        let (sender, receiver) = channel::<OutputToken>();
+        let tokens = vec![
+            OutputToken::Marker { description: "first plan".to_string() },
+            OutputToken::Call { loglines: Vec::new(), symbols: None, address: 0x1000, cycles: 12 },
+            OutputToken::Assertion { failure: None, description: "A is set".to_string() },
+            OutputToken::Marker { description: "second <plan>".to_string() },
+            OutputToken::Assertion { failure: Some("A = 0x00".to_string()), description: "A is \"set\"".to_string() },
+            OutputToken::Assertion { failure: None, description: "X is set".to_string() },
+            OutputToken::Skipped { description: "third plan".to_string() },
+        ];
+        for token in tokens {
+            sender.send(token).unwrap();
+        }
+
+        receiver
+    }
+
+    #[test]
+    fn test_plan_recorder() {
+        let mut recorder = PlanRecorder::default();
+        let mut reports = recorder.record(&OutputToken::Assertion { failure: None, description: "ok".to_string() });
+        reports.extend(recorder.record(&OutputToken::Skipped { description: "skipped".to_string() }));
+        reports.extend(recorder.finish());
+
+        assert_eq!(2, reports.len());
+        assert_eq!((UNMARKED_PLAN, 1, false), (reports[0].description.as_str(), reports[0].assertions, reports[0].skipped));
+        assert_eq!(("skipped", 0, true), (reports[1].description.as_str(), reports[1].assertions, reports[1].skipped));
+    }
+
+    #[test]
+    fn test_junit_displayer() {
+        let mut buffer: Vec<u8> = Vec::new();
+        JunitDisplayer::new(&mut buffer, "tests.txt").display(send_plans()).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+
+        assert!(output.contains(r#"<testsuite name="tests.txt" tests="3" failures="1" errors="0" skipped="1""#), "{output}");
+        assert!(output.contains(r#"<testcase name="first plan" classname="tests.txt" assertions="1""#), "{output}");
+        assert!(output.contains(r#"<property name="cycles" value="12"/>"#), "{output}");
+        assert!(output.contains(r#"<testcase name="second &lt;plan&gt;" classname="tests.txt" assertions="2""#), "{output}");
+        assert!(output.contains(r#"<failure message="A is &quot;set&quot;: A = 0x00" type="assertion">"#), "{output}");
+        assert!(output.contains("<skipped/>"), "{output}");
+        assert!(output.ends_with("</testsuite>\n"), "{output}");
+    }
+
+    #[test]
+    fn test_tap_displayer() {
+        let mut buffer: Vec<u8> = Vec::new();
+        TapDisplayer::new(&mut buffer).display(send_plans()).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!("TAP version 13", lines[0]);
+        assert_eq!("ok 1 - first plan", lines[1]);
+        assert_eq!("not ok 2 - second <plan>", lines[2]);
+        assert_eq!(r#"  message: "A is \"set\": A = 0x00""#, lines[4]);
+        assert_eq!("  cycles: 0", lines[8]);
+        assert_eq!("ok 3 - third plan # SKIP", lines[10]);
+        assert_eq!("1..3", lines[11]);
+    }
+
+    #[test]
+    fn test_json_displayer() {
+        let (sender, receiver) = channel::<OutputToken>();
+        let log_line = LogLine {
+            address: 0x1000,
+            opcode: 0xad,
+            mnemonic: "LDA".to_string(),
+            resolution: AddressingModeResolution {
+                operands: vec![0x00, 0x02],
+                addressing_mode: AddressingMode::Absolute([0x00, 0x02]),
+                target_address: Some(0x0200),
+            },
+            outcome: "(0x12)".to_string(),
+            registers: RegisterState {
+                accumulator: 0x12,
+                register_x: 0x00,
+                register_y: 0x00,
+                status: 0x30,
+                stack_pointer: 0xff,
+                command_pointer: 0x1003,
+            },
+            cycles: 4,
+        };
+        sender.send(OutputToken::Marker { description: "plan".to_string() }).unwrap();
+        sender.send(OutputToken::None).unwrap();
+        sender.send(OutputToken::TerminatedRun { loglines: vec![log_line], symbols: None, reason: "too long".to_string() }).unwrap();
+        sender.send(OutputToken::Setup(vec!["memory flushed".to_string()])).unwrap();
+        sender.send(OutputToken::ControlAction { function: crate::ControllableFunction::TraceLogging, enabled: false }).unwrap();
+        drop(sender);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        JsonDisplayer::new(&mut buffer).display(receiver).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(4, lines.len());
+        assert_eq!(r#"{"type":"marker","data":{"description":"plan"}}"#, lines[0]);
+        assert_eq!(
+            concat!(
+                r#"{"type":"terminated_run","data":{"loglines":[{"address":4096,"opcode":173,"mnemonic":"LDA","#,
+                r#""resolution":{"operands":[0,2],"addressing_mode":{"mode":"absolute","operands":[0,2]},"target_address":512},"#,
+                r#""outcome":"(0x12)","registers":{"accumulator":18,"register_x":0,"register_y":0,"status":48,"#,
+                r#""stack_pointer":255,"command_pointer":4099},"cycles":4}],"reason":"too long"}}"#
+            ),
+            lines[1]
+        );
+        assert_eq!(r#"{"type":"setup","data":["memory flushed"]}"#, lines[2]);
+        assert_eq!(r#"{"type":"control_action","data":{"function":"trace_logging","enabled":false}}"#, lines[3]);
+    }
+}
@@ -23,6 +23,10 @@ impl SymbolTable {
         self.addresses.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
     pub fn load_vice_labels<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
         let file = File::open(path)?;
         let reader = io::BufReader::new(file);
@@ -95,8 +99,7 @@ impl SymbolTable {
     /// Get all symbols associated with a given address
     pub fn get_symbols_for_address(&self, addr: u16) -> Vec<String> {
         self.symbols
-            .get(&addr)
-            .map(|symbols| symbols.clone())
+            .get(&addr).cloned()
             .unwrap_or_default()
     }
 
@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use crate::SymbolTable;
+
+pub fn setup_test_symbols() -> SymbolTable {
+    let mut symbols = SymbolTable::new();
+    symbols.add_symbol(0x34, "byte_var".to_string());    // 8-bit value
+    symbols.add_symbol(0x1234, "word_var".to_string());  // 16-bit value
+    symbols.add_symbol(0x2000, "counter".to_string());
+    symbols
+}
+
+/*
+ * Fresh directory in the system's temporary directory for the files of a
+ * test, the test removes it when it is done.
+ */
+pub fn fixture_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("soft65c02_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    directory
+}
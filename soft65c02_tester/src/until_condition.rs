@@ -1,10 +1,13 @@
 use anyhow::anyhow;
-use soft65c02_lib::{AddressableIO, Memory, Registers};
+use soft65c02_lib::{AddressableIO, HostCallRecord, Memory, Registers};
+use soft65c02_lib::devices::{Console, AY38910, HD44780};
+use soft65c02_lib::machines::apple2;
 use std::fmt::{self};
+use std::path::PathBuf;
 
 use crate::{AppResult, utils};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum RegisterSource {
     Accumulator,
     RegisterX,
@@ -27,6 +30,19 @@ impl RegisterSource {
             Self::CycleCount => registers.cycle_count as usize,
         }
     }
+
+    /// Value the register had when a recorded host call was reached.
+    pub fn get_recorded_value(&self, record: &HostCallRecord) -> usize {
+        match self {
+            Self::Accumulator => record.registers.accumulator as usize,
+            Self::RegisterX => record.registers.register_x as usize,
+            Self::RegisterY => record.registers.register_y as usize,
+            Self::Status => record.registers.status as usize,
+            Self::StackPointer => record.registers.stack_pointer as usize,
+            Self::CommandPointer => record.registers.command_pointer,
+            Self::CycleCount => record.cycle_count as usize,
+        }
+    }
 }
 
 impl fmt::Display for RegisterSource {
@@ -43,7 +59,7 @@ impl fmt::Display for RegisterSource {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Assignment {
     pub source: Source,
     pub destination: RegisterSource,
@@ -132,11 +148,14 @@ mod assignment_tests {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Source {
     Register(RegisterSource),
     Memory(usize),
     Value(usize),
+    CallCount(usize),                         // Number of calls made to a mocked subroutine
+    CallRegister(usize, usize, RegisterSource), // Register when the Nth call (from 1) to a mocked subroutine was made
+    CallMemory(usize, usize, usize),          // Zero or stack page byte when the Nth call to a mocked subroutine was made
 }
 
 impl Source {
@@ -145,8 +164,40 @@ impl Source {
             Self::Register(register_source) => register_source.get_value(registers),
             Self::Memory(addr) => memory.read(*addr, 1).unwrap()[0] as usize,
             Self::Value(data) => *data,
+            Self::CallCount(address) => memory
+                .get_host_call_records(*address)
+                .map_or(0, |records| records.len()),
+            Self::CallRegister(call, address, register_source) => Self::get_call_record(memory, *call, *address)
+                .map_or(0, |record| register_source.get_recorded_value(record)),
+            Self::CallMemory(call, address, addr) => Self::get_call_record(memory, *call, *address)
+                .and_then(|record| record.get_byte(*addr))
+                .map_or(0, |byte| byte as usize),
+        }
+    }
+
+    /// Tell why the value of a call source cannot be read: the calls are not
+    /// recorded or the call was not made.
+    pub fn missing_call(&self, memory: &Memory) -> Option<String> {
+        let (call, address) = match self {
+            Self::CallCount(address) => (0, *address),
+            Self::CallRegister(call, address, _) | Self::CallMemory(call, address, _) => (*call, *address),
+            _ => return None,
+        };
+        match memory.get_host_call_records(address) {
+            None => Some(format!("calls to #0x{address:04X} are not recorded, the subroutine is not mocked")),
+            Some(records) if call > records.len() => Some(format!(
+                "call {call} to #0x{address:04X} was not made, {} call(s) recorded",
+                records.len()
+            )),
+            Some(_) => None,
         }
     }
+
+    fn get_call_record(memory: &Memory, call: usize, address: usize) -> Option<&HostCallRecord> {
+        memory
+            .get_host_call_records(address)
+            .and_then(|records| records.get(call.checked_sub(1)?))
+    }
 }
 
 impl fmt::Display for Source {
@@ -155,6 +206,11 @@ impl fmt::Display for Source {
             Self::Register(register_source) => write!(f, "{register_source}"),
             Self::Memory(addr) => write!(f, "#0x{addr:04X}"),
             Self::Value(data) => write!(f, "0x{data:02X}"),
+            Self::CallCount(address) => write!(f, "calls #0x{address:04X}"),
+            Self::CallRegister(call, address, register_source) => {
+                write!(f, "call {call} #0x{address:04X} {register_source}")
+            }
+            Self::CallMemory(call, address, addr) => write!(f, "call {call} #0x{address:04X} #0x{addr:04X}"),
         }
     }
 }
@@ -172,12 +228,19 @@ pub enum BooleanExpression {
     Or(Box<BooleanExpression>, Box<BooleanExpression>),
     Not(Box<BooleanExpression>),
     MemorySequence(Source, Vec<u8>),  // For comparing memory contents against a sequence of bytes
+    LcdLine(usize, Vec<u8>),          // For comparing the start of a LCD line (starting at 1) against a text
+    SoundLog(PathBuf),                // For comparing the sound chip register log against a reference file
+    ScreenText(Vec<u8>),              // For looking for a text on the Apple II text screen
+    ConsoleOutput(Vec<u8>),           // For looking for a text in the captured console output
 }
 
 impl BooleanExpression {
     /// Solve the boolean expression with the given registers and memory.
     /// If the expression is true, None is returned. Otherwise, the failure message is returned.
     pub fn solve(&self, registers: &Registers, memory: &Memory) -> Option<String> {
+        if let Some(message) = self.missing_call(memory) {
+            return Some(format!("({self}) {message}"));
+        }
         match self {
             BooleanExpression::Equal(left, right) => {
                 let left_value = left.get_value(registers, memory);
@@ -273,11 +336,7 @@ impl BooleanExpression {
             }
             BooleanExpression::Or(expr1, expr2) => {
                 if let Some(msg1) = expr1.solve(registers, memory) {
-                    if let Some(msg2) = expr2.solve(registers, memory) {
-                        Some(format!("({self}) both conditions failed:\n  {msg1}\n  {msg2}"))
-                    } else {
-                        None
-                    }
+                    expr2.solve(registers, memory).map(|msg2| format!("({self}) both conditions failed:\n  {msg1}\n  {msg2}"))
                 } else {
                     None
                 }
@@ -313,6 +372,73 @@ impl BooleanExpression {
                     ))
                 }
             }
+            BooleanExpression::LcdLine(line, expected) => {
+                match memory.find_device::<HD44780>() {
+                    Some(lcd) => {
+                        if lcd.line_bytes(line - 1).starts_with(expected) {
+                            None
+                        } else {
+                            Some(format!("({self}) LCD line {} is \"{}\"", line, lcd.line(line - 1)))
+                        }
+                    }
+                    None => Some(format!("({self}) no LCD device found")),
+                }
+            }
+            BooleanExpression::SoundLog(filepath) => {
+                let chip = match memory.find_device::<AY38910>() {
+                    Some(chip) => chip,
+                    None => return Some(format!("({self}) no sound device found")),
+                };
+                let expected = match std::fs::read_to_string(filepath) {
+                    Ok(content) => content,
+                    Err(e) => return Some(format!("({self}) could not read '{}': {}", filepath.display(), e)),
+                };
+                let actual: Vec<String> = chip.get_register_log().iter().map(|entry| entry.to_string()).collect();
+                let expected: Vec<&str> = expected.lines().collect();
+                let difference = (0..actual.len().max(expected.len()))
+                    .find(|index| actual.get(*index).map(|line| line.as_str()) != expected.get(*index).copied());
+
+                difference.map(|index| format!(
+                    "({self}) line {} differs, expected '{}' got '{}'",
+                    index + 1,
+                    expected.get(index).unwrap_or(&"<end of log>"),
+                    actual.get(index).map(|line| line.as_str()).unwrap_or("<end of log>")
+                ))
+            }
+            BooleanExpression::ScreenText(expected) => {
+                let expected = String::from_utf8_lossy(expected);
+                match apple2::text_screen(memory) {
+                    Ok(lines) if lines.iter().any(|line| line.contains(expected.as_ref())) => None,
+                    Ok(lines) => Some(format!("({self}) text not found on screen:\n{}\n", lines.join("\n"))),
+                    Err(e) => Some(format!("({self}) could not read the text page: {}", e)),
+                }
+            }
+            BooleanExpression::ConsoleOutput(expected) => {
+                let expected = String::from_utf8_lossy(expected);
+                match memory.find_device::<Console>() {
+                    Some(console) if console.text().contains(expected.as_ref()) => None,
+                    Some(console) => Some(format!("({self}) output is \"{}\"", console.text().escape_debug())),
+                    None => Some(format!("({self}) no console found, declare a machine")),
+                }
+            }
+        }
+    }
+
+    /*
+     * A comparison reading a call to a mocked subroutine fails when the call
+     * was not made, the value read would be meaningless.
+     */
+    fn missing_call(&self, memory: &Memory) -> Option<String> {
+        match self {
+            BooleanExpression::Equal(left, right)
+            | BooleanExpression::GreaterOrEqual(left, right)
+            | BooleanExpression::StrictlyGreater(left, right)
+            | BooleanExpression::LesserOrEqual(left, right)
+            | BooleanExpression::StrictlyLesser(left, right)
+            | BooleanExpression::Different(left, right) => {
+                left.missing_call(memory).or_else(|| right.missing_call(memory))
+            }
+            _ => None,
         }
     }
 }
@@ -338,6 +464,12 @@ impl fmt::Display for BooleanExpression {
                         .join(",")
                 )
             }
+            BooleanExpression::LcdLine(line, text) => {
+                write!(f, "lcd line {} ~ \"{}\"", line, String::from_utf8_lossy(text))
+            }
+            BooleanExpression::SoundLog(filepath) => write!(f, "sound log ~ \"{}\"", filepath.display()),
+            BooleanExpression::ScreenText(text) => write!(f, "screen ~ \"{}\"", String::from_utf8_lossy(text)),
+            BooleanExpression::ConsoleOutput(text) => write!(f, "output ~ \"{}\"", String::from_utf8_lossy(text)),
         }
     }
 }
@@ -346,6 +478,7 @@ impl fmt::Display for BooleanExpression {
 mod tests_boolean_expression {
     use super::*;
     use soft65c02_lib::Memory;
+    use crate::test_utils::fixture_directory;
 
     #[test]
     fn test_memory_sequence_comparison() {
@@ -384,6 +517,121 @@ mod tests_boolean_expression {
         assert!(expr.solve(&registers, &memory).is_some());
     }
 
+    #[test]
+    fn test_lcd_line() {
+        let mut memory = Memory::new_with_ram();
+        let registers = Registers::new(0);
+        let expr = BooleanExpression::LcdLine(2, b"WORLD".to_vec());
+        assert_eq!(
+            Some("(lcd line 2 ~ \"WORLD\") no LCD device found".to_string()),
+            expr.solve(&registers, &memory)
+        );
+
+        memory.add_subsystem("LCD", 0x8000, HD44780::default());
+        // function set 2 lines, display on, go to line 2
+        for instruction in [0x38, 0x0C, 0xC0] {
+            memory.write(0x8000, &[instruction]).unwrap();
+        }
+        for byte in b"WORLD" {
+            memory.write_byte(0x8001, *byte).unwrap();
+        }
+        assert!(expr.solve(&registers, &memory).is_none());
+        assert!(BooleanExpression::LcdLine(2, b"WORLD    ".to_vec()).solve(&registers, &memory).is_none());
+
+        let expr = BooleanExpression::LcdLine(1, b"HELLO".to_vec());
+        assert_eq!(
+            Some("(lcd line 1 ~ \"HELLO\") LCD line 1 is \"                \"".to_string()),
+            expr.solve(&registers, &memory)
+        );
+    }
+
+    #[test]
+    fn test_sound_log() {
+        let mut memory = Memory::new_with_ram();
+        let registers = Registers::new(0);
+        let directory = fixture_directory("sound_log");
+        let filepath = directory.join("sound.log");
+        std::fs::write(&filepath, "0: R07 = 0x38\n0: R08 = 0x0F\n").unwrap();
+        let expr = BooleanExpression::SoundLog(filepath.clone());
+        assert!(expr.solve(&registers, &memory).unwrap().ends_with("no sound device found"));
+
+        memory.add_subsystem("SOUND", 0xB300, AY38910::default());
+        memory.write_byte(0xB307, 0x38).unwrap();
+        let message = expr.solve(&registers, &memory);
+        memory.write_byte(0xB308, 0x0F).unwrap();
+        let result = expr.solve(&registers, &memory);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(message.unwrap().ends_with("line 2 differs, expected '0: R08 = 0x0F' got '<end of log>'"));
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_screen_and_output() {
+        let mut memory = Memory::new_with_ram();
+        let registers = Registers::new(0);
+        let output = BooleanExpression::ConsoleOutput(b"HELLO".to_vec());
+        assert_eq!(
+            Some("(output ~ \"HELLO\") no console found, declare a machine".to_string()),
+            output.solve(&registers, &memory)
+        );
+
+        apple2::install(&mut memory);
+        memory.write(0x0400, &[0xA0; 0x400]).unwrap();
+        memory.write(0x0628, &[0xC8, 0xC5, 0xCC, 0xCC, 0xCF]).unwrap();
+        assert!(BooleanExpression::ScreenText(b"HELLO".to_vec()).solve(&registers, &memory).is_none());
+        assert!(BooleanExpression::ScreenText(b"WORLD".to_vec()).solve(&registers, &memory).is_some());
+
+        for byte in b"HELLO\r" {
+            memory.write_byte(0xC0F0, *byte).unwrap();
+        }
+        assert!(output.solve(&registers, &memory).is_none());
+        assert_eq!(
+            Some("(output ~ \"WORLD\") output is \"HELLO\\n\"".to_string()),
+            BooleanExpression::ConsoleOutput(b"WORLD".to_vec()).solve(&registers, &memory)
+        );
+    }
+
+    #[test]
+    fn test_call_sources() {
+        let mut memory = Memory::new_with_ram();
+        let calls = BooleanExpression::Equal(Source::CallCount(0x2000), Source::Value(1));
+        let argument = BooleanExpression::Equal(
+            Source::CallRegister(1, 0x2000, RegisterSource::Accumulator),
+            Source::Value(0x41),
+        );
+        let zero_page = BooleanExpression::Equal(Source::CallMemory(1, 0x2000, 0x0080), Source::Value(0x12));
+        let mut registers = Registers::new_initialized(0x1000);
+        assert_eq!(
+            Some("(calls #0x2000 = 0x01) calls to #0x2000 are not recorded, the subroutine is not mocked".to_string()),
+            calls.solve(&registers, &memory)
+        );
+
+        memory.add_host_call(0x2000, |_registers, _memory| Ok(()));
+        memory.record_host_calls(0x2000);
+        assert!(calls.solve(&registers, &memory).is_some());
+        assert_eq!(
+            Some("(call 1 #0x2000 A = 0x41) call 1 to #0x2000 was not made, 0 call(s) recorded".to_string()),
+            argument.solve(&registers, &memory)
+        );
+
+        // LDA #$41; JSR $2000
+        memory.write(0x1000, &[0xA9, 0x41, 0x20, 0x00, 0x20]).unwrap();
+        memory.write_byte(0x0080, 0x12).unwrap();
+        for _ in 0..3 {
+            soft65c02_lib::execute_step(&mut registers, &mut memory).unwrap();
+        }
+        assert!(calls.solve(&registers, &memory).is_none());
+        assert!(argument.solve(&registers, &memory).is_none());
+        assert!(zero_page.solve(&registers, &memory).is_none());
+        assert!(BooleanExpression::Equal(Source::CallRegister(1, 0x2000, RegisterSource::CommandPointer), Source::Value(0x2000))
+            .solve(&registers, &memory)
+            .is_none());
+        assert!(BooleanExpression::Equal(Source::CallCount(0x2000), Source::Value(2))
+            .solve(&registers, &memory)
+            .is_some());
+    }
+
     #[test]
     fn test_memory_sequence_display() {
         let expr = BooleanExpression::MemorySequence(
@@ -27,7 +27,7 @@ pub fn format_hex_dump_with_width(addr: usize, bytes: &[u8], width: usize) -> St
         // Add ASCII representation
         result.push_str("| ");
         for &byte in chunk {
-            let ch = if byte >= 0x20 && byte <= 0x7E {
+            let ch = if (0x20..=0x7E).contains(&byte) {
                 byte as char
             } else {
                 '.'
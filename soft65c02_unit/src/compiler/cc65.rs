@@ -168,8 +168,7 @@ impl CC65Compiler {
     /// Creates a directory structure in the work_dir that matches the source file's path
     fn create_output_dirs(&self, source: &Path, work_dir: &Path, path_mapping: &HashMap<PathBuf, PathBuf>) -> Result<PathBuf> {
         // Get the unique path for this source
-        let relative_path = path_mapping.get(source)
-            .map(|p| p.clone())
+        let relative_path = path_mapping.get(source).cloned()
             .unwrap_or_else(|| {
                 // Fallback to just filename if something went wrong
                 let name = source.file_name().unwrap().to_string_lossy().to_string();
@@ -405,30 +404,31 @@ mod tests {
     use tempfile::TempDir;
 
     fn create_test_config() -> Config {
-        let mut config = Config::default();
-        config.target = Some("atari".to_string());
-        config.compiler = Some(CompilerType::CC65);
-        config.config_file = Some(PathBuf::from("config.cfg"));
-        config.asm_include_paths = Some(vec![
-            PathBuf::from("asm1"),
-            PathBuf::from("asm2"),
-        ]);
-        config.include_paths = Some(vec![
-            PathBuf::from("include1"),
-            PathBuf::from("include2"),
-        ]);
-        config.c_flags = Some(vec![
-            "-O".to_string(),
-            "-g".to_string(),
-        ]);
-        config.asm_flags = Some(vec![
-            "-g".to_string(),
-        ]);
-        config.ld_flags = Some(vec![
-            "-m".to_string(),
-            "atari.map".to_string(),
-        ]);
-        config
+        Config {
+            target: Some("atari".to_string()),
+            compiler: Some(CompilerType::CC65),
+            config_file: Some(PathBuf::from("config.cfg")),
+            asm_include_paths: Some(vec![
+                PathBuf::from("asm1"),
+                PathBuf::from("asm2"),
+            ]),
+            include_paths: Some(vec![
+                PathBuf::from("include1"),
+                PathBuf::from("include2"),
+            ]),
+            c_flags: Some(vec![
+                "-O".to_string(),
+                "-g".to_string(),
+            ]),
+            asm_flags: Some(vec![
+                "-g".to_string(),
+            ]),
+            ld_flags: Some(vec![
+                "-m".to_string(),
+                "atari.map".to_string(),
+            ]),
+            ..Config::default()
+        }
     }
 
     fn create_test_compiler() -> CC65Compiler {
@@ -555,7 +555,7 @@ mod tests {
         ).unwrap();
 
         let work_dir = PathBuf::from("build");
-        let path_mapping = compiler.create_output_path_mapping(&[source_path.clone()]);
+        let path_mapping = compiler.create_output_path_mapping(std::slice::from_ref(&source_path));
         let result = compiler.compile_source(&source_path, &work_dir, &path_mapping);
         assert!(result.is_ok());
     }
@@ -576,7 +576,7 @@ mod tests {
         ).unwrap();
 
         let work_dir = PathBuf::from("build");
-        let path_mapping = compiler.create_output_path_mapping(&[source_path.clone()]);
+        let path_mapping = compiler.create_output_path_mapping(std::slice::from_ref(&source_path));
         let result = compiler.compile_source(&source_path, &work_dir, &path_mapping);
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("Mock compilation error"));
@@ -12,7 +12,7 @@ pub enum CompilerType {
 }
 
 /// Simple configuration structure that contains all needed settings
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct Config {
     pub name: Option<String>,
     pub target: Option<String>,
@@ -32,25 +32,6 @@ pub struct Config {
     pub ld_flags: Option<Vec<String>>,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            name: None,
-            target: None,
-            compiler: None,
-            include_paths: None,
-            src_files: None,
-            test_script: None,
-            configs: None,
-            config_file: None,
-            asm_include_paths: None,
-            c_flags: None,
-            asm_flags: None,
-            ld_flags: None,
-        }
-    }
-}
-
 impl Config {
     pub fn load(path: &Path) -> Result<Self> {
         // Get the load order first
@@ -61,8 +61,8 @@ impl Executor for CommandExecutor {
 
         if !output.status.success() {
             // If stderr starts with "Error:", just return that message without wrapping
-            if error_msg.starts_with("Error:") {
-                Err(error_msg[6..].trim().to_string())
+            if let Some(message) = error_msg.strip_prefix("Error:") {
+                Err(message.trim().to_string())
             } else {
                 Err(stderr.into_owned())
             }